@@ -25,6 +25,7 @@ use crate::context::Context;
 use crate::errors::ApiError;
 use crate::services::Result;
 use amp_resources::actor;
+use amp_resources::ext::ActorExt;
 
 pub struct ActorService;
 
@@ -68,12 +69,14 @@ impl ActorService {
     }
 
     pub async fn stats(ctx: Arc<Context>, pid: Uuid, name: String) -> Result<HashMap<String, String>> {
-        let metrics =
-            actor::metrics(&ctx.k8s, &format!("amp-{}", pid), &name).await.map_err(ApiError::ResourceError)?;
-
-        // Just return the metrics for name
-        let container = metrics.containers.iter().find(|c| c.name == name).ok_or_else(|| {
-            error!("Container {} not found", name);
+        let namespace = format!("amp-{}", pid);
+        let actor = actor::get(&ctx.k8s, &namespace, &name).await.map_err(ApiError::ResourceError)?;
+        let metrics = actor::metrics(&ctx.k8s, &namespace, &name).await.map_err(ApiError::ResourceError)?;
+
+        // Just return the metrics for the main container
+        let container_name = actor.container_name();
+        let container = metrics.containers.iter().find(|c| c.name == container_name).ok_or_else(|| {
+            error!("Container {} not found", container_name);
             ApiError::NotFound
         })?;
 
@@ -88,6 +91,10 @@ impl ActorService {
         let actor = actor::get(&ctx.k8s, &format!("amp-{}", pid), &name).await.map_err(ApiError::ResourceError)?;
 
         let mut info = HashMap::new();
+        if let Some(duration) = actor.build_duration() {
+            let build = HashMap::from([("duration".to_string(), format!("{}s", duration.as_secs()))]);
+            info.insert("build".to_string(), build);
+        }
         if let Some(deploy) = actor.spec.character.deploy {
             if let Some(env) = deploy.env {
                 info.insert("environments".to_string(), env);
@@ -17,7 +17,7 @@ use std::{sync::Arc, time::Duration};
 use crate::{errors::Error, Builder, Result};
 
 use amp_common::resource::Actor;
-use amp_resources::{containers::kaniko, job};
+use amp_resources::{containers::kaniko, ext::ActorExt, job};
 
 use async_trait::async_trait;
 use tracing::info;
@@ -42,7 +42,7 @@ impl Builder for KanikoBuilder {
     }
 
     async fn build(&self) -> Result<()> {
-        let name = format!("{}-builder", &self.actor.spec.name);
+        let name = self.actor.build_name();
         let pod = kaniko::pod(&self.actor).map_err(Error::ResourceError)?;
 
         // Build or update the build job
@@ -65,4 +65,9 @@ impl Builder for KanikoBuilder {
     async fn completed(&self) -> Result<bool> {
         job::completed(&self.k8s, &self.actor).await.map_err(Error::ResourceError)
     }
+
+    #[inline]
+    async fn digest(&self) -> Result<Option<String>> {
+        job::digest(&self.k8s, &self.actor).await.map_err(Error::ResourceError)
+    }
 }
@@ -18,6 +18,7 @@ use crate::{errors::Error, Builder, Result};
 
 use amp_common::{config::Credentials, resource::Actor};
 use amp_resources::{
+    ext::ActorExt,
     kpack::{
         cluster_builder, cluster_buildpack, cluster_store, encode_name, image, syncer,
         types::{find_top_level_buildpacks, Buildpack, Group, Order},
@@ -35,11 +36,17 @@ pub struct KpackBuilder {
     k8s: Arc<kube::Client>,
     credentials: Arc<RwLock<Credentials>>,
     actor: Arc<Actor>,
+    default_builder: String,
 }
 
 impl KpackBuilder {
-    pub fn new(k8s: Arc<kube::Client>, actor: Arc<Actor>, credentials: Arc<RwLock<Credentials>>) -> Self {
-        Self { k8s, credentials, actor }
+    pub fn new(
+        k8s: Arc<kube::Client>,
+        actor: Arc<Actor>,
+        credentials: Arc<RwLock<Credentials>>,
+        default_builder: String,
+    ) -> Self {
+        Self { k8s, credentials, actor, default_builder }
     }
 }
 
@@ -69,7 +76,7 @@ impl Builder for KpackBuilder {
 
     async fn build(&self) -> Result<()> {
         // Build or update the Image
-        let name = format!("{}-builder", &self.actor.spec.name);
+        let name = self.actor.build_name();
         match image::exists(&self.k8s, &self.actor).await.map_err(Error::ResourceError)? {
             true => {
                 // Image already exists, update it if there are new changes
@@ -89,6 +96,11 @@ impl Builder for KpackBuilder {
     async fn completed(&self) -> Result<bool> {
         image::completed(&self.k8s, &self.actor).await.map_err(Error::ResourceError)
     }
+
+    #[inline]
+    async fn digest(&self) -> Result<Option<String>> {
+        image::digest(&self.k8s, &self.actor).await.map_err(Error::ResourceError)
+    }
 }
 
 impl KpackBuilder {
@@ -126,7 +138,7 @@ impl KpackBuilder {
     async fn try_init_builder(&self) -> Result<Option<Duration>, amp_resources::error::Error> {
         if !cluster_builder::exists(&self.k8s, &self.actor).await? {
             if !cluster_store::exists(&self.k8s, &self.actor).await? {
-                cluster_store::create(&self.k8s, &self.actor).await?;
+                cluster_store::create(&self.k8s, &self.actor, &self.default_builder).await?;
             }
 
             if !cluster_store::ready(&self.k8s, &self.actor).await? {
@@ -34,6 +34,7 @@ pub trait Builder: Send + Sync {
     async fn prepare(&self) -> Result<Option<Duration>>;
     async fn build(&self) -> Result<()>;
     async fn completed(&self) -> Result<bool>;
+    async fn digest(&self) -> Result<Option<String>>;
 }
 
 /// Build director, it's a strategy pattern implementation
@@ -66,6 +67,11 @@ impl BuildDirector {
     pub async fn completed(&self) -> Result<bool> {
         self.builder.completed().await
     }
+
+    /// Returns the digest of the built image, if the builder reports it
+    pub async fn digest(&self) -> Result<Option<String>> {
+        self.builder.digest().await
+    }
 }
 
 #[cfg(test)]
@@ -76,6 +82,7 @@ mod tests {
     };
 
     use super::*;
+    use amp_resources::ext::DEFAULT_BUILDER;
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
@@ -86,7 +93,7 @@ mod tests {
         if let Ok(k8s) = k8s {
             let k8s = Arc::new(k8s);
             let actor = Arc::new(Actor::new("test", ActorSpec::default()));
-            let builder = LifecycleBuilder::new(k8s, actor);
+            let builder = LifecycleBuilder::new(k8s, actor, DEFAULT_BUILDER.into());
             let _ = BuildDirector::new(Box::new(builder));
         }
     }
@@ -110,7 +117,8 @@ mod tests {
         if let Ok(k8s) = k8s {
             let k8s = Arc::new(k8s);
             let actor = Arc::new(Actor::new("test", ActorSpec::default()));
-            let builder = KpackBuilder::new(k8s, actor, Arc::new(RwLock::new(Credentials::default())));
+            let credentials = Arc::new(RwLock::new(Credentials::default()));
+            let builder = KpackBuilder::new(k8s, actor, credentials, DEFAULT_BUILDER.into());
             let _ = BuildDirector::new(Box::new(builder));
         }
     }
@@ -17,7 +17,7 @@ use std::{sync::Arc, time::Duration};
 use crate::{errors::Error, Builder, Result};
 
 use amp_common::resource::Actor;
-use amp_resources::{containers::lifecycle, job};
+use amp_resources::{containers::lifecycle, ext::ActorExt, job};
 
 use async_trait::async_trait;
 use tracing::info;
@@ -26,11 +26,12 @@ use tracing::info;
 pub struct LifecycleBuilder {
     k8s: Arc<kube::Client>,
     actor: Arc<Actor>,
+    default_builder: String,
 }
 
 impl LifecycleBuilder {
-    pub fn new(k8s: Arc<kube::Client>, actor: Arc<Actor>) -> Self {
-        Self { k8s, actor }
+    pub fn new(k8s: Arc<kube::Client>, actor: Arc<Actor>, default_builder: String) -> Self {
+        Self { k8s, actor, default_builder }
     }
 }
 
@@ -42,8 +43,8 @@ impl Builder for LifecycleBuilder {
     }
 
     async fn build(&self) -> Result<()> {
-        let name = format!("{}-builder", &self.actor.spec.name);
-        let pod = lifecycle::pod(&self.actor).map_err(Error::ResourceError)?;
+        let name = self.actor.build_name();
+        let pod = lifecycle::pod(&self.actor, &self.default_builder).map_err(Error::ResourceError)?;
 
         // Build or update the build job
         match job::exists(&self.k8s, &self.actor).await.map_err(Error::ResourceError)? {
@@ -65,4 +66,9 @@ impl Builder for LifecycleBuilder {
     async fn completed(&self) -> Result<bool> {
         job::completed(&self.k8s, &self.actor).await.map_err(Error::ResourceError)
     }
+
+    #[inline]
+    async fn digest(&self) -> Result<Option<String>> {
+        job::digest(&self.k8s, &self.actor).await.map_err(Error::ResourceError)
+    }
 }
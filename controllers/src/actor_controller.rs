@@ -16,14 +16,18 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use amp_common::resource::Actor;
+use amp_resources::ext::ActorExt;
+use amp_resources::managed_selector;
 use amp_workflow::Workflow;
 use futures::{future, StreamExt};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::Service;
 use kube::api::ListParams;
 use kube::runtime::controller::Action;
 use kube::runtime::finalizer::{finalizer, Event};
 use kube::runtime::{watcher, Controller};
 use kube::{Api, ResourceExt};
-use tracing::{error, info};
+use tracing::{error, info, info_span, Instrument};
 
 use crate::context::Context;
 use crate::errors::{Error, Result};
@@ -40,7 +44,12 @@ pub async fn new(ctx: &Arc<Context>) {
         std::process::exit(1);
     }
 
+    // Only watch the child resources managed by us, and ignore the unrelated ones
+    let config = watcher::Config::default().labels(&managed_selector());
+
     Controller::new(api, watcher::Config::default())
+        .owns(Api::<Deployment>::all(ctx.k8s.clone()), config.clone())
+        .owns(Api::<Service>::all(ctx.k8s.clone()), config)
         .run(reconcile, error_policy, ctx.clone())
         .for_each(|_| future::ready(()))
         .await
@@ -49,6 +58,7 @@ pub async fn new(ctx: &Arc<Context>) {
 /// The reconciler that will be called when either object change
 pub async fn reconcile(actor: Arc<Actor>, ctx: Arc<Context>) -> Result<Action> {
     let ns = actor.namespace().unwrap(); // actor is namespace scoped
+    let key = format!("{}/{}", ns, actor.name_any());
     let api: Api<Actor> = Api::namespaced(ctx.k8s.clone(), &ns);
 
     let mut workflow = Workflow::new(
@@ -56,13 +66,27 @@ pub async fn reconcile(actor: Arc<Actor>, ctx: Arc<Context>) -> Result<Action> {
             k8s: Arc::new(ctx.k8s.clone()),
             jetstream: ctx.jetstream.clone(),
             credentials: ctx.credentials.clone(),
+            throttle: ctx.throttle.clone(),
+            default_builder: ctx.config.default_builder.clone(),
             object: actor.clone(),
         },
         Box::new(amp_workflow::actor::InitialState),
     );
 
+    // Attach the attributes of the actor to the span of this reconciliation
+    let span = info_span!(
+        "reconcile",
+        service.name = tracing::field::Empty,
+        vcs.repository.url.full = tracing::field::Empty,
+        vcs.repository.ref.revision = tracing::field::Empty,
+        amphitheatre.actor.state = tracing::field::Empty,
+    );
+    for (key, value) in actor.otel_attributes() {
+        span.record(key, value.as_str());
+    }
+
     // Reconcile the actor custom resource.
-    finalizer(&api, FINALIZER_NAME, actor, |event| async {
+    let action = finalizer(&api, FINALIZER_NAME, actor, |event| async {
         match event {
             Event::Apply(actor) => {
                 info!("Apply actor {}", actor.name_any());
@@ -78,13 +102,22 @@ pub async fn reconcile(actor: Arc<Actor>, ctx: Arc<Context>) -> Result<Action> {
         // Runs the workflow until there is no next state
         workflow.run().await.map_err(Error::WorkflowError)
     })
+    .instrument(span)
     .await
-    .map_err(|e| Error::FinalizerError(Box::new(e)))
+    .map_err(|e| Error::FinalizerError(Box::new(e)))?;
+
+    // Reset the backoff of the actor, as its errors are no longer consecutive
+    ctx.clear_errors(&key);
+    Ok(action)
 }
 
 /// an error handler that will be called when the reconciler fails with access to both the
 /// object that caused the failure and the actual error
-pub fn error_policy(_: Arc<Actor>, error: &Error, _ctx: Arc<Context>) -> Action {
+pub fn error_policy(actor: Arc<Actor>, error: &Error, ctx: Arc<Context>) -> Action {
     error!("reconcile failed: {:?}", error);
-    Action::requeue(Duration::from_secs(60))
+
+    // Back off by the consecutive errors of the actor, on top of its failures
+    let key = format!("{}/{}", actor.namespace().unwrap_or_default(), actor.name_any());
+    let errors = ctx.record_error(&key);
+    Action::requeue(actor.next_backoff(errors, Duration::from_secs(60), Duration::from_secs(3600)))
 }
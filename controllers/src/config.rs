@@ -49,4 +49,14 @@ pub struct Config {
     /// Persistent Volume access mode, the default is `ReadWriteOnce`.
     #[clap(long, env = "AMP_PV_ACCESS_MODE", default_value = "ReadWriteOnce")]
     pub pv_access_mode: String,
+
+    /// The builder image for Buildpacks when the actor does not set one,
+    /// the default is `gcr.io/buildpacks/builder:v1`.
+    #[clap(long, env = "AMP_DEFAULT_BUILDER", default_value = "gcr.io/buildpacks/builder:v1")]
+    pub default_builder: String,
+
+    /// The maximum number of concurrent builds in each namespace, further
+    /// builds are queued until a slot is free. The default `0` means no limit.
+    #[clap(long, env = "AMP_MAX_BUILDS_PER_NAMESPACE", default_value = "0")]
+    pub max_builds_per_namespace: usize,
 }
@@ -12,10 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 use amp_common::config::Credentials;
 use amp_resources::credential;
+use amp_workflow::BuildThrottle;
 use async_nats::jetstream;
 use tokio::sync::RwLock;
 
@@ -34,6 +36,10 @@ pub struct Context {
     pub credentials: Arc<RwLock<Credentials>>,
     pub config: Arc<Config>,
     pub jetstream: Arc<jetstream::Context>,
+    pub throttle: Arc<BuildThrottle>,
+    /// The number of consecutive reconciles that erred, by object, to back off
+    /// the retries of the objects failing to reconcile.
+    pub errors: Mutex<HashMap<String, u32>>,
 }
 
 impl Context {
@@ -47,12 +53,30 @@ impl Context {
             .await
             .map_err(|e| anyhow::anyhow!("Failed to connect to NATS: {}, {}", &config.nats_url, e))?;
         let jetstream = jetstream::new(client);
+        let throttle = BuildThrottle::new(config.max_builds_per_namespace);
 
         Ok(Context {
             k8s,
             credentials: Arc::new(credentials),
             config: Arc::new(config),
             jetstream: Arc::new(jetstream),
+            throttle: Arc::new(throttle),
+            errors: Mutex::new(HashMap::new()),
         })
     }
+
+    /// Count an erred reconcile of the object, returning the number of its
+    /// consecutive errors before this one.
+    pub fn record_error(&self, key: &str) -> u32 {
+        let mut errors = self.errors.lock().unwrap();
+        let count = errors.entry(key.to_string()).or_default();
+        let previous = *count;
+        *count = count.saturating_add(1);
+        previous
+    }
+
+    /// Forget the errors of the object once it reconciled successfully.
+    pub fn clear_errors(&self, key: &str) {
+        self.errors.lock().unwrap().remove(key);
+    }
 }
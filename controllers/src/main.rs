@@ -31,6 +31,7 @@ use crate::context::Context;
 mod actor_controller;
 mod credentials_watcher;
 mod namespace_watcher;
+mod orphan_collector;
 mod playbook_controller;
 mod timeout_controller;
 
@@ -58,6 +59,7 @@ async fn main() -> anyhow::Result<()> {
         _ = actor_controller::new(&ctx) => tracing::warn!("actor controller exited"),
         _ = credentials_watcher::new(&ctx) => tracing::warn!("credentials watcher exited"),
         _ = namespace_watcher::new(&ctx) => tracing::warn!("namespace watcher exited"),
+        _ = orphan_collector::new(&ctx) => tracing::warn!("orphan collector exited"),
         _ = timeout_controller::new(&ctx) => tracing::warn!("timeout controller exited")
     }
 
@@ -0,0 +1,36 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use amp_resources::orphan;
+use tracing::{error, info};
+
+use crate::context::Context;
+
+/// How often the Deployments and Services left behind by actors are collected.
+const INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Periodically delete the Deployments and Services managed by Amphitheatre
+/// whose actor no longer exists, e.g. after its finalizer was removed by hand.
+pub async fn new(ctx: &Arc<Context>) {
+    info!("Orphan collector is running...");
+    loop {
+        if let Err(err) = orphan::delete_orphans(&ctx.k8s).await {
+            error!("Delete orphaned resources failed: {}", err.to_string());
+        }
+        tokio::time::sleep(INTERVAL).await;
+    }
+}
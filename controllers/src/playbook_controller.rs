@@ -56,6 +56,8 @@ pub async fn reconcile(playbook: Arc<Playbook>, ctx: Arc<Context>) -> Result<Act
             k8s: Arc::new(ctx.k8s.clone()),
             jetstream: ctx.jetstream.clone(),
             credentials: ctx.credentials.clone(),
+            throttle: ctx.throttle.clone(),
+            default_builder: ctx.config.default_builder.clone(),
             object: playbook.clone(),
         },
         Box::new(amp_workflow::playbook::InitialState),
@@ -32,6 +32,9 @@ pub enum ResolveError {
     #[error("TomlParseFailed: {0}")]
     TomlParseFailed(toml::de::Error),
 
+    #[error("TomlSerializeFailed: {0}")]
+    TomlSerializeFailed(toml::ser::Error),
+
     #[error("InvalidRegistryAddress: {0}")]
     InvalidRegistryAddress(#[source] url::ParseError),
 
@@ -61,6 +64,9 @@ pub enum ResolveError {
 
     #[error("NameNotSet")]
     NameNotSet,
+
+    #[error("InvalidManifestPath: {0}")]
+    InvalidManifestPath(String),
 }
 
 pub type Result<T, E = ResolveError> = std::result::Result<T, E>;
@@ -16,16 +16,21 @@ use amp_common::resource::CharacterSpec;
 use amp_common::schema::{Character, GitReference};
 use amp_common::scm::client::Client as ScmClient;
 use amp_common::{config::Credentials, resource::ActorSpec};
-use amp_resources::character;
+use amp_resources::{character, normalize_manifest_path};
 use errors::{ResolveError, Result};
 use kube::Client as KubeClient;
+use toml::{Table, Value};
 use tracing::debug;
 
 pub mod errors;
 pub mod partner;
 pub mod patches;
+pub mod playbook;
 pub mod preface;
+pub mod provider;
+pub mod sync;
 pub mod utils;
+pub mod validation;
 
 const CATALOG_REPO_URL: &str = "https://github.com/amphitheatre-app/catalog.git";
 
@@ -45,7 +50,7 @@ pub async fn load_from_source(credentials: &Credentials, reference: &GitReferenc
     let client = ScmClient::init(credentials, &reference.repo).map_err(ResolveError::SCMError)?;
 
     let reference = patches::source(&client, reference).await?;
-    let path = reference.path.clone().unwrap_or(".amp.toml".into());
+    let path = normalize_manifest_path(reference.path.as_deref()).map_err(ResolveError::InvalidManifestPath)?;
     let repo = utils::repo(&reference.repo)?;
 
     let content = client
@@ -60,6 +65,28 @@ pub async fn load_from_source(credentials: &Credentials, reference: &GitReferenc
     Ok(CharacterSpec::from(&manifest))
 }
 
+/// Export the character to TOML, for its `.amp.toml` manifest. The keys of
+/// all the tables, such as the environments of the build and deploy, are
+/// sorted, so that the same character is always exported byte for byte the
+/// same, whatever the order of its maps.
+pub fn to_manifest_toml(character: &CharacterSpec) -> Result<String> {
+    let value = Value::try_from(character).map_err(ResolveError::TomlSerializeFailed)?;
+    toml::to_string(&sorted(value)).map_err(ResolveError::TomlSerializeFailed)
+}
+
+/// Sort the keys of the tables in the value, recursively.
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Table(table) => {
+            let mut entries: Vec<(String, Value)> = table.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Table(entries.into_iter().map(|(key, value)| (key, sorted(value))).collect::<Table>())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
 /// Load manifest from Kubernetes cluster and return the actor spec.
 pub async fn load_from_cluster(client: &KubeClient, name: &str) -> Result<CharacterSpec> {
     let character = character::get(client, name).await.map_err(ResolveError::ResourceError)?;
@@ -94,3 +121,45 @@ pub async fn to_actor(character: &CharacterSpec, credentials: &Credentials) -> R
 
     Ok(actor)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use amp_common::schema::{Build, Deploy, Metadata};
+
+    use super::*;
+
+    fn character(names: &[&str]) -> CharacterSpec {
+        let env: HashMap<String, String> = names.iter().map(|name| (name.to_string(), name.to_lowercase())).collect();
+        CharacterSpec {
+            meta: Metadata { name: "api".into(), ..Default::default() },
+            build: Some(Build { env: Some(env.clone()), ..Default::default() }),
+            deploy: Some(Deploy { env: Some(env), ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_manifest_toml_is_stable() {
+        let names: Vec<String> = (0..32).map(|index| format!("VAR_{:02}", index)).collect();
+        let mut names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+        let exported = to_manifest_toml(&character(&names)).unwrap();
+        for _ in 0..8 {
+            assert_eq!(to_manifest_toml(&character(&names)).unwrap(), exported);
+        }
+        names.reverse();
+        assert_eq!(to_manifest_toml(&character(&names)).unwrap(), exported);
+    }
+
+    #[test]
+    fn test_manifest_toml_sorts_env() {
+        let exported = to_manifest_toml(&character(&["ZONE", "API_KEY", "LOG_LEVEL"])).unwrap();
+
+        let keys: Vec<&str> = exported.lines().filter_map(|line| line.split_once(" = ")).map(|(key, _)| key).collect();
+        let env: Vec<&str> =
+            keys.into_iter().filter(|key| key.chars().all(|c| c.is_ascii_uppercase() || c == '_')).collect();
+        assert_eq!(env, vec!["API_KEY", "LOG_LEVEL", "ZONE", "API_KEY", "LOG_LEVEL", "ZONE"]);
+    }
+}
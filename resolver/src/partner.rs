@@ -12,14 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use crate::{
     errors::{ResolveError, Result},
     load_from_catalog, load_from_cluster, load_from_source,
+    utils::repository_parts,
 };
 use amp_common::{
     config::Credentials,
-    resource::{CharacterSpec, Partner},
+    resource::{ActorSpec, CharacterSpec, Partner},
+    schema::GitReference,
 };
+use amp_resources::{normalize_manifest_path, DEFAULT_MANIFEST_PATH};
 use kube::Client as KubeClient;
 
 /// Load manifest from different sources and return the actor spec.
@@ -43,3 +48,238 @@ pub async fn load(
         _ => Err(ResolveError::UnsupportedPartner),
     }
 }
+
+/// Returns the node of the partner in the dependency graph, that is the
+/// normalized address of its repository, the path of its manifest if not at
+/// the root, and its reference if any, so the same repository on different
+/// references or paths are distinct nodes. The https, `ssh://` and scp-like
+/// `git@host:owner/name.git` addresses of a repository are the same node,
+/// like `github.com/owner/name//apps/api@main`. Partners in a registry are
+/// resolved by name and have no node.
+pub fn url(partner: &Partner) -> Option<String> {
+    match partner {
+        Partner::Repository(reference) => Some(node(reference)),
+        _ => None,
+    }
+}
+
+fn node(reference: &GitReference) -> String {
+    let mut repo = match repository_parts(&reference.repo) {
+        Some(parts) => format!("{}/{}/{}", parts.host, parts.owner, parts.name),
+        None => reference.repo.trim().to_string(),
+    };
+
+    let manifest = normalize_manifest_path(reference.path.as_deref())
+        .unwrap_or_else(|_| reference.path.as_deref().unwrap_or_default().trim().to_string());
+    let dir = manifest.strip_suffix(DEFAULT_MANIFEST_PATH).filter(|dir| dir.is_empty() || dir.ends_with('/'));
+    let path = dir.unwrap_or(&manifest).trim_end_matches('/');
+    if !path.is_empty() {
+        repo = format!("{}//{}", repo, path);
+    }
+
+    match reference.branch.as_ref().or(reference.tag.as_ref()).or(reference.rev.as_ref()) {
+        Some(reference) => format!("{}@{}", repo, reference),
+        None => repo,
+    }
+}
+
+/// Detect the dependency cycles among the actors through their partners,
+/// returns each of them as the path of the actor names, like `api -> db -> api`.
+pub fn detect_cycles(actors: &[ActorSpec]) -> Result<(), Vec<String>> {
+    let edges = edges(actors);
+
+    let mut cycles = vec![];
+    let mut visited = vec![false; actors.len()];
+    for start in 0..actors.len() {
+        visit(start, &edges, &mut visited, &mut vec![], &mut |path| {
+            let names: Vec<&str> = path.iter().map(|index| actors[*index].name.as_str()).collect();
+            cycles.push(names.join(" -> "));
+        });
+    }
+
+    if cycles.is_empty() {
+        Ok(())
+    } else {
+        Err(cycles)
+    }
+}
+
+/// Returns the names of the actors in the order to deploy them, each after
+/// the actors of its partners, otherwise in the given order. Fails with the
+/// dependency cycles, if any, as there is no such order then.
+pub fn deploy_order(actors: &[ActorSpec]) -> Result<Vec<String>, Vec<String>> {
+    detect_cycles(actors)?;
+    let edges = edges(actors);
+
+    let mut order = vec![];
+    let mut placed = vec![false; actors.len()];
+    for index in 0..actors.len() {
+        place(index, &edges, &mut placed, &mut order);
+    }
+
+    Ok(order.into_iter().map(|index| actors[index].name.clone()).collect())
+}
+
+/// Returns the edges of each actor to the actors of its partners, in order
+/// of the partner names. Partners that are not among the actors are left out.
+fn edges(actors: &[ActorSpec]) -> Vec<Vec<usize>> {
+    let nodes: HashMap<String, usize> = actors
+        .iter()
+        .enumerate()
+        .map(|(index, actor)| {
+            let source = actor.source.clone().unwrap_or_else(|| GitReference {
+                repo: actor.character.meta.repository.clone(),
+                ..Default::default()
+            });
+            (node(&source), index)
+        })
+        .collect();
+
+    actors
+        .iter()
+        .map(|actor| {
+            let mut partners: Vec<_> = actor.character.partners.iter().flatten().collect();
+            partners.sort_by(|a, b| a.0.cmp(b.0));
+            partners.into_iter().filter_map(|(_, partner)| nodes.get(&url(partner)?).copied()).collect()
+        })
+        .collect()
+}
+
+/// Visit the actor depth-first, reporting the path of every back edge
+/// to an actor on the current path.
+fn visit(
+    index: usize,
+    edges: &[Vec<usize>],
+    visited: &mut [bool],
+    path: &mut Vec<usize>,
+    report: &mut dyn FnMut(&[usize]),
+) {
+    if let Some(position) = path.iter().position(|node| *node == index) {
+        let mut cycle = path[position..].to_vec();
+        cycle.push(index);
+        report(&cycle);
+        return;
+    }
+    if visited[index] {
+        return;
+    }
+
+    visited[index] = true;
+    path.push(index);
+    for next in &edges[index] {
+        visit(*next, edges, visited, path, report);
+    }
+    path.pop();
+}
+
+/// Place the actors of the partners of the actor, then the actor itself.
+fn place(index: usize, edges: &[Vec<usize>], placed: &mut [bool], order: &mut Vec<usize>) {
+    if placed[index] {
+        return;
+    }
+
+    placed[index] = true;
+    for next in &edges[index] {
+        place(*next, edges, placed, order);
+    }
+    order.push(index);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor(name: &str, repo: &str, partners: &[(&str, &str, Option<&str>)]) -> ActorSpec {
+        let source = GitReference { repo: repo.into(), branch: Some("main".into()), ..Default::default() };
+        let mut actor = ActorSpec { name: name.into(), source: Some(source), ..Default::default() };
+        let partners = partners.iter().map(|(name, repo, branch)| {
+            let reference =
+                GitReference { repo: repo.to_string(), branch: branch.map(Into::into), ..Default::default() };
+            (name.to_string(), Partner::Repository(reference))
+        });
+        actor.character.partners = Some(partners.collect());
+        actor
+    }
+
+    const API: &str = "https://github.com/amphitheatre-app/api.git";
+    const DB: &str = "https://github.com/amphitheatre-app/db";
+    const CACHE: &str = "git@github.com:amphitheatre-app/cache.git";
+
+    #[test]
+    fn test_url() {
+        let reference = GitReference { repo: API.into(), tag: Some("v1".into()), ..Default::default() };
+        assert_eq!(url(&Partner::Repository(reference)), Some("github.com/amphitheatre-app/api@v1".into()));
+
+        let reference = GitReference { repo: DB.into(), ..Default::default() };
+        assert_eq!(url(&Partner::Repository(reference)), Some("github.com/amphitheatre-app/db".into()));
+    }
+
+    #[test]
+    fn test_scp_url() {
+        let reference = GitReference { repo: CACHE.into(), ..Default::default() };
+        assert_eq!(url(&Partner::Repository(reference)), Some("github.com/amphitheatre-app/cache".into()));
+
+        let reference = GitReference { repo: CACHE.into(), branch: Some("main".into()), ..Default::default() };
+        assert_eq!(url(&Partner::Repository(reference)), Some("github.com/amphitheatre-app/cache@main".into()));
+    }
+
+    #[test]
+    fn test_ssh_url() {
+        let repo = "ssh://git@github.com:22/amphitheatre-app/cache.git";
+        let reference = GitReference { repo: repo.into(), rev: Some("abc123".into()), ..Default::default() };
+        assert_eq!(url(&Partner::Repository(reference)), Some("github.com/amphitheatre-app/cache@abc123".into()));
+    }
+
+    #[test]
+    fn test_url_with_path() {
+        let url = |repo: &str, path: Option<&str>| {
+            let reference = GitReference {
+                repo: repo.into(),
+                path: path.map(Into::into),
+                tag: Some("v1".into()),
+                ..Default::default()
+            };
+            url(&Partner::Repository(reference)).unwrap()
+        };
+
+        assert_eq!(url(CACHE, Some("apps/cache")), "github.com/amphitheatre-app/cache//apps/cache@v1");
+        assert_eq!(url(CACHE, Some("./apps/cache/.amp.toml")), "github.com/amphitheatre-app/cache//apps/cache@v1");
+        assert_eq!(url(CACHE, Some(".amp.toml")), "github.com/amphitheatre-app/cache@v1");
+        assert_eq!(
+            url(CACHE, Some("apps/cache")),
+            url("https://github.com/amphitheatre-app/cache", Some("apps/cache/"))
+        );
+    }
+
+    #[test]
+    fn test_two_node_cycle() {
+        let api = actor("api", API, &[("db", DB, Some("main"))]);
+        let db = actor("db", DB, &[("api", "https://GitHub.com/amphitheatre-app/api/", Some("main"))]);
+
+        assert_eq!(detect_cycles(&[api, db]), Err(vec!["api -> db -> api".to_string()]));
+    }
+
+    #[test]
+    fn test_self_loop() {
+        let api = actor("api", API, &[("api", API, Some("main"))]);
+
+        assert_eq!(detect_cycles(&[api]), Err(vec!["api -> api".to_string()]));
+    }
+
+    #[test]
+    fn test_dag() {
+        let api = actor("api", API, &[("db", DB, Some("main")), ("cache", CACHE, Some("main"))]);
+        let cache = actor("cache", CACHE, &[("db", DB, Some("main"))]);
+        let db = actor("db", DB, &[]);
+
+        assert_eq!(detect_cycles(&[api, cache, db]), Ok(()));
+    }
+
+    #[test]
+    fn test_distinct_references() {
+        let api = actor("api", API, &[("db", DB, Some("main"))]);
+        let db = actor("db", DB, &[("api", API, Some("develop"))]);
+
+        assert_eq!(detect_cycles(&[api, db]), Ok(()));
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashSet};
+
+use amp_common::resource::{Actor, ActorSpec, PlaybookSpec};
+use amp_resources::ext::{ActorExt, ActorSpecExt};
+use amp_resources::footprint::ResourceFootprint;
+
+use crate::partner;
+
+pub trait PlaybookSpecExt {
+    fn deploy_order(&self) -> Result<Vec<String>, Vec<String>>;
+    fn rollback_order(&self) -> Vec<String>;
+    fn total_footprint(&self, actors: &[Actor]) -> ResourceFootprint;
+    fn validate_unique_names(&self) -> Result<(), Vec<String>>;
+    fn drift(&self, live: &[Actor]) -> Vec<String>;
+}
+
+impl PlaybookSpecExt for PlaybookSpec {
+    /// Returns the names of the characters in the order to deploy them, the
+    /// partners first, see `partner::deploy_order`. Fails with the dependency
+    /// cycles among the characters, if any.
+    fn deploy_order(&self) -> Result<Vec<String>, Vec<String>> {
+        let actors: Vec<ActorSpec> = self.characters.iter().flatten().map(ActorSpec::from).collect();
+        partner::deploy_order(&actors)
+    }
+
+    /// Returns the names of the characters in the order to remove them, the
+    /// reverse of the deploy order, so that the dependents go first. With
+    /// dependency cycles, they are removed in the reverse order of declaration.
+    fn rollback_order(&self) -> Vec<String> {
+        let mut order = self.deploy_order().unwrap_or_else(|_| {
+            self.characters.iter().flatten().map(|character| character.meta.name.clone()).collect()
+        });
+        order.reverse();
+        order
+    }
+
+    /// Returns the compute resources requested by the actors of the characters
+    /// of the playbook, to size the quota of its namespace. The actors of other
+    /// playbooks are not counted.
+    fn total_footprint(&self, actors: &[Actor]) -> ResourceFootprint {
+        let names: HashSet<&String> = self.characters.iter().flatten().map(|character| &character.meta.name).collect();
+        actors.iter().filter(|actor| names.contains(&actor.spec.name)).map(ActorExt::footprint).sum()
+    }
+
+    /// Check that the characters of the playbook are named uniquely, as the
+    /// resources of their actors are named after them and would collide.
+    /// Fails with an error for each name used more than once.
+    fn validate_unique_names(&self) -> Result<(), Vec<String>> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for character in self.characters.iter().flatten() {
+            *counts.entry(character.meta.name.as_str()).or_default() += 1;
+        }
+
+        let errors: Vec<String> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(name, count)| format!("actor name `{}` is used by {} characters, it must be unique", name, count))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns how the live actors diverge from the playbook, one message for
+    /// each character without an actor, or whose actor does not match it, see
+    /// `spec_eq`, then for each actor that is not a character of the playbook.
+    fn drift(&self, live: &[Actor]) -> Vec<String> {
+        let characters: Vec<ActorSpec> = self.characters.iter().flatten().map(ActorSpec::from).collect();
+
+        let mut drift = vec![];
+        for intent in &characters {
+            match live.iter().find(|actor| actor.spec.name == intent.name) {
+                None => drift.push(format!("actor `{}` is missing", intent.name)),
+                Some(actor) if !intent.spec_eq(&actor.spec) => {
+                    drift.push(format!("actor `{}` differs from its character", intent.name))
+                }
+                Some(_) => {}
+            }
+        }
+        for actor in live.iter().filter(|actor| !characters.iter().any(|intent| intent.name == actor.spec.name)) {
+            drift.push(format!("actor `{}` is not a character of the playbook", actor.spec.name));
+        }
+
+        drift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::{CharacterSpec, Partner};
+    use amp_common::schema::{Deploy, GitReference, Metadata};
+    use amp_resources::options::ActorOptions;
+    use k8s_openapi::api::core::v1::ResourceRequirements;
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+    use super::*;
+
+    fn repo(name: &str) -> String {
+        format!("https://github.com/amphitheatre-app/{}.git", name)
+    }
+
+    fn character(name: &str, partners: &[&str]) -> CharacterSpec {
+        let partners = partners.iter().map(|name| {
+            let reference = GitReference { repo: repo(name), ..Default::default() };
+            (name.to_string(), Partner::Repository(reference))
+        });
+        CharacterSpec {
+            meta: Metadata { name: name.into(), repository: repo(name), ..Default::default() },
+            partners: Some(partners.collect()),
+            ..Default::default()
+        }
+    }
+
+    fn playbook(characters: Vec<CharacterSpec>) -> PlaybookSpec {
+        PlaybookSpec { characters: Some(characters), ..Default::default() }
+    }
+
+    #[test]
+    fn test_linear_chain() {
+        let playbook = playbook(vec![character("web", &["api"]), character("api", &["db"]), character("db", &[])]);
+
+        assert_eq!(playbook.deploy_order(), Ok(vec!["db".into(), "api".into(), "web".into()]));
+    }
+
+    #[test]
+    fn test_diamond() {
+        let playbook = playbook(vec![
+            character("web", &["api", "worker"]),
+            character("api", &["db"]),
+            character("worker", &["db"]),
+            character("db", &[]),
+        ]);
+
+        assert_eq!(playbook.deploy_order(), Ok(vec!["db".into(), "api".into(), "worker".into(), "web".into()]));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &["api"])]);
+
+        assert_eq!(playbook.deploy_order(), Err(vec!["api -> db -> api".into()]));
+    }
+
+    #[test]
+    fn test_rollback_order() {
+        let playbook = playbook(vec![
+            character("web", &["api", "worker"]),
+            character("api", &["db"]),
+            character("worker", &["db"]),
+            character("db", &[]),
+        ]);
+
+        let mut order = playbook.deploy_order().unwrap();
+        order.reverse();
+        assert_eq!(playbook.rollback_order(), order);
+        assert_eq!(playbook.rollback_order(), vec!["web", "worker", "api", "db"]);
+    }
+
+    #[test]
+    fn test_rollback_order_with_cycle() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &["api"]), character("web", &[])]);
+
+        assert_eq!(playbook.rollback_order(), vec!["web", "db", "api"]);
+    }
+
+    #[test]
+    fn test_without_characters() {
+        assert_eq!(PlaybookSpec::default().deploy_order(), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_total_footprint() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &[])]);
+        let actor = |name: &str, cpu: &str, memory: &str, replicas: i32| {
+            let requests =
+                BTreeMap::from([("cpu".into(), Quantity(cpu.into())), ("memory".into(), Quantity(memory.into()))]);
+            let resources = Some(ResourceRequirements { requests: Some(requests), ..Default::default() });
+            let mut actor = Actor::new(name, ActorSpec { name: name.into(), ..Default::default() });
+            actor.set_options(&ActorOptions { resources, replicas: Some(replicas), ..Default::default() });
+            actor
+        };
+
+        let actors = [actor("api", "250m", "256Mi", 2), actor("db", "1", "1Gi", 1), actor("other", "4", "8Gi", 1)];
+        assert_eq!(
+            playbook.total_footprint(&actors),
+            ResourceFootprint { cpu_millis: 1500, memory_bytes: 1536 * 1024 * 1024 }
+        );
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &[])]);
+
+        assert_eq!(playbook.validate_unique_names(), Ok(()));
+        assert_eq!(PlaybookSpec::default().validate_unique_names(), Ok(()));
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let playbook = playbook(vec![
+            character("api", &["db"]),
+            character("db", &[]),
+            character("api", &[]),
+            character("web", &["api"]),
+            character("db", &[]),
+        ]);
+
+        assert_eq!(
+            playbook.validate_unique_names(),
+            Err(vec![
+                "actor name `api` is used by 2 characters, it must be unique".into(),
+                "actor name `db` is used by 2 characters, it must be unique".into(),
+            ])
+        );
+    }
+
+    fn live(playbook: &PlaybookSpec) -> Vec<Actor> {
+        let characters = playbook.characters.iter().flatten();
+        characters.map(|character| Actor::new(&character.meta.name, ActorSpec::from(character))).collect()
+    }
+
+    #[test]
+    fn test_drift() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &[])]);
+
+        assert!(playbook.drift(&live(&playbook)).is_empty());
+    }
+
+    #[test]
+    fn test_drift_with_missing_member() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &[])]);
+        let mut actors = live(&playbook);
+        actors.remove(1);
+
+        assert_eq!(playbook.drift(&actors), vec!["actor `db` is missing"]);
+    }
+
+    #[test]
+    fn test_drift_with_extra_actor() {
+        let playbook = playbook(vec![character("api", &[])]);
+        let mut actors = live(&playbook);
+        actors.push(Actor::new("worker", ActorSpec { name: "worker".into(), ..Default::default() }));
+
+        assert_eq!(playbook.drift(&actors), vec!["actor `worker` is not a character of the playbook"]);
+    }
+
+    #[test]
+    fn test_drift_with_mismatched_spec() {
+        let playbook = playbook(vec![character("api", &["db"]), character("db", &[])]);
+        let mut actors = live(&playbook);
+        actors[1].spec.character.deploy = Some(Deploy { command: Some("db --debug".into()), ..Default::default() });
+
+        assert_eq!(playbook.drift(&actors), vec!["actor `db` differs from its character"]);
+    }
+}
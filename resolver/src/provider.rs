@@ -0,0 +1,145 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+
+use amp_common::resource::Actor;
+use amp_resources::ext::ActorExt;
+
+use crate::utils::repository_parts;
+
+/// The SCM providers we can receive webhooks from and talk to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    GitHub,
+    GitLab,
+    Gitea,
+    Bitbucket,
+    Unknown,
+}
+
+impl Provider {
+    /// Detect the provider from the repository host. Well-known hosts are
+    /// matched exactly, self-hosted ones by a label in their domain name,
+    /// e.g. `github.example.com` or `gitlab-ce.example.com`.
+    pub fn from_host(host: &str) -> Provider {
+        let host = host.to_lowercase();
+        match host.as_str() {
+            "github.com" => return Provider::GitHub,
+            "gitlab.com" => return Provider::GitLab,
+            "gitea.com" | "codeberg.org" => return Provider::Gitea,
+            "bitbucket.org" => return Provider::Bitbucket,
+            _ => {}
+        }
+
+        for label in host.split(['.', '-']) {
+            match label {
+                "github" => return Provider::GitHub,
+                "gitlab" => return Provider::GitLab,
+                "gitea" => return Provider::Gitea,
+                "bitbucket" => return Provider::Bitbucket,
+                _ => {}
+            }
+        }
+
+        Provider::Unknown
+    }
+}
+
+impl FromStr for Provider {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "github" => Ok(Provider::GitHub),
+            "gitlab" => Ok(Provider::GitLab),
+            "gitea" => Ok(Provider::Gitea),
+            "bitbucket" => Ok(Provider::Bitbucket),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provider::GitHub => write!(f, "github"),
+            Provider::GitLab => write!(f, "gitlab"),
+            Provider::Gitea => write!(f, "gitea"),
+            Provider::Bitbucket => write!(f, "bitbucket"),
+            Provider::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Detect the provider of the actor's repository. The `provider` option, which
+/// is useful for self-hosted instances on custom domains, takes precedence over
+/// the detection from the repository host. Returns `None` if the repository
+/// address can not be parsed.
+pub fn detect(actor: &Actor) -> Option<Provider> {
+    let options = actor.options().unwrap_or_default();
+    if let Some(provider) = options.provider.and_then(|value| value.parse().ok()) {
+        return Some(provider);
+    }
+
+    let repository = match &actor.spec.source {
+        Some(source) => source.repo.as_str(),
+        None => actor.spec.character.meta.repository.as_str(),
+    };
+
+    repository_parts(repository).map(|parts| Provider::from_host(&parts.host))
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+    use amp_common::schema::GitReference;
+    use amp_resources::options::ActorOptions;
+
+    use super::*;
+
+    fn actor(repo: &str) -> Actor {
+        let source = GitReference { repo: repo.into(), ..Default::default() };
+        Actor::new("test", ActorSpec { name: "test".into(), source: Some(source), ..Default::default() })
+    }
+
+    #[test]
+    fn test_provider_from_host() {
+        assert_eq!(Provider::from_host("github.com"), Provider::GitHub);
+        assert_eq!(Provider::from_host("gitlab.com"), Provider::GitLab);
+        assert_eq!(Provider::from_host("codeberg.org"), Provider::Gitea);
+        assert_eq!(Provider::from_host("bitbucket.org"), Provider::Bitbucket);
+        assert_eq!(Provider::from_host("github.example.com"), Provider::GitHub);
+        assert_eq!(Provider::from_host("gitlab-ce.example.com"), Provider::GitLab);
+        assert_eq!(Provider::from_host("git.example.com"), Provider::Unknown);
+        assert_eq!(Provider::from_host("notgithub.com"), Provider::Unknown);
+    }
+
+    #[test]
+    fn test_detect_provider() {
+        assert_eq!(detect(&actor("https://github.com/amphitheatre-app/amphitheatre.git")), Some(Provider::GitHub));
+        assert_eq!(detect(&actor("git@gitlab.example.com:group/project.git")), Some(Provider::GitLab));
+        assert_eq!(detect(&actor("https://git.example.com/group/project.git")), Some(Provider::Unknown));
+        assert_eq!(detect(&actor("")), None);
+    }
+
+    #[test]
+    fn test_detect_provider_with_option() {
+        let mut actor = actor("https://git.example.com/group/project.git");
+        actor.set_options(&ActorOptions { provider: Some("Gitea".into()), ..Default::default() });
+
+        assert_eq!(detect(&actor), Some(Provider::Gitea));
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::resource::Actor;
+use amp_resources::ext::{ActorExt, ActorSpecExt, ManagementMode};
+
+/// A push to the repository, as reported by the webhook of the provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PushEvent {
+    /// The full git reference that was pushed, e.g. `refs/heads/main`.
+    pub git_ref: String,
+    /// The commit the reference points to after the push.
+    pub commit: String,
+    /// The files changed by the push, if the provider reports them.
+    pub changed_files: Option<Vec<String>>,
+}
+
+/// Whether the actor should be rebuilt, and from which commit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RebuildDecision {
+    pub rebuild: bool,
+    pub new_commit: Option<String>,
+}
+
+/// Decide whether the push should rebuild the actor. The actor must be synced,
+/// the pushed reference must be the one it tracks, or match one of its sync
+/// refs if set, and the push must carry a new commit. When the push reports the changed files, the actor must be
+/// affected by them, so that unrelated changes in a monorepo do not trigger
+/// a rebuild.
+pub fn rebuild_decision(actor: &Actor, event: &PushEvent) -> RebuildDecision {
+    let skip = RebuildDecision::default();
+
+    // Only synced actors are rebuilt on pushes, GitOps ones follow their spec.
+    let source = match &actor.spec.source {
+        Some(source) if actor.management_mode() == ManagementMode::Sync => source,
+        _ => return skip,
+    };
+
+    // Tags take priority over branches, just like resolving the source.
+    let patterns = actor.sync_refs();
+    let tracked = match (&source.tag, &source.branch) {
+        _ if !patterns.is_empty() => patterns.iter().any(|pattern| ref_matches(pattern, &event.git_ref)),
+        (Some(tag), _) => event.git_ref == format!("refs/tags/{}", tag),
+        (None, Some(branch)) => event.git_ref == format!("refs/heads/{}", branch),
+        (None, None) => false,
+    };
+    if !tracked || source.rev.as_deref() == Some(event.commit.as_str()) {
+        return skip;
+    }
+
+    if event.changed_files.as_ref().is_some_and(|files| !actor.spec.affected_by(files)) {
+        return skip;
+    }
+
+    RebuildDecision { rebuild: true, new_commit: Some(event.commit.clone()) }
+}
+
+/// Check if the git reference matches the pattern. `*` matches anything within
+/// a path segment, and a `**` segment matches any number of segments. Patterns
+/// starting with `refs/` match the full reference, the others match the short
+/// name of the branch or tag, so that `main` matches `refs/heads/main`.
+pub fn ref_matches(pattern: &str, git_ref: &str) -> bool {
+    let name = if pattern.starts_with("refs/") {
+        git_ref
+    } else {
+        git_ref.strip_prefix("refs/heads/").or_else(|| git_ref.strip_prefix("refs/tags/")).unwrap_or(git_ref)
+    };
+
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let name: Vec<&str> = name.split('/').collect();
+    segments_match(&pattern, &name)
+}
+
+fn segments_match(pattern: &[&str], name: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&"**", rest)) => (0..=name.len()).any(|skip| segments_match(rest, &name[skip..])),
+        Some((segment, rest)) => {
+            name.split_first().is_some_and(|(first, others)| glob_match(segment, first) && segments_match(rest, others))
+        }
+    }
+}
+
+/// Match a single path segment against a pattern segment with `*` wildcards.
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else { return false };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else { return rest.is_empty() };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// Validate the sync ref pattern, rejecting empty segments, whitespace and
+/// `**` mixed with other characters within a segment.
+pub fn validate_ref_pattern(pattern: &str) -> Result<(), String> {
+    if pattern.is_empty() || pattern.chars().any(char::is_whitespace) {
+        return Err(format!("sync ref `{}` must be non-empty without whitespace", pattern));
+    }
+    if pattern.split('/').any(|segment| segment.is_empty()) {
+        return Err(format!("sync ref `{}` has an empty path segment", pattern));
+    }
+    if pattern.split('/').any(|segment| segment.contains("**") && segment != "**") {
+        return Err(format!("sync ref `{}` must use `**` as a whole path segment", pattern));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+    use amp_common::schema::GitReference;
+    use amp_resources::options::ActorOptions;
+
+    use super::*;
+
+    fn actor(path: &str) -> Actor {
+        let source = GitReference {
+            repo: "https://github.com/amphitheatre-app/monorepo.git".into(),
+            branch: Some("main".into()),
+            rev: Some("abc123".into()),
+            path: Some(path.into()),
+            ..Default::default()
+        };
+        let mut actor =
+            Actor::new("test", ActorSpec { name: "test".into(), source: Some(source), ..Default::default() });
+        actor.set_options(&ActorOptions { sync: true, ..Default::default() });
+        actor
+    }
+
+    fn event(git_ref: &str, files: &[&str]) -> PushEvent {
+        PushEvent {
+            git_ref: git_ref.into(),
+            commit: "def456".into(),
+            changed_files: Some(files.iter().map(|file| file.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_rebuild_on_full_match() {
+        let decision =
+            rebuild_decision(&actor("services/api/.amp.toml"), &event("refs/heads/main", &["services/api/main.go"]));
+
+        assert_eq!(decision, RebuildDecision { rebuild: true, new_commit: Some("def456".into()) });
+    }
+
+    #[test]
+    fn test_skip_on_ref_match_but_path_miss() {
+        let decision =
+            rebuild_decision(&actor("services/api/.amp.toml"), &event("refs/heads/main", &["services/web/index.js"]));
+
+        assert_eq!(decision, RebuildDecision::default());
+    }
+
+    #[test]
+    fn test_skip_on_ref_miss() {
+        let decision =
+            rebuild_decision(&actor("services/api/.amp.toml"), &event("refs/heads/dev", &["services/api/main.go"]));
+
+        assert!(!decision.rebuild);
+    }
+
+    #[test]
+    fn test_skip_without_sync() {
+        let mut actor = actor(".amp.toml");
+        actor.set_options(&ActorOptions::default());
+
+        assert!(!rebuild_decision(&actor, &event("refs/heads/main", &["main.go"])).rebuild);
+    }
+
+    #[test]
+    fn test_skip_on_same_commit() {
+        let mut event = event("refs/heads/main", &["main.go"]);
+        event.commit = "abc123".into();
+
+        assert!(!rebuild_decision(&actor(".amp.toml"), &event).rebuild);
+    }
+
+    #[test]
+    fn test_rebuild_on_sync_refs() {
+        let mut actor = actor(".amp.toml");
+        actor.set_options(&ActorOptions { sync: true, sync_refs: vec!["release/*".into()], ..Default::default() });
+
+        assert!(rebuild_decision(&actor, &event("refs/heads/release/1.0", &["main.go"])).rebuild);
+        assert!(!rebuild_decision(&actor, &event("refs/heads/main", &["main.go"])).rebuild);
+    }
+
+    #[test]
+    fn test_ref_matches() {
+        let cases = [
+            ("main", "refs/heads/main", true),
+            ("main", "refs/heads/maintenance", false),
+            ("refs/heads/main", "refs/heads/main", true),
+            ("refs/heads/main", "refs/heads/dev", false),
+            ("release/*", "refs/heads/release/1.0", true),
+            ("release/*", "refs/heads/release/1.0/hotfix", false),
+            ("release/*", "refs/heads/release", false),
+            ("release-*", "refs/heads/release-2024", true),
+            ("*-stable", "refs/heads/v1-stable", true),
+            ("refs/tags/**", "refs/tags/v1.0.0", true),
+            ("refs/tags/**", "refs/tags/nested/v1.0.0", true),
+            ("refs/tags/**", "refs/heads/main", false),
+            ("feature/**/ready", "refs/heads/feature/login/ready", true),
+            ("feature/**/ready", "refs/heads/feature/ready", true),
+            ("v*", "refs/tags/v1.2.3", true),
+            ("*", "refs/heads/feature/login", false),
+            ("**", "refs/heads/feature/login", true),
+        ];
+
+        for (pattern, git_ref, expected) in cases {
+            assert_eq!(ref_matches(pattern, git_ref), expected, "{} against {}", pattern, git_ref);
+        }
+    }
+
+    #[test]
+    fn test_validate_ref_pattern() {
+        assert!(validate_ref_pattern("release/*").is_ok());
+        assert!(validate_ref_pattern("refs/tags/**").is_ok());
+        assert!(validate_ref_pattern("").is_err());
+        assert!(validate_ref_pattern("release /*").is_err());
+        assert!(validate_ref_pattern("release//*").is_err());
+        assert!(validate_ref_pattern("release/**x").is_err());
+    }
+}
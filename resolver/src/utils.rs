@@ -16,11 +16,144 @@ use url::Url;
 
 use crate::errors::{ResolveError, Result};
 
-/// Resolve the repo from the URL.
+/// Resolve the `owner/name` repo from its address, supporting https, `ssh://`
+/// and the scp-like `git@host:owner/name.git` shorthand as `repository_parts`.
+/// The reference after a `#`, if any, is not part of the repo.
 pub fn repo(url: &str) -> Result<String> {
-    let url = Url::parse(url).map_err(ResolveError::InvalidRepoAddress)?;
-    let mut repo = url.path().replace(".git", "");
-    repo = repo.trim_start_matches('/').to_string();
+    let url = url.trim();
+    let path = match split_scp(url) {
+        Some((_, path)) => path.split(['#', '?']).next().unwrap_or_default().to_string(),
+        None => Url::parse(url).map_err(ResolveError::InvalidRepoAddress)?.path().to_string(),
+    };
 
-    Ok(repo)
+    Ok(normalize(&path))
+}
+
+/// The host, owner and name parts of a repository address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoParts {
+    pub host: String,
+    pub owner: String,
+    pub name: String,
+}
+
+/// Split the repository address into its host, owner and name, supporting
+/// https, `ssh://` and the scp-like `git@host:owner/name.git` shorthand.
+///
+/// Nested groups (e.g. GitLab subgroups) are kept in the owner part,
+/// so `gitlab.example.com/group/subgroup/project` has owner `group/subgroup`.
+pub fn repository_parts(url: &str) -> Option<RepoParts> {
+    let url = url.trim();
+    let (host, path) = match split_scp(url) {
+        Some((host, path)) => (host.to_string(), path.to_string()),
+        None => {
+            let url = Url::parse(url).ok()?;
+            (url.host_str()?.to_string(), url.path().to_string())
+        }
+    };
+
+    let path = normalize(&path);
+    let (owner, name) = path.rsplit_once('/')?;
+    if host.is_empty() || owner.is_empty() || name.is_empty() {
+        return None;
+    }
+
+    Some(RepoParts { host: host.to_lowercase(), owner: owner.to_string(), name: name.to_string() })
+}
+
+/// Split the scp-like address `[user@]host:path` into host and path,
+/// returns `None` if the address is a URL with a scheme.
+fn split_scp(url: &str) -> Option<(&str, &str)> {
+    if url.contains("://") {
+        return None;
+    }
+
+    let (authority, path) = url.split_once(':')?;
+    if authority.contains('/') {
+        return None;
+    }
+
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    Some((host, path))
+}
+
+/// Normalize the repository path, trimming the slashes and `.git` suffix.
+fn normalize(path: &str) -> String {
+    let path = path.trim_matches('/');
+    path.strip_suffix(".git").unwrap_or(path).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(host: &str, owner: &str, name: &str) -> Option<RepoParts> {
+        Some(RepoParts { host: host.into(), owner: owner.into(), name: name.into() })
+    }
+
+    #[test]
+    fn test_repo() {
+        assert_eq!(
+            repo("https://github.com/amphitheatre-app/amphitheatre.git").unwrap(),
+            "amphitheatre-app/amphitheatre"
+        );
+        assert_eq!(repo("https://github.com/amphitheatre-app/amphitheatre").unwrap(), "amphitheatre-app/amphitheatre");
+        assert_eq!(
+            repo("https://github.com/amphitheatre-app/amphitheatre.git#main").unwrap(),
+            "amphitheatre-app/amphitheatre"
+        );
+    }
+
+    #[test]
+    fn test_repo_ssh() {
+        assert_eq!(repo("git@github.com:amphitheatre-app/amphitheatre.git").unwrap(), "amphitheatre-app/amphitheatre");
+        assert_eq!(
+            repo("git@github.com:amphitheatre-app/amphitheatre.git#v1.0.0").unwrap(),
+            "amphitheatre-app/amphitheatre"
+        );
+        assert_eq!(repo("ssh://git@gitlab.example.com:2222/group/project.git").unwrap(), "group/project");
+    }
+
+    #[test]
+    fn test_invalid_repo() {
+        assert!(matches!(repo("not a repository"), Err(ResolveError::InvalidRepoAddress(_))));
+    }
+
+    #[test]
+    fn test_repository_parts_github() {
+        assert_eq!(
+            repository_parts("https://github.com/amphitheatre-app/amphitheatre.git"),
+            parts("github.com", "amphitheatre-app", "amphitheatre")
+        );
+        assert_eq!(
+            repository_parts("https://GitHub.com/amphitheatre-app/amphitheatre/"),
+            parts("github.com", "amphitheatre-app", "amphitheatre")
+        );
+    }
+
+    #[test]
+    fn test_repository_parts_gitlab_self_hosted() {
+        assert_eq!(
+            repository_parts("https://gitlab.example.com/group/subgroup/project.git"),
+            parts("gitlab.example.com", "group/subgroup", "project")
+        );
+    }
+
+    #[test]
+    fn test_repository_parts_ssh() {
+        assert_eq!(
+            repository_parts("git@github.com:amphitheatre-app/amphitheatre.git"),
+            parts("github.com", "amphitheatre-app", "amphitheatre")
+        );
+        assert_eq!(
+            repository_parts("ssh://git@gitlab.example.com:2222/group/project.git"),
+            parts("gitlab.example.com", "group", "project")
+        );
+    }
+
+    #[test]
+    fn test_invalid_repository_parts() {
+        assert_eq!(repository_parts("https://github.com/amphitheatre"), None);
+        assert_eq!(repository_parts("not a repository"), None);
+    }
 }
@@ -0,0 +1,1433 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use amp_common::resource::{Actor, ActorSpec, Partner};
+use amp_resources::containers::application;
+use amp_resources::cronjob::validate_schedule;
+use amp_resources::ext::{ActorExt, ActorSpecExt, BuildSpecExt, DeploymentStrategy};
+use amp_resources::options::{ActorOptions, OPTIONS_KEY};
+use amp_resources::service::{self, ServiceKind};
+use amp_resources::{
+    is_dns1123_label, is_dns1123_subdomain, normalize_manifest_path, validate_image_reference, validate_platform,
+};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+
+use crate::provider::{self, Provider};
+use crate::sync::validate_ref_pattern;
+
+/// The prefix of the environment variables provided by Amphitheatre.
+const RESERVED_ENV_PREFIX: &str = "AMP_";
+
+/// The registries of Docker Hub, where the official images are published.
+const DOCKER_HUB_REGISTRIES: &[&str] = &["docker.io", "index.docker.io", "registry-1.docker.io"];
+
+/// The protocols supported by Kubernetes for the ports.
+const PORT_PROTOCOLS: &[&str] = &["TCP", "UDP", "SCTP"];
+
+/// The outcome of validating an actor. Errors reject the actor,
+/// while warnings are only reported to the user.
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    /// Returns true if there are no errors.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Validate the actor as a whole before reconciling it.
+pub fn validate_full(actor: &Actor) -> Report {
+    let mut report = Report::default();
+    validate_options(actor, &mut report);
+    validate_required(actor, &mut report);
+    validate_image_build(actor, &mut report);
+    validate_container_name(actor, &mut report);
+    validate_image_pull_secrets(actor, &mut report);
+    validate_command(actor, &mut report);
+    validate_build_strategy(actor, &mut report);
+    validate_dockerfile(actor, &mut report);
+    validate_build_timeout(actor, &mut report);
+    validate_deploy_timeout(actor, &mut report);
+    validate_platforms(actor, &mut report);
+    validate_build_cache(actor, &mut report);
+    validate_build_secrets(actor, &mut report);
+    validate_manifest_paths(actor, &mut report);
+    validate_schedule_options(actor, &mut report);
+    validate_replicas(actor, &mut report);
+    validate_scaling(actor, &mut report);
+    validate_canary(actor, &mut report);
+    validate_sync(actor, &mut report);
+    validate_sync_refs(actor, &mut report);
+    validate_reserved_env(actor, &mut report);
+    validate_env_sources(actor, &mut report);
+    validate_services(actor, &mut report);
+    validate_service_type(actor, &mut report);
+    validate_service_port_names(actor, &mut report);
+    validate_session_affinity(actor, &mut report);
+    validate_ports(actor, &mut report);
+    validate_probes(actor, &mut report);
+    validate_shared_artifacts(actor, &mut report);
+    validate_tls(actor, &mut report);
+    report.warnings.extend(actor.pull_policy_warnings());
+
+    report
+}
+
+/// Validate the actor and all the partners reachable from it, resolving each
+/// partner to its spec with `resolve`. The errors of the partners are prefixed
+/// with their path from the root, like `api/postgres: ...`. Each partner is
+/// only visited once, so cycles between partners are not followed.
+pub fn validate_graph<F>(root: &ActorSpec, resolve: F) -> Result<(), Vec<String>>
+where
+    F: Fn(&Partner) -> Option<ActorSpec>,
+{
+    let mut errors = vec![];
+    let mut visited = HashSet::from([root.name.clone()]);
+    let mut stack = vec![(String::new(), root.clone())];
+
+    while let Some((path, spec)) = stack.pop() {
+        let prefix = |error: String| if path.is_empty() { error } else { format!("{}: {}", path, error) };
+        errors.extend(validate_full(&Actor::new(&spec.name, spec.clone())).errors.into_iter().map(prefix));
+
+        // Visit the partners in order of their names
+        let mut partners: Vec<_> = spec.character.partners.iter().flatten().collect();
+        partners.sort_by(|a, b| b.0.cmp(a.0));
+        for (name, partner) in partners {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+
+            let path = if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) };
+            match resolve(partner) {
+                Some(spec) => stack.push((path, spec)),
+                None => errors.push(format!("{}: partner can not be resolved", path)),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Malformed options would be ignored as a whole, and the actor reconciled
+/// with the defaults instead.
+fn validate_options(actor: &Actor, report: &mut Report) {
+    if let Err(err) = actor.options() {
+        report.errors.push(format!("{}, in the `{}` annotation", err, OPTIONS_KEY));
+    }
+}
+
+/// The name and image are always required. The repository and commit are
+/// required to build the image from the source, live actors are synced
+/// instead and carry no source.
+fn validate_required(actor: &Actor, report: &mut Report) {
+    let spec = &actor.spec;
+    if spec.name.is_empty() {
+        report.errors.push("name is required".to_string());
+    }
+    if spec.image.is_empty() {
+        // Without a build, the error is reported along with the build.
+        if spec.character.build.is_some() || (spec.source.is_some() && !spec.live) {
+            report.errors.push("image is required".to_string());
+        }
+    } else {
+        report.errors.extend(validate_image_reference(&spec.image).err());
+    }
+
+    if let Some(source) = spec.source.as_ref().filter(|_| !spec.live) {
+        if source.repo.is_empty() {
+            report.errors.push("source repository is required".to_string());
+        }
+        if source.rev.as_deref().unwrap_or_default().is_empty() {
+            report.errors.push("source commit is required, resolve the reference before building".to_string());
+        }
+    }
+}
+
+/// With a build, the image is where the built image is pushed to, pushing to an
+/// official image of Docker Hub, like `nginx`, is most likely a mistake. Without
+/// a build, nor a source to build from, the image must already exist to run.
+fn validate_image_build(actor: &Actor, report: &mut Report) {
+    let spec = &actor.spec;
+    let builds = spec.character.build.is_some() || (spec.source.is_some() && !spec.live);
+
+    if builds && is_official_image(&spec.image) {
+        report.warnings.push(format!(
+            "image `{}` looks like an official image of Docker Hub, but the built image is pushed to it, \
+            set it to a repository of your registry",
+            spec.image
+        ));
+    }
+    if !builds && spec.image.is_empty() {
+        report.errors.push("image is required to run the actor, as there is no build or source to produce it".into());
+    }
+}
+
+/// Check if the image is an official image of Docker Hub, like `nginx:1.25`
+/// or `docker.io/library/nginx`.
+fn is_official_image(image: &str) -> bool {
+    let name = image.split('@').next().unwrap_or_default();
+    let name = match name.rfind(':') {
+        Some(index) if !name[index..].contains('/') => &name[..index],
+        _ => name,
+    };
+    let mut components: Vec<&str> = name.split('/').collect();
+    if components.len() > 1 && (components[0].contains('.') || components[0] == "localhost") {
+        if !DOCKER_HUB_REGISTRIES.contains(&components[0]) {
+            return false;
+        }
+        components.remove(0);
+    }
+
+    !name.is_empty() && (components.len() == 1 || (components.len() == 2 && components[0] == "library"))
+}
+
+/// The name of the main container must be a valid DNS-1123 label.
+fn validate_container_name(actor: &Actor, report: &mut Report) {
+    let name = actor.container_name();
+    if !is_dns1123_label(&name) {
+        report.errors.push(format!("container name `{}` is not a valid DNS-1123 label", name));
+    }
+}
+
+/// The command must split into arguments, or the image would run its own CMD.
+fn validate_command(actor: &Actor, report: &mut Report) {
+    if let Err(err) = actor.spec.command_args() {
+        report.errors.push(err.to_string());
+    }
+}
+
+/// An actor builds either with kaniko or with Cloud Native Buildpacks.
+fn validate_build_strategy(actor: &Actor, report: &mut Report) {
+    if let Some(Err(err)) = actor.spec.character.build.as_ref().map(|build| build.strategy()) {
+        report.errors.push(err.to_string());
+    }
+}
+
+/// The Dockerfile is a path relative to the build context, it must not escape it.
+/// Only a Dockerfile has stages to target, buildpacks do not.
+fn validate_dockerfile(actor: &Actor, report: &mut Report) {
+    let dockerfile = actor.spec.character.build.as_ref().is_some_and(|build| build.dockerfile.is_some());
+    if let Some(target) = actor.dockerfile_target().filter(|_| !dockerfile) {
+        report.errors.push(format!("dockerfile target `{}` requires a dockerfile to build", target));
+    }
+
+    if let Some(build) = &actor.spec.character.build {
+        if let Some(config) = build.dockerfile.as_ref().filter(|_| build.dockerfile_path(Path::new("")).is_none()) {
+            report.errors.push(format!(
+                "dockerfile `{}` must be a path relative to the build context, without `..`",
+                config.dockerfile
+            ));
+        }
+    }
+}
+
+/// An invalid build timeout would be ignored, and a hung build never stopped.
+fn validate_build_timeout(actor: &Actor, report: &mut Report) {
+    if let Some(timeout) = options(actor).build_timeout {
+        if actor.build_timeout_seconds().is_none() {
+            report.errors.push(format!("build timeout `{}` must be a positive duration like `30m`", timeout));
+        }
+    }
+}
+
+/// An invalid deploy timeout would be ignored, and a stuck rollout never reported.
+fn validate_deploy_timeout(actor: &Actor, report: &mut Report) {
+    if let Some(timeout) = options(actor).deploy_timeout {
+        if actor.deploy_timeout_seconds().is_none() {
+            report.errors.push(format!("deploy timeout `{}` must be a positive duration like `10m`", timeout));
+        }
+    }
+}
+
+/// Malformed or unsupported platforms would be ignored, and the image built for
+/// another one. Kaniko only builds one platform, it can not build a multi-arch image.
+fn validate_platforms(actor: &Actor, report: &mut Report) {
+    let platforms = actor.platforms();
+    report.errors.extend(platforms.iter().filter_map(|platform| validate_platform(platform).err()));
+
+    let dockerfile = actor.spec.character.build.as_ref().is_some_and(|build| build.dockerfile.is_some());
+    if dockerfile && platforms.len() > 1 {
+        report.warnings.push(format!(
+            "kaniko builds one platform only, the image is built for `{}` and not the others",
+            actor.platforms_or_default()[0]
+        ));
+    }
+}
+
+/// The cache image is pushed to the registry like the image itself.
+fn validate_build_cache(actor: &Actor, report: &mut Report) {
+    if let Some(repo) = actor.build_cache().and_then(|cache| cache.repo) {
+        if let Err(err) = validate_image_reference(&repo) {
+            report.errors.push(format!("build cache repo is invalid, {}", err));
+        }
+    }
+}
+
+/// The build secrets are mounted by their id, which must be set and unique.
+fn validate_build_secrets(actor: &Actor, report: &mut Report) {
+    if let Err(err) = actor.build_secrets() {
+        report.errors.push(err.to_string());
+    }
+}
+
+/// Invalid secret names would be ignored, and the images fail to pull.
+fn validate_image_pull_secrets(actor: &Actor, report: &mut Report) {
+    for name in actor.image_pull_secret_names().iter().filter(|name| !is_dns1123_subdomain(name)) {
+        report.errors.push(format!("image pull secret `{}` must be a valid RFC 1123 name", name));
+    }
+}
+
+/// The manifest paths of the source and of the partners must be relative to
+/// the repository root.
+fn validate_manifest_paths(actor: &Actor, report: &mut Report) {
+    let source = actor.spec.source.as_ref().map(|source| source.path.as_deref());
+    if let Some(Err(err)) = source.map(normalize_manifest_path) {
+        report.errors.push(err);
+    }
+
+    let mut partners: Vec<_> = actor.spec.character.partners.iter().flatten().collect();
+    partners.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, partner) in partners {
+        if let Partner::Repository(reference) = partner {
+            if let Err(err) = normalize_manifest_path(reference.path.as_deref()) {
+                report.errors.push(format!("partner {}: {}", name, err));
+            }
+        }
+    }
+}
+
+/// An invalid schedule would be ignored, and the actor deployed as a Deployment.
+/// The concurrency policy only applies to the CronJob of a scheduled actor.
+fn validate_schedule_options(actor: &Actor, report: &mut Report) {
+    let options = options(actor);
+    let schedule = options.schedule.as_deref();
+    if let Some(schedule) = schedule {
+        report.errors.extend(validate_schedule(schedule.trim()).err());
+    }
+
+    if let Some(policy) = options.concurrency_policy {
+        if !["Allow", "Forbid", "Replace"].contains(&policy.as_str()) {
+            report.errors.push(format!("concurrency policy `{}` must be one of Allow, Forbid or Replace", policy));
+        }
+        if schedule.is_none() {
+            report.errors.push("concurrency policy is only allowed with a schedule".to_string());
+        }
+    }
+}
+
+/// An invalid number of replicas would be ignored, and the actor run as one pod.
+fn validate_replicas(actor: &Actor, report: &mut Report) {
+    if let Some(replicas) = options(actor).replicas.filter(|replicas| *replicas < 0) {
+        report.errors.push(format!("replicas `{}` must be a non-negative integer", replicas));
+    }
+}
+
+/// A scheduled actor runs as a CronJob, which has no replicas to scale, and
+/// the sources of a synced actor are pushed to a single pod.
+fn validate_scaling(actor: &Actor, report: &mut Report) {
+    let options = options(actor);
+    if options.schedule.is_some() && options.replicas.is_some() {
+        report.errors.push("replicas are not allowed with a schedule, the actor runs as a CronJob".to_string());
+    }
+
+    let replicas = actor.replicas();
+    if actor.sync() && replicas != 1 {
+        report.errors.push(format!("replicas must be 1 when sync is enabled, found {}", replicas));
+    }
+}
+
+/// The weights of the canary are percents, its steps go up to the full traffic.
+/// Blue-green already deploys the new version next to the active one.
+fn validate_canary(actor: &Actor, report: &mut Report) {
+    let Some(canary) = actor.canary() else { return };
+
+    if canary.steps.windows(2).any(|steps| steps[0] > steps[1]) {
+        report.errors.push("canary steps must be in ascending order".to_string());
+    }
+    for weight in std::iter::once(canary.weight).chain(canary.steps) {
+        if !(0..=100).contains(&weight) {
+            report.errors.push(format!("canary weight `{}` must be between 0 and 100", weight));
+        }
+    }
+    if actor.deployment_strategy() == DeploymentStrategy::BlueGreen {
+        report.errors.push("canary is not allowed with the blue-green deployment strategy".to_string());
+    }
+}
+
+/// Sync relies on the webhooks of the provider, it silently does nothing
+/// when we can not tell which provider the repository is hosted on.
+fn validate_sync(actor: &Actor, report: &mut Report) {
+    if actor.sync() && matches!(provider::detect(actor), None | Some(Provider::Unknown)) {
+        report.warnings.push(
+            "sync is enabled, but the repository provider is unknown and its webhooks can not be received, \
+            set the `provider` option to enable it"
+                .to_string(),
+        );
+    }
+}
+
+/// The sync ref patterns must be well-formed, or they would never match.
+fn validate_sync_refs(actor: &Actor, report: &mut Report) {
+    report.errors.extend(actor.sync_refs().iter().filter_map(|pattern| validate_ref_pattern(pattern).err()));
+}
+
+/// The `AMP_*` variables are provided by Amphitheatre, users must opt in
+/// to override them so they are not clobbered by accident.
+fn validate_reserved_env(actor: &Actor, report: &mut Report) {
+    if actor.allow_reserved_env() {
+        return;
+    }
+
+    let character = &actor.spec.character;
+    let build = character.build.as_ref().and_then(|build| build.env.as_ref());
+    let deploy = character.deploy.as_ref().and_then(|deploy| deploy.env.as_ref());
+
+    let sources = actor.env_sources();
+    let mut names: Vec<&str> = build
+        .into_iter()
+        .chain(deploy)
+        .flat_map(|env| env.keys())
+        .chain(sources.keys())
+        .map(|name| name.as_str())
+        .filter(|name| name.starts_with(RESERVED_ENV_PREFIX))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    if !names.is_empty() {
+        report.errors.push(format!(
+            "environment variables {} are reserved, \
+            set the `allowReservedEnv` option to override them",
+            names.join(", ")
+        ));
+    }
+}
+
+/// The references of the environment variables must name a key.
+fn validate_env_sources(actor: &Actor, report: &mut Report) {
+    for (name, source) in actor.env_sources() {
+        if let Err(err) = source.to_env_var(&name) {
+            report.errors.push(err.to_string());
+        }
+    }
+}
+
+/// A service without ports produces a Service object that routes nothing.
+fn validate_services(actor: &Actor, report: &mut Report) {
+    let services = actor.spec.character.deploy.as_ref().and_then(|deploy| deploy.services.as_ref());
+    for (index, service) in services.into_iter().flatten().enumerate() {
+        if service.ports.is_empty() {
+            report.errors.push(format!("service #{} has no ports, at least one is required", index + 1));
+        }
+    }
+}
+
+/// The kind of the services must be a Service type of Kubernetes, the same for all.
+/// `ExternalName` is rejected, as the actor has no way to declare the external
+/// name, and the Service would be rejected on apply without it.
+fn validate_service_type(actor: &Actor, report: &mut Report) {
+    match actor.spec.service_type() {
+        Ok(ServiceKind::ExternalName) => report.errors.push(
+            "service type `ExternalName` is not supported, \
+            use one of ClusterIP, NodePort or LoadBalancer"
+                .to_string(),
+        ),
+        Ok(_) => {}
+        Err(err) => report.errors.push(err.to_string()),
+    }
+}
+
+/// The session affinity must be one of Kubernetes. When the not ready addresses
+/// are published, they are by a headless companion Service named after the
+/// actor, which has no virtual IP, so the affinity only applies to the ready
+/// pods behind the Service.
+fn validate_session_affinity(actor: &Actor, report: &mut Report) {
+    let affinity = actor.session_affinity();
+    if let Some(affinity) = affinity.as_deref().filter(|affinity| !matches!(*affinity, "ClientIP" | "None")) {
+        report.errors.push(format!("session affinity `{}` is unknown, expected ClientIP or None", affinity));
+    }
+
+    if !actor.publish_not_ready_addresses() {
+        return;
+    }
+
+    let name = service::headless_name(actor);
+    if !is_dns1123_label(&name) {
+        report.errors.push(format!(
+            "the headless Service `{}` publishing the not ready addresses is not a valid DNS-1123 label, \
+            shorten the name of the actor",
+            name
+        ));
+    }
+    if affinity.as_deref() == Some("ClientIP") {
+        report.warnings.push(format!(
+            "session affinity `ClientIP` only applies to the ready pods, \
+            the headless Service `{}` resolves to the pods directly",
+            name
+        ));
+    }
+}
+
+/// All the services are exposed by a single Service object, with the ports
+/// named after their protocol and port, e.g. `tcp-8080`. The same port declared
+/// by two services would derive the same name, and be rejected on apply.
+fn validate_service_port_names(actor: &Actor, report: &mut Report) {
+    let services = actor.spec.character.deploy.as_ref().and_then(|deploy| deploy.services.as_ref());
+    let mut owners: Vec<(String, usize)> = vec![];
+    for (index, service) in services.into_iter().flatten().enumerate() {
+        for port in &service.ports {
+            let protocol = port.protocol.as_deref().unwrap_or("TCP").to_ascii_lowercase();
+            let name = format!("{}-{}", protocol, port.port);
+            match owners.iter().find(|(existing, _)| *existing == name) {
+                Some((_, owner)) => report.errors.push(format!(
+                    "services #{} and #{} both declare the port `{}`, port names must be unique",
+                    owner + 1,
+                    index + 1,
+                    name
+                )),
+                None => owners.push((name, index)),
+            }
+        }
+    }
+}
+
+/// Ports must be in the range of 1-65535, with one of the protocols of
+/// Kubernetes, and a port can only be exposed once across the services. The
+/// privileged ports below 1024 can only be bound by root or with
+/// `NET_BIND_SERVICE`, which the actor container is not granted, so they
+/// most likely fail to start.
+fn validate_ports(actor: &Actor, report: &mut Report) {
+    let services = actor.spec.character.deploy.as_ref().and_then(|deploy| deploy.services.as_ref());
+    let mut exposed = HashSet::new();
+    for port in services.into_iter().flatten().flat_map(|service| service.ports.iter()) {
+        if let Some(protocol) = port.protocol.as_deref().filter(|protocol| !PORT_PROTOCOLS.contains(protocol)) {
+            report.errors.push(format!(
+                "port {} has an unsupported protocol `{}`, expected one of {}",
+                port.port,
+                protocol,
+                PORT_PROTOCOLS.join(", ")
+            ));
+        }
+        if port.expose.unwrap_or_default() && !exposed.insert(port.port) {
+            report.errors.push(format!("port {} is exposed more than once", port.port));
+        }
+
+        if !(1..=65535).contains(&port.port) {
+            report.errors.push(format!("port {} is out of range 1-65535", port.port));
+        } else if port.port < 1024 {
+            report.warnings.push(format!(
+                "port {} is privileged, it can only be bound with the NET_BIND_SERVICE capability",
+                port.port
+            ));
+        }
+    }
+}
+
+/// The probes may target undeclared ports by number, they are added to the
+/// container, but the ports they target by name must be declared, e.g.
+/// `tcp-8080`. Targeting an exposed port by number is only warned about, as
+/// the port is unnamed and known by its derived name in the pod.
+fn validate_probes(actor: &Actor, report: &mut Report) {
+    let ports = application::container(&actor.spec).ports.unwrap_or_default();
+    let services = actor.spec.character.deploy.as_ref().and_then(|deploy| deploy.services.as_ref());
+    let exposed: HashSet<i32> = services
+        .into_iter()
+        .flatten()
+        .flat_map(|service| service.ports.iter())
+        .filter(|port| port.expose.unwrap_or_default())
+        .map(|port| port.port)
+        .collect();
+
+    let probes = [("liveness", actor.liveness_probe()), ("readiness", actor.readiness_probe())];
+    for (kind, probe) in probes.iter().filter_map(|(kind, probe)| probe.as_ref().map(|probe| (kind, probe))) {
+        let targets = [probe.http_get.as_ref().map(|p| &p.port), probe.tcp_socket.as_ref().map(|p| &p.port)];
+        for port in targets.into_iter().flatten() {
+            match port {
+                IntOrString::Int(number) if exposed.contains(number) => {
+                    let name = ports.iter().find(|port| port.container_port == *number).and_then(|p| p.name.clone());
+                    report.warnings.push(format!(
+                        "{} probe targets the exposed port {}, which is unnamed and known as `{}` in the pod",
+                        kind,
+                        number,
+                        name.unwrap_or_default()
+                    ));
+                }
+                IntOrString::Int(_) => {}
+                IntOrString::String(name) => {
+                    if !ports.iter().any(|declared| declared.name.as_ref() == Some(name)) {
+                        report.errors.push(format!("{} probe port {} is not declared by any service", kind, name));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The artifacts are copied and served from absolute paths, by sidecars
+/// named apart from the main container. A CronJob would never complete with
+/// a sidecar serving forever next to its container.
+fn validate_shared_artifacts(actor: &Actor, report: &mut Report) {
+    let artifacts = actor.shared_artifacts();
+    if !artifacts.is_empty() && options(actor).schedule.is_some() {
+        report.errors.push("shared artifacts are not allowed with a schedule".to_string());
+    }
+
+    let mut names = HashSet::from([actor.container_name()]);
+    for artifact in &artifacts {
+        if !is_dns1123_label(&artifact.volume_name()) {
+            report.errors.push(format!("shared artifact name `{}` is not a valid DNS-1123 label", artifact.name));
+        }
+        for (field, path) in [("build path", &artifact.build_path), ("serve path", &artifact.serve_path)] {
+            if !path.starts_with('/') {
+                report.errors.push(format!("shared artifact {}: {} `{}` must be absolute", artifact.name, field, path));
+            }
+        }
+        if !names.insert(artifact.sidecar.name.clone()) {
+            report.errors.push(format!(
+                "shared artifact {}: sidecar name `{}` is already used by another container",
+                artifact.name, artifact.sidecar.name
+            ));
+        }
+    }
+}
+
+/// Returns the options of the actor, malformed ones are reported by `validate_options`.
+fn options(actor: &Actor) -> ActorOptions {
+    actor.options().unwrap_or_default()
+}
+
+/// The TLS secret must be a valid Kubernetes secret name.
+fn validate_tls(actor: &Actor, report: &mut Report) {
+    if let Some(tls) = actor.ingress_tls() {
+        if !is_dns1123_subdomain(&tls.secret_name) {
+            report.errors.push(format!("TLS secret name `{}` is not a valid DNS-1123 subdomain", tls.secret_name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use amp_common::resource::{ActorSpec, CharacterSpec};
+    use amp_common::schema::{Build, BuildpacksConfig, Deploy, DockerfileConfig, GitReference, Port, Service};
+    use amp_resources::options::OPTIONS_VERSION;
+    use kube::ResourceExt;
+
+    use super::*;
+
+    fn actor(repo: &str, sync: bool) -> Actor {
+        let source = GitReference { repo: repo.into(), rev: Some("abc123".into()), ..Default::default() };
+        let image = "registry.example.com/test".into();
+        let spec = ActorSpec { name: "test".into(), image, source: Some(source), ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+        actor.set_options(&ActorOptions { sync, ..Default::default() });
+        actor
+    }
+
+    /// Set the options of the actor as the user writes them, from the fields
+    /// of the JSON object like `"replicas": 3`.
+    fn set_options(actor: &mut Actor, fields: &str) {
+        let value = format!(r#"{{"version": "{}", {}}}"#, OPTIONS_VERSION, fields);
+        actor.annotations_mut().insert(OPTIONS_KEY.into(), value);
+    }
+
+    #[test]
+    fn test_malformed_options() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""replica": 3"#);
+
+        let report = validate_full(&actor);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].starts_with("InvalidOptions: unknown field `replica`"));
+        assert!(report.errors[0].ends_with("in the `amphitheatre.app/options` annotation"));
+    }
+
+    #[test]
+    fn test_options_of_another_version() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.annotations_mut().insert(OPTIONS_KEY.into(), r#"{"version": "v2", "replicas": 3}"#.into());
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["InvalidOptions: version `v2` is not supported, expected `v1`, in the `amphitheatre.app/options` annotation"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_container_name() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""containerName": "My_App""#);
+
+        let report = validate_full(&actor);
+        assert!(!report.is_valid());
+        assert_eq!(report.errors, vec!["container name `My_App` is not a valid DNS-1123 label"]);
+    }
+
+    #[test]
+    fn test_sync_with_known_provider() {
+        let report = validate_full(&actor("https://github.com/amphitheatre-app/amphitheatre.git", true));
+
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_sync_with_unknown_provider() {
+        let report = validate_full(&actor("https://git.example.com/group/project.git", true));
+
+        assert!(report.is_valid());
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_provider_without_sync() {
+        let report = validate_full(&actor("https://git.example.com/group/project.git", false));
+
+        assert!(report.warnings.is_empty());
+    }
+
+    fn with_env(names: &[&str]) -> Actor {
+        let env: HashMap<String, String> = names.iter().map(|name| (name.to_string(), "value".to_string())).collect();
+
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.character = CharacterSpec {
+            build: Some(Build {
+                env: Some(HashMap::from([("AMP_ACTOR".into(), "value".into())])),
+                ..Default::default()
+            }),
+            deploy: Some(Deploy { env: Some(env), ..Default::default() }),
+            ..Default::default()
+        };
+        actor
+    }
+
+    #[test]
+    fn test_reserved_env() {
+        let report = validate_full(&with_env(&["PORT", "AMP_PLAYBOOK", "AMP_ACTOR"]));
+
+        assert_eq!(
+            report.errors,
+            vec![
+                "environment variables AMP_ACTOR, AMP_PLAYBOOK are reserved, \
+                set the `allowReservedEnv` option to override them"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allowed_reserved_env() {
+        let mut actor = with_env(&["AMP_PLAYBOOK"]);
+        set_options(&mut actor, r#""allowReservedEnv": true"#);
+
+        assert!(validate_full(&actor).is_valid());
+    }
+
+    fn with_ports(ports: &[i32]) -> Actor {
+        let ports = ports.iter().map(|port| Port { port: *port, ..Default::default() }).collect();
+
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let services = vec![Service { ports, ..Default::default() }];
+        actor.spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+        actor
+    }
+
+    #[test]
+    fn test_env_source_without_key() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""env": {"PASSWORD": {"configMapKeyRef": {"name": "db", "key": ""}}}"#);
+
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["InvalidEnvSource: PASSWORD: the key of the reference is empty"]);
+    }
+
+    #[test]
+    fn test_replicas() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        for replicas in [0, 3] {
+            set_options(&mut actor, &format!(r#""replicas": {}"#, replicas));
+            assert!(validate_full(&actor).is_valid());
+        }
+
+        set_options(&mut actor, r#""replicas": -1"#);
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["replicas `-1` must be a non-negative integer"]);
+    }
+
+    #[test]
+    fn test_replicas_with_schedule() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""schedule": "0 * * * *""#);
+        assert!(validate_full(&actor).is_valid());
+
+        set_options(&mut actor, r#""schedule": "0 * * * *", "replicas": 2"#);
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["replicas are not allowed with a schedule, the actor runs as a CronJob"]
+        );
+    }
+
+    #[test]
+    fn test_replicas_with_sync() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", true);
+        set_options(&mut actor, r#""sync": true, "replicas": 1"#);
+        assert!(validate_full(&actor).is_valid());
+
+        set_options(&mut actor, r#""sync": true, "replicas": 3"#);
+        assert_eq!(validate_full(&actor).errors, vec!["replicas must be 1 when sync is enabled, found 3"]);
+    }
+
+    #[test]
+    fn test_shared_artifacts() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let artifact = |name: &str, build_path: &str, sidecar: &str| {
+            format!(
+                r#"{{"name": "{}", "buildPath": "{}", "servePath": "/srv", "sidecar": {{"name": "{}"}}}}"#,
+                name, build_path, sidecar
+            )
+        };
+
+        set_options(&mut actor, &format!(r#""sharedArtifacts": [{}]"#, artifact("public", "/app/dist", "nginx")));
+        assert!(validate_full(&actor).is_valid());
+
+        let artifacts = [artifact("public", "dist", "nginx"), artifact("Docs", "/app/docs", "test")];
+        set_options(&mut actor, &format!(r#""sharedArtifacts": [{}]"#, artifacts.join(", ")));
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec![
+                "shared artifact public: build path `dist` must be absolute",
+                "shared artifact name `Docs` is not a valid DNS-1123 label",
+                "shared artifact Docs: sidecar name `test` is already used by another container",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_artifacts_with_schedule() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let artifact =
+            r#"{"name": "public", "buildPath": "/app/dist", "servePath": "/srv", "sidecar": {"name": "nginx"}}"#;
+        set_options(&mut actor, &format!(r#""schedule": "0 * * * *", "sharedArtifacts": [{}]"#, artifact));
+
+        assert_eq!(validate_full(&actor).errors, vec!["shared artifacts are not allowed with a schedule"]);
+    }
+
+    #[test]
+    fn test_canary() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""canary": {"weight": 10, "steps": [50, 100]}"#);
+        assert!(validate_full(&actor).is_valid());
+
+        set_options(&mut actor, r#""canary": {"weight": 10, "steps": [150, 50]}"#);
+        let report = validate_full(&actor);
+        assert_eq!(
+            report.errors,
+            vec!["canary steps must be in ascending order", "canary weight `150` must be between 0 and 100"]
+        );
+    }
+
+    #[test]
+    fn test_canary_with_blue_green() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""canary": {"weight": 10}, "deploymentStrategy": "blue-green""#);
+
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["canary is not allowed with the blue-green deployment strategy"]);
+    }
+
+    #[test]
+    fn test_build_timeout() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        for timeout in ["30m", "1h30m", "90"] {
+            set_options(&mut actor, &format!(r#""buildTimeout": "{}""#, timeout));
+            assert!(validate_full(&actor).is_valid());
+        }
+
+        for timeout in ["abc", "-5m", "0s"] {
+            set_options(&mut actor, &format!(r#""buildTimeout": "{}""#, timeout));
+            let report = validate_full(&actor);
+            assert_eq!(
+                report.errors,
+                vec![format!("build timeout `{}` must be a positive duration like `30m`", timeout)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_deploy_timeout() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        for timeout in ["10m", "1h30m", "600"] {
+            set_options(&mut actor, &format!(r#""deployTimeout": "{}""#, timeout));
+            assert!(validate_full(&actor).is_valid());
+        }
+
+        for timeout in ["abc", "0s", "18446744073709551615s1s", "100000d"] {
+            set_options(&mut actor, &format!(r#""deployTimeout": "{}""#, timeout));
+            let report = validate_full(&actor);
+            assert_eq!(
+                report.errors,
+                vec![format!("deploy timeout `{}` must be a positive duration like `10m`", timeout)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_tls_secret_name() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""tls": {"secretName": "api.tls"}"#);
+        assert!(validate_full(&actor).is_valid());
+
+        set_options(&mut actor, r#""tls": {"secretName": "API_TLS"}"#);
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["TLS secret name `API_TLS` is not a valid DNS-1123 subdomain"]);
+    }
+
+    #[test]
+    fn test_manifest_paths() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.source.as_mut().unwrap().path = Some("./services/api/".into());
+        assert!(validate_full(&actor).is_valid());
+
+        actor.spec.source.as_mut().unwrap().path = Some("/abs/.amp.toml".into());
+        let reference = GitReference {
+            repo: "https://github.com/amphitheatre-app/db".into(),
+            path: Some("/db".into()),
+            ..Default::default()
+        };
+        actor.spec.character.partners = Some(HashMap::from([("db".into(), Partner::Repository(reference))]));
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec![
+                "manifest path `/abs/.amp.toml` must be relative to the repository root",
+                "partner db: manifest path `/db` must be relative to the repository root"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_ports() {
+        let report = validate_full(&with_ports(&[0, 65536]));
+
+        assert_eq!(report.errors, vec!["port 0 is out of range 1-65535", "port 65536 is out of range 1-65535"]);
+    }
+
+    #[test]
+    fn test_privileged_ports() {
+        let report = validate_full(&with_ports(&[80]));
+
+        assert!(report.is_valid());
+        assert_eq!(
+            report.warnings,
+            vec!["port 80 is privileged, it can only be bound with the NET_BIND_SERVICE capability"]
+        );
+    }
+
+    #[test]
+    fn test_normal_ports() {
+        let report = validate_full(&with_ports(&[1024, 8080, 65535]));
+
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_probes_on_declared_ports() {
+        let mut actor = with_ports(&[8080]);
+        let probes =
+            r#""livenessProbe": {"httpGet": {"port": 8080}}, "readinessProbe": {"tcpSocket": {"port": "tcp-8080"}}"#;
+        set_options(&mut actor, probes);
+
+        assert!(validate_full(&actor).is_valid());
+    }
+
+    #[test]
+    fn test_probes_on_undeclared_ports() {
+        let mut actor = with_ports(&[8080]);
+        let probes =
+            r#""livenessProbe": {"httpGet": {"port": 9090}}, "readinessProbe": {"tcpSocket": {"port": "http"}}"#;
+        set_options(&mut actor, probes);
+
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["readiness probe port http is not declared by any service"]);
+    }
+
+    #[test]
+    fn test_probes_on_exposed_ports() {
+        let mut actor = with_ports(&[8080]);
+        let services = actor.spec.character.deploy.as_mut().unwrap().services.as_mut().unwrap();
+        services[0].ports[0].expose = Some(true);
+        set_options(&mut actor, r#""readinessProbe": {"httpGet": {"port": 8080}}"#);
+
+        let report = validate_full(&actor);
+        assert!(report.is_valid());
+        assert_eq!(
+            report.warnings,
+            vec!["readiness probe targets the exposed port 8080, which is unnamed and known as `tcp-8080` in the pod"]
+        );
+    }
+
+    #[test]
+    fn test_malformed_sync_refs() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", true);
+        set_options(&mut actor, r#""sync": true, "syncRefs": ["main", "release//*"]"#);
+
+        assert_eq!(validate_full(&actor).errors, vec!["sync ref `release//*` has an empty path segment"]);
+    }
+
+    #[test]
+    fn test_service_without_ports() {
+        let report = validate_full(&with_ports(&[]));
+
+        assert_eq!(report.errors, vec!["service #1 has no ports, at least one is required"]);
+    }
+
+    #[test]
+    fn test_required_fields() {
+        let mut actor = actor("", false);
+        set_options(&mut actor, r#""containerName": "test""#);
+        actor.spec.name = String::new();
+        actor.spec.image = String::new();
+        actor.spec.source.as_mut().unwrap().rev = None;
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec![
+                "name is required",
+                "image is required",
+                "source repository is required",
+                "source commit is required, resolve the reference before building",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_required_fields_of_live_actor() {
+        let mut actor = actor("", false);
+        actor.spec.live = true;
+        actor.spec.source.as_mut().unwrap().rev = None;
+
+        assert!(validate_full(&actor).is_valid());
+    }
+
+    fn with_services(services: &[&[i32]]) -> Actor {
+        let services = services
+            .iter()
+            .map(|ports| Service {
+                ports: ports.iter().map(|port| Port { port: *port, ..Default::default() }).collect(),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+        actor
+    }
+
+    #[test]
+    fn test_services_on_different_ports() {
+        assert!(validate_full(&with_services(&[&[8080], &[9090]])).is_valid());
+    }
+
+    #[test]
+    fn test_services_on_the_same_port() {
+        let report = validate_full(&with_services(&[&[8080], &[9090, 8080]]));
+
+        assert_eq!(
+            report.errors,
+            vec!["services #1 and #2 both declare the port `tcp-8080`, port names must be unique"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_service_type() {
+        let mut actor = with_ports(&[8080]);
+        actor.spec.character.deploy.as_mut().unwrap().services.as_mut().unwrap()[0].kind = Some("loadbalancer".into());
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["InvalidServiceType: service type `loadbalancer` is unknown, did you mean `LoadBalancer`?"]
+        );
+    }
+
+    #[test]
+    fn test_external_name_service_type() {
+        let mut actor = with_ports(&[8080]);
+        actor.spec.character.deploy.as_mut().unwrap().services.as_mut().unwrap()[0].kind = Some("ExternalName".into());
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["service type `ExternalName` is not supported, use one of ClusterIP, NodePort or LoadBalancer"]
+        );
+    }
+
+    #[test]
+    fn test_session_affinity() {
+        let mut actor = with_ports(&[8080]);
+        set_options(&mut actor, r#""sessionAffinity": "clientip""#);
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["session affinity `clientip` is unknown, expected ClientIP or None"]
+        );
+
+        set_options(&mut actor, r#""sessionAffinity": "ClientIP""#);
+        let report = validate_full(&actor);
+        assert!(report.errors.is_empty());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_session_affinity_with_not_ready_addresses() {
+        let mut actor = with_ports(&[8080]);
+        set_options(&mut actor, r#""sessionAffinity": "ClientIP", "publishNotReadyAddresses": true"#);
+
+        let report = validate_full(&actor);
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            report.warnings,
+            vec![
+                "session affinity `ClientIP` only applies to the ready pods, \
+            the headless Service `test-headless` resolves to the pods directly"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_headless_service_name_too_long() {
+        let mut actor = with_ports(&[8080]);
+        actor.metadata.name = Some("a".repeat(60));
+        set_options(&mut actor, r#""publishNotReadyAddresses": true"#);
+
+        let name = format!("{}-headless", "a".repeat(60));
+        assert!(validate_full(&actor).errors.contains(&format!(
+            "the headless Service `{}` publishing the not ready addresses is not a valid DNS-1123 label, \
+            shorten the name of the actor",
+            name
+        )));
+    }
+
+    #[test]
+    fn test_ports_with_bad_protocol() {
+        let mut actor = with_ports(&[8080, 9090]);
+        let ports = &mut actor.spec.character.deploy.as_mut().unwrap().services.as_mut().unwrap()[0].ports;
+        ports[0].protocol = Some("UDP".into());
+        ports[1].protocol = Some("HTTP".into());
+
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["port 9090 has an unsupported protocol `HTTP`, expected one of TCP, UDP, SCTP"]);
+    }
+
+    #[test]
+    fn test_ports_exposed_twice() {
+        let mut actor = with_services(&[&[8080], &[9090]]);
+        let services = actor.spec.character.deploy.as_mut().unwrap().services.as_mut().unwrap();
+        services[0].ports[0].expose = Some(true);
+        services[1].ports.push(Port {
+            port: 8080,
+            protocol: Some("UDP".into()),
+            expose: Some(true),
+            ..Default::default()
+        });
+
+        let report = validate_full(&actor);
+        assert_eq!(report.errors, vec!["port 8080 is exposed more than once"]);
+    }
+
+    #[test]
+    fn test_command_with_unbalanced_quotes() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.character.deploy =
+            Some(Deploy { command: Some(r#"myapp --name "a b"#.into()), ..Default::default() });
+
+        assert_eq!(validate_full(&actor).errors, vec![r#"InvalidCommand: `myapp --name "a b`: unterminated `"`"#]);
+    }
+
+    #[test]
+    fn test_dockerfile_outside_the_context() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let dockerfile = Some(DockerfileConfig { dockerfile: "../Dockerfile".into() });
+        actor.spec.character.build = Some(Build { dockerfile, ..Default::default() });
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["dockerfile `../Dockerfile` must be a path relative to the build context, without `..`"]
+        );
+    }
+
+    #[test]
+    fn test_dockerfile_target() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let dockerfile = Some(DockerfileConfig { dockerfile: "Dockerfile".into() });
+        actor.spec.character.build = Some(Build { dockerfile, ..Default::default() });
+        set_options(&mut actor, r#""dockerfileTarget": "runtime""#);
+
+        assert!(validate_full(&actor).is_valid());
+    }
+
+    #[test]
+    fn test_dockerfile_target_with_buildpacks() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let buildpacks = Some(BuildpacksConfig { builder: "paketobuildpacks/builder:base".into(), buildpacks: None });
+        actor.spec.character.build = Some(Build { buildpacks, ..Default::default() });
+        set_options(&mut actor, r#""dockerfileTarget": "runtime""#);
+
+        assert_eq!(validate_full(&actor).errors, vec!["dockerfile target `runtime` requires a dockerfile to build"]);
+    }
+
+    #[test]
+    fn test_platforms() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""platforms": ["linux/arm64", "linux/arm64"]"#);
+        assert!(validate_full(&actor).is_valid());
+
+        set_options(&mut actor, r#""platforms": ["arm64", "windows/amd64"]"#);
+        let report = validate_full(&actor);
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0], "platform `arm64` must be an `os/arch[/variant]` pair like `linux/arm64`");
+        assert!(report.errors[1].starts_with("platform `windows/amd64` is not supported"));
+    }
+
+    #[test]
+    fn test_multiple_platforms_with_kaniko() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let dockerfile = Some(DockerfileConfig { dockerfile: "Dockerfile".into() });
+        actor.spec.character.build = Some(Build { dockerfile, ..Default::default() });
+        set_options(&mut actor, r#""platforms": ["linux/arm64", "linux/amd64"]"#);
+
+        let report = validate_full(&actor);
+        assert!(report.is_valid());
+        assert_eq!(
+            report.warnings,
+            vec!["kaniko builds one platform only, the image is built for `linux/arm64` and not the others"]
+        );
+    }
+
+    #[test]
+    fn test_build_cache() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""buildCache": {"enabled": true}"#);
+        assert!(validate_full(&actor).is_valid());
+        assert_eq!(actor.cache_image(), Some("registry.example.com/test-cache".into()));
+
+        set_options(&mut actor, r#""buildCache": {"enabled": true, "repo": "registry.example.com/cache/test"}"#);
+        assert!(validate_full(&actor).is_valid());
+        assert_eq!(actor.cache_image(), Some("registry.example.com/cache/test".into()));
+    }
+
+    #[test]
+    fn test_invalid_build_cache() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""buildCache": {"enabled": true, "repo": "registry.example.com/Cache"}"#);
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["build cache repo is invalid, image `registry.example.com/Cache` has an uppercase repository component `Cache`"]
+        );
+    }
+
+    #[test]
+    fn test_build_secrets() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let secrets =
+            r#"{"id": "npm", "name": "npm-token", "key": "token"}, {"id": "pip", "name": "pip", "key": "conf"}"#;
+        set_options(&mut actor, &format!(r#""buildSecrets": [{}]"#, secrets));
+
+        assert!(validate_full(&actor).is_valid());
+    }
+
+    #[test]
+    fn test_duplicate_build_secrets() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let secrets = r#"{"id": "npm", "name": "a", "key": "token"}, {"id": "npm", "name": "b", "key": "token"}"#;
+        set_options(&mut actor, &format!(r#""buildSecrets": [{}]"#, secrets));
+
+        assert_eq!(validate_full(&actor).errors, vec!["InvalidBuild: build secret id `npm` is used more than once"]);
+    }
+
+    #[test]
+    fn test_image_pull_secrets() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""imagePullSecrets": ["registry-credentials", "ghcr.io"]"#);
+        assert!(validate_full(&actor).is_valid());
+
+        set_options(&mut actor, r#""imagePullSecrets": ["Registry_Credentials", "-registry"]"#);
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec![
+                "image pull secret `Registry_Credentials` must be a valid RFC 1123 name",
+                "image pull secret `-registry` must be a valid RFC 1123 name",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dockerfile_and_buildpacks() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        let dockerfile = Some(DockerfileConfig { dockerfile: "Dockerfile".into() });
+        let buildpacks = Some(BuildpacksConfig { builder: "paketobuildpacks/builder:base".into(), buildpacks: None });
+        actor.spec.character.build = Some(Build { dockerfile, buildpacks, ..Default::default() });
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["InvalidBuild: dockerfile and buildpacks are both set, an actor builds with one of them"]
+        );
+    }
+
+    #[test]
+    fn test_buildpacks_without_builder() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.character.build = Some(Build { buildpacks: Some(Default::default()), ..Default::default() });
+
+        assert_eq!(validate_full(&actor).errors, vec!["InvalidBuild: buildpacks require a builder image"]);
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""schedule": "* * *""#);
+
+        assert_eq!(validate_full(&actor).errors, vec!["schedule `* * *` must have 5 fields, found 3"]);
+    }
+
+    #[test]
+    fn test_concurrency_policy() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        set_options(&mut actor, r#""concurrencyPolicy": "Sometimes""#);
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec![
+                "concurrency policy `Sometimes` must be one of Allow, Forbid or Replace",
+                "concurrency policy is only allowed with a schedule",
+            ]
+        );
+
+        set_options(&mut actor, r#""concurrencyPolicy": "Replace", "schedule": "@daily""#);
+        assert!(validate_full(&actor).is_valid());
+    }
+
+    fn with_build(image: &str) -> Actor {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.image = image.into();
+        actor.spec.character.build = Some(Build::default());
+        actor
+    }
+
+    #[test]
+    fn test_build_to_custom_registry() {
+        let report = validate_full(&with_build("registry.example.com/team/nginx:abc123"));
+
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_build_to_official_image() {
+        let report = validate_full(&with_build("docker.io/library/nginx"));
+
+        assert!(report.is_valid());
+        assert_eq!(
+            report.warnings,
+            vec![
+                "image `docker.io/library/nginx` looks like an official image of Docker Hub, \
+                but the built image is pushed to it, set it to a repository of your registry"
+            ]
+        );
+        assert_eq!(validate_full(&with_build("nginx:1.25")).warnings.len(), 1);
+        assert!(validate_full(&with_build("amphitheatre/nginx")).warnings.is_empty());
+    }
+
+    #[test]
+    fn test_image_without_build() {
+        let mut actor = actor("", false);
+        actor.spec.source = None;
+        actor.spec.image = String::new();
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["image is required to run the actor, as there is no build or source to produce it"]
+        );
+
+        actor.spec.image = "nginx:1.25".into();
+        let report = validate_full(&actor);
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_image() {
+        let mut actor = actor("https://github.com/amphitheatre-app/amphitheatre.git", false);
+        actor.spec.image = "my app:latest".into();
+
+        assert_eq!(
+            validate_full(&actor).errors,
+            vec!["image `my app:latest` has an invalid repository component `my app`"]
+        );
+    }
+
+    fn partner(name: &str) -> Partner {
+        let repo = format!("https://github.com/amphitheatre-app/{}.git", name);
+        Partner::Repository(GitReference { repo, ..Default::default() })
+    }
+
+    fn graph_spec(name: &str, image: &str, partners: &[&str]) -> ActorSpec {
+        let partners = partners.iter().map(|name| (name.to_string(), partner(name))).collect();
+        let character = CharacterSpec { partners: Some(partners), ..Default::default() };
+        ActorSpec { name: name.into(), image: image.into(), character, ..Default::default() }
+    }
+
+    fn resolve(partner: &Partner) -> Option<ActorSpec> {
+        let Partner::Repository(reference) = partner else {
+            return None;
+        };
+        let name = reference.repo.trim_start_matches("https://github.com/amphitheatre-app/").trim_end_matches(".git");
+        match name {
+            "api" => Some(graph_spec("api", "api", &["postgres", "web"])),
+            "postgres" => Some(graph_spec("postgres", "postgres", &[])),
+            "redis" => Some(graph_spec("redis", "", &[])),
+            "web" => Some(graph_spec("web", "web", &["api"])),
+            "worker" => Some(graph_spec("worker", "worker", &["postgres", "redis", "missing"])),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_validate_graph() {
+        // The cycle between web and api is not followed
+        assert_eq!(validate_graph(&graph_spec("web", "web", &["api"]), resolve), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_graph_with_invalid_partner() {
+        let root = graph_spec("web", "web", &["api", "worker"]);
+
+        assert_eq!(
+            validate_graph(&root, resolve),
+            Err(vec![
+                "worker/missing: partner can not be resolved".into(),
+                "worker/redis: image is required to run the actor, as there is no build or source to produce it".into()
+            ])
+        );
+    }
+}
@@ -12,31 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::blue_green::Color;
 use super::error::{Error, Result};
+use super::ext::actor::{
+    ACTIVE_COLOR_KEY, BUILD_FINISHED_AT_KEY, BUILD_STARTED_AT_KEY, ENV_CONFIGMAP_KEY, FAILURE_COUNT_KEY,
+    IMAGE_DIGEST_KEY, LAST_BUILD_COMMIT_KEY, LAST_BUILD_FINGERPRINT_KEY, RECONCILED_FINGERPRINT_KEY, REPLICAS_KEY,
+};
+use super::ext::{ActorExt, ActorSpecExt, ActorStatusExt, ConditionExt, PlaybookExt};
 
 use amp_common::resource::{Actor, ActorSpec, ActorState, Playbook};
 use k8s_metrics::v1beta1::PodMetrics;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
-use kube::api::{ListParams, Patch, PatchParams, PostParams};
+use k8s_openapi::chrono::{SecondsFormat, Utc};
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams, PostParams};
 use kube::{Api, Client, Resource, ResourceExt};
 use serde_json::json;
 use tracing::{debug, error, info};
 
 pub async fn exists(client: &Client, playbook: &Playbook, name: &str) -> Result<bool> {
-    let namespace = playbook.spec.namespace();
+    let namespace = playbook.target_namespace(name);
     let api: Api<Actor> = Api::namespaced(client.clone(), namespace.as_str());
 
     Ok(api.get_opt(name).await.map_err(Error::KubeError)?.is_some())
 }
 
 pub async fn create(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> Result<Actor> {
-    let namespace = playbook.spec.namespace();
+    let namespace = playbook.target_namespace(&spec.name);
     let api: Api<Actor> = Api::namespaced(client.clone(), namespace.as_str());
 
-    let name = spec.name.clone();
-    let mut resource = Actor::new(&name, spec.clone());
-    resource.owner_references_mut().push(playbook.controller_owner_ref(&()).unwrap());
-
+    let resource = resource(playbook, spec);
     let actor = api.create(&PostParams::default(), &resource).await.map_err(Error::KubeError)?;
     info!("Created Actor: {}", actor.name_any());
 
@@ -46,20 +50,19 @@ pub async fn create(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> R
 }
 
 pub async fn update(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> Result<Actor> {
-    let namespace = playbook.spec.namespace();
+    let namespace = playbook.target_namespace(&spec.name);
     let api: Api<Actor> = Api::namespaced(client.clone(), namespace.as_str());
 
     let name = spec.name.clone();
     let mut actor = api.get(&name).await.map_err(Error::KubeError)?;
     debug!("The Actor {} already exists", &spec.name);
 
-    if &actor.spec == spec {
+    let resource = resource(playbook, spec);
+    if up_to_date(&actor, &resource) {
         debug!("The Actor {} is already up-to-date", &spec.name);
         return Ok(actor);
     }
 
-    let mut resource = Actor::new(&name, spec.clone());
-    resource.owner_references_mut().push(playbook.controller_owner_ref(&()).unwrap());
     debug!("The updating Actor resource:\n {:?}\n", resource);
 
     let params = &PatchParams::apply("amp-controllers").force();
@@ -70,18 +73,137 @@ pub async fn update(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> R
     Ok(actor)
 }
 
+/// Check if the actor already has the spec and the replicas of the resource.
+/// The replicas are compared both ways, so that an override dropped from the
+/// playbook is removed from the actor by the apply too.
+fn up_to_date(actor: &Actor, resource: &Actor) -> bool {
+    actor.spec == resource.spec && actor.annotations().get(REPLICAS_KEY) == resource.annotations().get(REPLICAS_KEY)
+}
+
+/// Build the Actor of the character owned by the playbook, with the overrides
+/// of the playbook applied to its spec, and its replicas if overridden.
+fn resource(playbook: &Playbook, spec: &ActorSpec) -> Actor {
+    let name = spec.name.clone();
+    let mut spec = spec.clone();
+    playbook.apply_overrides(&mut spec, &name);
+
+    let mut resource = Actor::new(&name, spec);
+    resource.owner_references_mut().push(playbook.controller_owner_ref(&()).unwrap());
+    if let Some(replicas) = playbook.overrides().get(&name).and_then(|overrides| overrides.replicas) {
+        resource.annotations_mut().insert(REPLICAS_KEY.into(), replicas.to_string());
+    }
+
+    resource
+}
+
+/// Delete the actor of the character from the playbook, if it exists.
+pub async fn delete(client: &Client, playbook: &Playbook, name: &str) -> Result<()> {
+    let namespace = playbook.target_namespace(name);
+    let api: Api<Actor> = Api::namespaced(client.clone(), namespace.as_str());
+
+    if api.get_opt(name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(name, &DeleteParams::default()).await.map_err(Error::KubeError)?;
+        info!("Deleted Actor: {}", name);
+    }
+
+    Ok(())
+}
+
 pub async fn patch_status(client: &Client, actor: &Actor, condition: Condition) -> Result<()> {
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
 
     let api: Api<Actor> = Api::namespaced(client.clone(), &namespace);
 
-    let status = json!({ "status": { "conditions": vec![condition.clone()] }});
-    let actor = api
+    let status = status_patch(actor, condition.clone());
+    let patched = api
         .patch_status(actor.name_any().as_str(), &PatchParams::default(), &Patch::Merge(&status))
         .await
         .map_err(Error::KubeError)?;
 
-    info!("Patched status {:?} with reason {:?} for Actor {}", condition.type_, condition.reason, actor.name_any());
+    info!("Patched status {:?} with reason {:?} for Actor {}", condition.type_, condition.reason, patched.name_any());
+
+    if let Some(failure_count) = next_failure_count(actor, &patched) {
+        annotate(client, actor, json!({ FAILURE_COUNT_KEY: failure_count })).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the merge patch setting the condition on the status of the actor.
+/// A merge patch replaces the conditions as a whole, so it carries all of
+/// them, with the condition upserted and stamped with the generation of the
+/// actor it was observed at.
+fn status_patch(actor: &Actor, condition: Condition) -> serde_json::Value {
+    let mut status = actor.status.clone().unwrap_or_default();
+    status.set_condition(condition.observed(actor.metadata.generation));
+
+    json!({ "status": { "conditions": status.conditions } })
+}
+
+/// Count the consecutive failures for the backoff: the count is incremented
+/// on each transition to failed, and reset once the actor runs again.
+/// Returns the new value of the annotation, or `None` if it is unchanged.
+fn next_failure_count(before: &Actor, after: &Actor) -> Option<serde_json::Value> {
+    let failed = |actor: &Actor| actor.status.as_ref().is_some_and(|status| status.failed());
+    let running = after.status.as_ref().is_some_and(|status| status.running());
+
+    if failed(after) && !failed(before) {
+        Some(json!((before.failure_count() + 1).to_string()))
+    } else if running && before.failure_count() > 0 {
+        Some(serde_json::Value::Null)
+    } else {
+        None
+    }
+}
+
+/// Record the start of a new build, and clear the finish time and the image
+/// digest of the previous one.
+pub async fn mark_build_started(client: &Client, actor: &Actor) -> Result<()> {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let annotations = json!({ BUILD_STARTED_AT_KEY: now, BUILD_FINISHED_AT_KEY: null, IMAGE_DIGEST_KEY: null });
+    annotate(client, actor, annotations).await
+}
+
+/// Record the digest of the pushed image, to deploy the image pinned by it.
+pub async fn record_image_digest(client: &Client, actor: &Actor, digest: &str) -> Result<()> {
+    annotate(client, actor, json!({ IMAGE_DIGEST_KEY: digest })).await
+}
+
+/// Record the name of the ConfigMap the resolved environment is materialized in.
+pub async fn record_env_configmap(client: &Client, actor: &Actor, name: &str) -> Result<()> {
+    annotate(client, actor, json!({ ENV_CONFIGMAP_KEY: name })).await
+}
+
+/// Record the fingerprint of the desired state a `Reconciled` event was emitted for.
+pub async fn record_reconciled_fingerprint(client: &Client, actor: &Actor, fingerprint: &str) -> Result<()> {
+    annotate(client, actor, json!({ RECONCILED_FINGERPRINT_KEY: fingerprint })).await
+}
+
+/// Record the color serving the traffic of an actor deployed blue-green.
+pub async fn record_active_color(client: &Client, actor: &Actor, color: Color) -> Result<()> {
+    annotate(client, actor, json!({ ACTIVE_COLOR_KEY: color.to_string() })).await
+}
+
+/// Record the finish of the current build, with the fingerprint and commit it was built from.
+pub async fn mark_build_finished(client: &Client, actor: &Actor) -> Result<()> {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let commit = actor.spec.source.as_ref().map(|source| source.rev()).unwrap_or_default();
+    let annotations = json!({
+        BUILD_FINISHED_AT_KEY: now,
+        LAST_BUILD_COMMIT_KEY: commit,
+        LAST_BUILD_FINGERPRINT_KEY: actor.spec.build_fingerprint(),
+    });
+    annotate(client, actor, annotations).await
+}
+
+async fn annotate(client: &Client, actor: &Actor, annotations: serde_json::Value) -> Result<()> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Actor> = Api::namespaced(client.clone(), &namespace);
+
+    let patch = json!({ "metadata": { "annotations": annotations }});
+    api.patch(actor.name_any().as_str(), &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+        .map_err(Error::KubeError)?;
 
     Ok(())
 }
@@ -119,3 +241,98 @@ pub async fn list(client: &Client, namespace: &str) -> Result<Vec<Actor>> {
 
     Ok(actors.items)
 }
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorStatus;
+
+    use super::*;
+
+    fn actor(condition: Condition, failure_count: Option<&str>) -> Actor {
+        let mut actor = Actor::new("test", ActorSpec::default());
+        let mut status = ActorStatus::default();
+        status.conditions.push(condition);
+        actor.status = Some(status);
+        if let Some(count) = failure_count {
+            actor.annotations_mut().insert(FAILURE_COUNT_KEY.into(), count.into());
+        }
+        actor
+    }
+
+    fn failed() -> Condition {
+        ActorState::failed(true, "BuildFailed", None)
+    }
+
+    fn with_replicas(replicas: Option<&str>) -> Actor {
+        let mut actor = Actor::new("test", ActorSpec::default());
+        if let Some(replicas) = replicas {
+            actor.annotations_mut().insert(REPLICAS_KEY.into(), replicas.into());
+        }
+        actor
+    }
+
+    #[test]
+    fn test_up_to_date() {
+        assert!(up_to_date(&with_replicas(None), &with_replicas(None)));
+        assert!(up_to_date(&with_replicas(Some("3")), &with_replicas(Some("3"))));
+        assert!(!up_to_date(&with_replicas(None), &with_replicas(Some("3"))));
+        assert!(!up_to_date(&with_replicas(Some("2")), &with_replicas(Some("3"))));
+
+        let mut resource = with_replicas(None);
+        resource.spec.image = "registry.local/amp/api".into();
+        assert!(!up_to_date(&with_replicas(None), &resource));
+    }
+
+    #[test]
+    fn test_up_to_date_with_override_removed() {
+        // The playbook dropped the override, the stale annotation must be removed
+        assert!(!up_to_date(&with_replicas(Some("3")), &with_replicas(None)));
+    }
+
+    #[test]
+    fn test_failure_count_progression() {
+        let before = actor(ActorState::building(), None);
+        assert_eq!(next_failure_count(&before, &actor(failed(), None)), Some(json!("1")));
+
+        let before = actor(ActorState::building(), Some("2"));
+        assert_eq!(next_failure_count(&before, &actor(failed(), Some("2"))), Some(json!("3")));
+
+        // Staying failed is not a new transition
+        let before = actor(failed(), Some("3"));
+        assert_eq!(next_failure_count(&before, &actor(failed(), Some("3"))), None);
+    }
+
+    #[test]
+    fn test_failure_count_reset() {
+        let running = ActorState::running(true, "AutoRun", None);
+
+        let before = actor(ActorState::building(), Some("3"));
+        assert_eq!(next_failure_count(&before, &actor(running.clone(), Some("3"))), Some(serde_json::Value::Null));
+
+        let before = actor(ActorState::building(), None);
+        assert_eq!(next_failure_count(&before, &actor(running, None)), None);
+    }
+
+    #[test]
+    fn test_status_patch_keeps_the_conditions() {
+        let degraded = Condition { type_: "Degraded".into(), reason: "NotReady".into(), ..ActorState::building() };
+        let mut actor = actor(ActorState::building(), None);
+        actor.status.as_mut().unwrap().conditions.push(degraded);
+        actor.metadata.generation = Some(2);
+
+        let patch = status_patch(&actor, ActorState::running(true, "AutoRun", None));
+        let conditions = patch["status"]["conditions"].as_array().unwrap();
+        let states: Vec<_> = conditions.iter().map(|c| (c["type"].clone(), c["status"].clone())).collect();
+        let building = ActorState::building().type_;
+        let running = ActorState::running(true, "AutoRun", None).type_;
+        assert_eq!(
+            states,
+            vec![
+                (json!(building), json!("False")),
+                (json!("Degraded"), json!("True")),
+                (json!(running), json!("True")),
+            ]
+        );
+        assert_eq!(conditions[2]["observedGeneration"], json!(2));
+    }
+}
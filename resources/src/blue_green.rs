@@ -0,0 +1,237 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::Service;
+use kube::api::{DeleteParams, Patch, PatchParams};
+use kube::{Api, Client, ResourceExt};
+use serde_json::json;
+use tracing::info;
+
+use super::error::{Error, Result};
+use super::ext::{ActorExt, DeploymentStrategy};
+use super::{actor, deployment, to_dns1123_label};
+
+const CHARACTER_KEY: &str = "amphitheatre.app/character";
+const COLOR_KEY: &str = "amphitheatre.app/color";
+
+/// One of the two Deployments of an actor deployed blue-green.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Blue,
+    Green,
+}
+
+impl Color {
+    /// Returns the other color, the one the next version is deployed as.
+    pub fn other(self) -> Color {
+        match self {
+            Color::Blue => Color::Green,
+            Color::Green => Color::Blue,
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Blue => write!(f, "blue"),
+            Color::Green => write!(f, "green"),
+        }
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "blue" => Ok(Color::Blue),
+            "green" => Ok(Color::Green),
+            _ => Err(format!("color `{}` must be one of blue or green", value)),
+        }
+    }
+}
+
+/// Returns the name of the Deployment of the color, like `api-blue`.
+pub fn variant_name(name: &str, color: Color) -> String {
+    to_dns1123_label(&format!("{}-{}", name, color))
+}
+
+/// Returns the color to deploy the desired version as: the inactive one,
+/// or blue for the first deployment.
+pub fn next_color(actor: &Actor) -> Color {
+    actor.active_color().map_or(Color::Blue, Color::other)
+}
+
+/// Returns the selector of the Service of the actor, which also selects the
+/// active color when the actor is deployed blue-green, so that the traffic
+/// only switches once the other color is ready.
+pub fn active_selector(actor: &Actor) -> BTreeMap<String, String> {
+    let mut selector = BTreeMap::from([(CHARACTER_KEY.to_string(), actor.name_any())]);
+    if let Some(color) = actor.active_color().filter(|_| actor.deployment_strategy() == DeploymentStrategy::BlueGreen) {
+        selector.insert(COLOR_KEY.into(), color.to_string());
+    }
+    selector
+}
+
+/// Returns the color to deploy the desired version as. It is the active color
+/// if its Deployment is already up to date, so that the traffic stays there,
+/// otherwise the inactive one, or blue for the first deployment.
+pub async fn target_color(client: &Client, actor: &Actor, expected_hash: &str) -> Result<Color> {
+    let Some(active) = actor.active_color() else { return Ok(Color::Blue) };
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+
+    let name = variant_name(&actor.name_any(), active);
+    let deployment = deployment::get_opt(client, &namespace, &name).await?;
+    if deployment.is_some_and(|deployment| deployment::up_to_date(&deployment, expected_hash)) {
+        return Ok(active);
+    }
+    Ok(next_color(actor))
+}
+
+/// Turn the Deployment of the actor into the one of the color: named after
+/// it, labelled with it, and selecting its pods by it.
+pub fn paint(deployment: &mut Deployment, color: Color) {
+    deployment.metadata.name = Some(variant_name(&deployment.name_any(), color));
+    deployment.labels_mut().insert(COLOR_KEY.into(), color.to_string());
+
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.selector.match_labels.get_or_insert_with(Default::default).insert(COLOR_KEY.into(), color.to_string());
+        let metadata = spec.template.metadata.get_or_insert_with(Default::default);
+        metadata.labels.get_or_insert_with(Default::default).insert(COLOR_KEY.into(), color.to_string());
+    }
+}
+
+/// Check if the Deployment rolled out all its replicas, and they are available.
+pub fn ready(deployment: &Deployment) -> bool {
+    let (Some(spec), Some(status)) = (&deployment.spec, &deployment.status) else { return false };
+    let replicas = spec.replicas.unwrap_or(1);
+
+    status.observed_generation >= deployment.metadata.generation
+        && status.updated_replicas.unwrap_or_default() == replicas
+        && status.available_replicas.unwrap_or_default() == replicas
+}
+
+/// Switch the traffic to the color: select it by the Service if any, record
+/// it as the active color, then delete the Deployments of the other color,
+/// and of the rolling strategy if the actor was deployed with it before.
+pub async fn switch(client: &Client, actor: &Actor, color: Color) -> Result<()> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let name = actor.name_any();
+
+    let services: Api<Service> = Api::namespaced(client.clone(), &namespace);
+    if services.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        let patch = json!({ "spec": { "selector": { COLOR_KEY: color.to_string() } } });
+        services.patch(&name, &PatchParams::default(), &Patch::Merge(&patch)).await.map_err(Error::KubeError)?;
+    }
+    actor::record_active_color(client, actor, color).await?;
+    info!("Switched the traffic of Actor {} to {}", name, color);
+
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+    for previous in [variant_name(&name, color.other()), name.clone()] {
+        if deployments.get_opt(&previous).await.map_err(Error::KubeError)?.is_some() {
+            deployments.delete(&previous, &DeleteParams::default()).await.map_err(Error::KubeError)?;
+            info!("Deleted the previous Deployment: {}", previous);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+    use k8s_openapi::api::apps::v1::{DeploymentSpec, DeploymentStatus};
+
+    use super::*;
+    use crate::ext::actor::ACTIVE_COLOR_KEY;
+    use crate::options::ActorOptions;
+
+    fn actor(strategy: DeploymentStrategy, active: Option<&str>) -> Actor {
+        let mut actor = Actor::new("api", ActorSpec { name: "api".into(), ..Default::default() });
+        actor.metadata.uid = Some("uid".into());
+        actor.set_options(&ActorOptions { deployment_strategy: Some(strategy), ..Default::default() });
+        if let Some(color) = active {
+            actor.annotations_mut().insert(ACTIVE_COLOR_KEY.into(), color.into());
+        }
+        actor
+    }
+
+    #[test]
+    fn test_variant_name() {
+        assert_eq!(variant_name("api", Color::Blue), "api-blue");
+        assert_eq!(variant_name("api", Color::Green), "api-green");
+        assert_eq!(variant_name(&"a".repeat(63), Color::Green).len(), 63);
+    }
+
+    #[test]
+    fn test_next_color() {
+        assert_eq!(next_color(&actor(DeploymentStrategy::BlueGreen, None)), Color::Blue);
+        assert_eq!(next_color(&actor(DeploymentStrategy::BlueGreen, Some("blue"))), Color::Green);
+        assert_eq!(next_color(&actor(DeploymentStrategy::BlueGreen, Some("green"))), Color::Blue);
+    }
+
+    #[test]
+    fn test_active_selector() {
+        let selector = |actor: &Actor| {
+            active_selector(actor).into_iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(selector(&actor(DeploymentStrategy::BlueGreen, None)), vec!["amphitheatre.app/character=api"]);
+        assert_eq!(
+            selector(&actor(DeploymentStrategy::BlueGreen, Some("green"))),
+            vec!["amphitheatre.app/character=api", "amphitheatre.app/color=green"]
+        );
+        assert_eq!(
+            selector(&actor(DeploymentStrategy::Rolling, Some("green"))),
+            vec!["amphitheatre.app/character=api"]
+        );
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut deployment = deployment::new(&actor(DeploymentStrategy::BlueGreen, None), Default::default()).unwrap();
+        paint(&mut deployment, Color::Green);
+
+        assert_eq!(deployment.name_any(), "api-green");
+        assert_eq!(deployment.labels()[COLOR_KEY], "green");
+
+        let spec = deployment.spec.unwrap();
+        assert_eq!(spec.selector.match_labels.unwrap()[COLOR_KEY], "green");
+        assert_eq!(spec.template.metadata.unwrap().labels.unwrap()[COLOR_KEY], "green");
+    }
+
+    #[test]
+    fn test_ready() {
+        let deployment = |updated: i32, available: i32| Deployment {
+            spec: Some(DeploymentSpec { replicas: Some(2), ..Default::default() }),
+            status: Some(DeploymentStatus {
+                updated_replicas: Some(updated),
+                available_replicas: Some(available),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(ready(&deployment(2, 2)));
+        assert!(!ready(&deployment(2, 1)));
+        assert!(!ready(&deployment(1, 2)));
+        assert!(!ready(&Deployment::default()));
+    }
+}
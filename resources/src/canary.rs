@@ -0,0 +1,153 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::apps::v1::Deployment;
+use kube::api::DeleteParams;
+use kube::{Api, Client, ResourceExt};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::error::{Error, Result};
+use super::{hash, to_dns1123_label, LAST_APPLIED_HASH_KEY};
+
+const TRACK_KEY: &str = "amphitheatre.app/track";
+
+/// The canary rollout of an actor: the share of the traffic in percent the new
+/// version receives, next to the stable one, and the weights of the following
+/// steps of the rollout. Without a mesh, the traffic is split by the replicas,
+/// as the Service balances across the pods of both versions.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct CanaryConfig {
+    pub weight: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<i32>,
+}
+
+impl CanaryConfig {
+    /// Returns the replicas of the canary and of the stable version out of the
+    /// total, approximating the weight. The canary gets a pod as soon as its
+    /// weight is positive, and the stable version keeps one until the weight
+    /// reaches 100, as long as there are enough replicas for both.
+    pub fn replica_split(&self, total: i32) -> (i32, i32) {
+        let total = total.max(0);
+        let weight = self.weight.clamp(0, 100);
+
+        let mut canary = (total * weight + 50) / 100;
+        if weight > 0 {
+            canary = canary.max(1);
+        }
+        if weight < 100 {
+            canary = canary.min(total - 1);
+        }
+        let canary = canary.clamp(0, total);
+
+        (canary, total - canary)
+    }
+}
+
+/// Returns the name of the Deployment of the canary, like `api-canary`.
+pub fn canary_name(name: &str) -> String {
+    to_dns1123_label(&format!("{}-canary", name))
+}
+
+/// Turn the Deployment of the actor into its canary: named after it, labelled
+/// as the canary track, and sized to its share of the replicas. It keeps the
+/// labels of the actor, so the Service balances across both versions.
+/// Returns the expected hash of the canary, which also changes with its share.
+pub fn paint(deployment: &mut Deployment, replicas: i32, expected_hash: &str) -> Result<String> {
+    let expected_hash = hash(&(expected_hash, replicas))?;
+    deployment.metadata.name = Some(canary_name(&deployment.name_any()));
+    deployment.labels_mut().insert(TRACK_KEY.into(), "canary".into());
+    deployment.annotations_mut().insert(LAST_APPLIED_HASH_KEY.into(), expected_hash.clone());
+
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.replicas = Some(replicas);
+        spec.selector.match_labels.get_or_insert_with(Default::default).insert(TRACK_KEY.into(), "canary".into());
+        let metadata = spec.template.metadata.get_or_insert_with(Default::default);
+        metadata.labels.get_or_insert_with(Default::default).insert(TRACK_KEY.into(), "canary".into());
+    }
+
+    Ok(expected_hash)
+}
+
+/// Delete the Deployment of the canary once the new version is promoted to
+/// the Deployment of the actor, or the canary is called off.
+pub async fn finish(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let name = canary_name(&actor.name_any());
+
+    let api: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::default()).await.map_err(Error::KubeError)?;
+        info!("Deleted the canary Deployment: {}", name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+
+    use super::*;
+    use crate::deployment;
+
+    fn canary(weight: i32) -> CanaryConfig {
+        CanaryConfig { weight, steps: vec![] }
+    }
+
+    #[test]
+    fn test_replica_split() {
+        assert_eq!(canary(10).replica_split(10), (1, 9));
+        assert_eq!(canary(50).replica_split(10), (5, 5));
+        assert_eq!(canary(50).replica_split(4), (2, 2));
+    }
+
+    #[test]
+    fn test_replica_split_bounds() {
+        assert_eq!(canary(10).replica_split(3), (1, 2));
+        assert_eq!(canary(95).replica_split(10), (9, 1));
+        assert_eq!(canary(100).replica_split(3), (3, 0));
+        assert_eq!(canary(0).replica_split(3), (0, 3));
+        assert_eq!(canary(50).replica_split(1), (0, 1));
+        assert_eq!(canary(50).replica_split(0), (0, 0));
+    }
+
+    #[test]
+    fn test_config_from_json() {
+        let config: CanaryConfig = serde_json::from_str(r#"{"weight": 10, "steps": [25, 50]}"#).unwrap();
+        assert_eq!(config, CanaryConfig { weight: 10, steps: vec![25, 50] });
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut actor = Actor::new("api", ActorSpec { name: "api".into(), ..Default::default() });
+        actor.metadata.uid = Some("uid".into());
+
+        let mut deployment = deployment::new(&actor, Default::default()).unwrap();
+        let expected_hash = paint(&mut deployment, 2, "abc").unwrap();
+        assert_ne!(expected_hash, paint(&mut deployment.clone(), 3, "abc").unwrap());
+
+        assert_eq!(deployment.name_any(), "api-canary");
+        assert_eq!(deployment.labels()[TRACK_KEY], "canary");
+        assert_eq!(deployment.labels()["amphitheatre.app/character"], "api");
+        assert!(deployment::up_to_date(&deployment, &expected_hash));
+
+        let spec = deployment.spec.unwrap();
+        assert_eq!(spec.replicas, Some(2));
+        assert_eq!(spec.selector.match_labels.unwrap()[TRACK_KEY], "canary");
+        assert_eq!(spec.template.metadata.unwrap().labels.unwrap()[TRACK_KEY], "canary");
+    }
+}
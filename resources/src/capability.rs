@@ -0,0 +1,72 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::{Api, Client};
+
+use super::error::{Error, Result};
+
+/// A build subsystem that has to be available in the cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Dockerfile builds run Kaniko in a Job, which needs nothing else installed.
+    Kaniko,
+    /// Buildpacks builds are delegated to kpack.
+    Buildpacks,
+}
+
+impl Capability {
+    /// The CustomResourceDefinitions the capability depends on.
+    fn crds(&self) -> &'static [&'static str] {
+        match self {
+            Capability::Kaniko => &[],
+            Capability::Buildpacks => &["images.kpack.io", "clusterstores.kpack.io", "clusterbuilders.kpack.io"],
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Kaniko => write!(f, "Kaniko"),
+            Capability::Buildpacks => write!(f, "Buildpacks (kpack)"),
+        }
+    }
+}
+
+/// Check if the capability is installed in the cluster.
+pub async fn installed(client: &Client, capability: Capability) -> Result<bool> {
+    let api: Api<CustomResourceDefinition> = Api::all(client.clone());
+    for name in capability.crds() {
+        if api.get_opt(name).await.map_err(Error::KubeError)?.is_none() {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Ensure all the capabilities are installed, failing with
+/// [`Error::MissingCapability`] for the first one that is not.
+pub async fn ensure(client: &Client, capabilities: &[Capability]) -> Result<()> {
+    for capability in capabilities {
+        if !installed(client, *capability).await? {
+            return Err(Error::MissingCapability(capability.to_string()));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{Patch, PatchParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+use tracing::info;
+
+use super::error::{Error, Result};
+use super::ext::ActorExt;
+
+pub async fn apply(client: &Client, actor: &Actor) -> Result<Option<ConfigMap>> {
+    let resource = match new_env_configmap(actor) {
+        Some(resource) => resource,
+        None => return Ok(None),
+    };
+
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = resource.name_any();
+
+    let params = &PatchParams::apply("amp-controllers").force();
+    let config_map = api.patch(&name, params, &Patch::Apply(&resource)).await.map_err(Error::KubeError)?;
+
+    info!("Applied ConfigMap: {}", config_map.name_any());
+    Ok(Some(config_map))
+}
+
+/// Build a ConfigMap holding the resolved environment of the actor, for
+/// troubleshooting which value of a variable wins. Only the literal values
+/// are materialized, the variables sourced from secrets or config maps are
+/// left out. Returns `None` unless materializing the env is enabled.
+pub fn new_env_configmap(actor: &Actor) -> Option<ConfigMap> {
+    if !actor.materialize_env() {
+        return None;
+    }
+
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = BTreeMap::from([
+        ("amphitheatre.app/character".into(), actor.name_any()),
+        ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
+    ]);
+    let metadata = ObjectMeta {
+        name: Some(actor.env_configmap_name()),
+        owner_references: Some(vec![owner_reference]),
+        labels: Some(labels),
+        ..Default::default()
+    };
+
+    let data = actor
+        .environments()
+        .0
+        .into_iter()
+        .filter(|var| var.value_from.is_none())
+        .map(|var| (var.name, var.value.unwrap_or_default()))
+        .collect();
+
+    Some(ConfigMap { metadata, data: Some(data), ..Default::default() })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use amp_common::resource::{ActorSpec, CharacterSpec};
+    use amp_common::schema::Deploy;
+
+    use super::*;
+    use crate::options::ActorOptions;
+
+    fn actor(enabled: bool) -> Actor {
+        let env = HashMap::from([("PORT".to_string(), "8080".to_string()), ("MODE".to_string(), "dev".to_string())]);
+        let deploy = Deploy { env: Some(env), ..Default::default() };
+        let spec = ActorSpec {
+            name: "api".into(),
+            character: CharacterSpec { deploy: Some(deploy), ..Default::default() },
+            ..Default::default()
+        };
+
+        let mut actor = Actor::new("api", spec);
+        actor.metadata.uid = Some("uid".into());
+        actor.set_options(&ActorOptions { materialize_env: enabled, ..Default::default() });
+        actor
+    }
+
+    #[test]
+    fn test_env_configmap_disabled() {
+        assert!(new_env_configmap(&actor(false)).is_none());
+    }
+
+    #[test]
+    fn test_env_configmap_data() {
+        let mut actor = actor(true);
+        let env = r#"{"MODE": "prod", "PASSWORD": {"secretKeyRef": {"name": "db", "key": "password"}}}"#;
+        actor.set_options(&ActorOptions {
+            env: serde_json::from_str(env).unwrap(),
+            materialize_env: true,
+            ..Default::default()
+        });
+
+        let config_map = new_env_configmap(&actor).unwrap();
+        assert_eq!(config_map.metadata.name, Some("api-env".into()));
+        assert_eq!(
+            config_map.data,
+            Some(BTreeMap::from([("MODE".into(), "prod".into()), ("PORT".into(), "8080".into())]))
+        );
+    }
+}
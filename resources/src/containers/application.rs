@@ -12,33 +12,76 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use amp_common::resource::ActorSpec;
-use k8s_openapi::api::core::v1::Container;
+use amp_common::resource::{Actor, ActorSpec};
+use k8s_openapi::api::core::v1::{Container, ContainerPort, PodSpec};
+
+use super::artifacts;
+use crate::ext::{ActorExt, ActorSpecExt};
+use crate::ports::name_container_ports;
 
 /// Build and return the container spec for the actor
 pub fn container(spec: &ActorSpec) -> Container {
-    let mut environments = Some(vec![]);
     let mut container_ports = Some(vec![]);
 
-    // extract the env and ports from the deploy spec
+    // extract the ports from the deploy spec
     if let Some(deploy) = &spec.character.deploy {
-        environments.clone_from(&deploy.env());
         container_ports = deploy.container_ports();
     }
+    if let Some(ports) = container_ports.as_mut() {
+        name_container_ports(ports);
+    }
 
     Container {
         name: spec.name.clone(),
         image: Some(spec.image.clone()),
         image_pull_policy: Some("Always".into()),
-        env: environments,
+        args: spec.command_args().unwrap_or_default(),
+        env: Some(spec.resolve_env().0),
         ports: container_ports,
         ..Default::default()
     }
 }
 
+/// Build and return the pod spec running the actor's container
+pub fn pod(actor: &Actor) -> PodSpec {
+    let mut container = container(&actor.spec);
+    container.name = actor.container_name();
+    container.image = Some(actor.docker_ref());
+    container.image_pull_policy = Some(actor.image_pull_policy());
+    container.env = Some(actor.environments().0);
+    container.resources = actor.resource_requirements();
+    container.liveness_probe = actor.liveness_probe();
+    container.readiness_probe = actor.readiness_probe();
+    include_probe_ports(&mut container, &actor.probe_ports());
+
+    let image_pull_secrets = Some(actor.image_pull_secrets()).filter(|secrets| !secrets.is_empty());
+    let mut pod = PodSpec { containers: vec![container], image_pull_secrets, ..Default::default() };
+
+    // Serve the artifacts of the build by the sidecars, if any
+    artifacts::apply(&mut pod, &actor.shared_artifacts());
+    pod
+}
+
+/// Add the ports targeted by the probes that are not declared by the services,
+/// so that the probes are not refused the connection.
+fn include_probe_ports(container: &mut Container, probe_ports: &[i32]) {
+    let ports = container.ports.get_or_insert_with(Vec::new);
+    for port in probe_ports {
+        if !ports.iter().any(|declared| declared.container_port == *port) {
+            ports.push(ContainerPort { container_port: *port, ..Default::default() });
+        }
+    }
+    name_container_ports(ports);
+}
+
 #[cfg(test)]
 mod tests {
+    use amp_common::schema::{Deploy, Port, Service};
+    use k8s_openapi::api::core::v1::LocalObjectReference;
+
     use super::*;
+    use crate::containers::EnvSource;
+    use crate::options::ActorOptions;
 
     #[test]
     fn test_application_container() {
@@ -50,4 +93,79 @@ mod tests {
         assert_eq!(container.image, Some("test".into()));
         assert_eq!(container.image_pull_policy, Some("Always".into()));
     }
+
+    #[test]
+    fn test_application_container_args() {
+        let mut spec = ActorSpec { name: "test".into(), image: "test".into(), ..Default::default() };
+        spec.character.deploy = Some(Deploy { command: Some(r#"myapp --name "a b""#.into()), ..Default::default() });
+
+        let container = container(&spec);
+        assert_eq!(container.command, None);
+        assert_eq!(container.args, Some(vec!["myapp".into(), "--name".into(), "a b".into()]));
+    }
+
+    #[test]
+    fn test_pod_image_pull_secrets() {
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+        assert_eq!(pod(&actor).image_pull_secrets, None);
+
+        actor.set_options(&ActorOptions { image_pull_secrets: vec!["registry".into()], ..Default::default() });
+        let secrets = pod(&actor).image_pull_secrets.unwrap();
+        assert_eq!(secrets, vec![LocalObjectReference { name: "registry".into() }]);
+    }
+
+    #[test]
+    fn test_pod_includes_probe_ports() {
+        let ports = vec![Port { port: 8080, ..Default::default() }];
+        let services = vec![Service { ports, ..Default::default() }];
+        let mut spec = ActorSpec { name: "test".into(), image: "test".into(), ..Default::default() };
+        spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+
+        let mut actor = Actor::new("test", spec);
+        actor.set_options(&ActorOptions {
+            liveness_probe: serde_json::from_str(r#"{"httpGet":{"port":9090}}"#).unwrap(),
+            readiness_probe: serde_json::from_str(r#"{"tcpSocket":{"port":8080}}"#).unwrap(),
+            ..Default::default()
+        });
+
+        let pod = pod(&actor);
+        let ports: Vec<_> = pod.containers[0]
+            .ports
+            .iter()
+            .flatten()
+            .map(|port| (port.container_port, port.name.clone().unwrap()))
+            .collect();
+        assert_eq!(ports, vec![(8080, "tcp-8080".into()), (9090, "tcp-9090".into())]);
+    }
+
+    #[test]
+    fn test_pod_is_stable() {
+        let pod = |names: &[&str]| {
+            let env = names.iter().map(|name| (name.to_string(), "value".to_string())).collect();
+            let mut spec = ActorSpec { name: "test".into(), image: "test".into(), ..Default::default() };
+            spec.character.deploy = Some(Deploy { env: Some(env), ..Default::default() });
+            serde_json::to_vec(&super::pod(&Actor::new("test", spec))).unwrap()
+        };
+
+        let names = ["E", "B", "D", "A", "C", "F", "H", "G"];
+        assert_eq!(pod(&names), pod(&names));
+        let reversed: Vec<_> = names.iter().rev().copied().collect();
+        assert_eq!(pod(&names), pod(&reversed));
+    }
+
+    #[test]
+    fn test_pod_env_is_sorted() {
+        let env = ["E", "B", "D"].iter().map(|name| (name.to_string(), "value".to_string())).collect();
+        let mut spec = ActorSpec { name: "test".into(), image: "test".into(), ..Default::default() };
+        spec.character.deploy = Some(Deploy { env: Some(env), ..Default::default() });
+
+        let mut actor = Actor::new("test", spec);
+        let env = ["C", "A"].iter().map(|name| (name.to_string(), EnvSource::Value("value".into()))).collect();
+        actor.set_options(&ActorOptions { env, ..Default::default() });
+
+        let pod = pod(&actor);
+        let names: Vec<_> = pod.containers[0].env.iter().flatten().map(|var| var.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B", "C", "D", "E"]);
+    }
 }
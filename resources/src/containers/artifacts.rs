@@ -0,0 +1,126 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use k8s_openapi::api::core::v1::{Container, PodSpec, Volume, VolumeMount};
+use serde::{Deserialize, Serialize};
+
+const ARTIFACTS_DIR: &str = "/amp-artifacts";
+
+/// Artifacts produced by the build, like the files of a static site, served
+/// by a sidecar of the actor. The build runs in another pod, so they travel in
+/// the built image: an init container copies them from `buildPath` in the image
+/// to a volume the `sidecar` mounts at `servePath`. The image needs `cp`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedArtifact {
+    pub name: String,
+    pub build_path: String,
+    pub serve_path: String,
+    pub sidecar: Container,
+}
+
+impl SharedArtifact {
+    /// Returns the name of the emptyDir volume shared with the sidecar.
+    pub fn volume_name(&self) -> String {
+        format!("artifacts-{}", self.name)
+    }
+}
+
+/// Wire the shared artifacts into the pod running the main container: a volume,
+/// an init container copying them from the image, and a sidecar for each one.
+pub fn apply(pod: &mut PodSpec, artifacts: &[SharedArtifact]) {
+    let Some(main) = pod.containers.first().cloned() else { return };
+
+    for artifact in artifacts {
+        let volume_name = artifact.volume_name();
+        let copy_path = format!("{}/{}", ARTIFACTS_DIR, artifact.name);
+
+        pod.volumes.get_or_insert_with(Vec::new).push(Volume {
+            name: volume_name.clone(),
+            empty_dir: Some(Default::default()),
+            ..Default::default()
+        });
+
+        pod.init_containers.get_or_insert_with(Vec::new).push(Container {
+            name: format!("copy-{}", artifact.name),
+            image: main.image.clone(),
+            image_pull_policy: main.image_pull_policy.clone(),
+            command: Some(vec!["cp".into(), "-a".into(), format!("{}/.", artifact.build_path), copy_path.clone()]),
+            volume_mounts: Some(vec![VolumeMount {
+                name: volume_name.clone(),
+                mount_path: copy_path,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+
+        let mut sidecar = artifact.sidecar.clone();
+        sidecar.volume_mounts.get_or_insert_with(Vec::new).push(VolumeMount {
+            name: volume_name,
+            mount_path: artifact.serve_path.clone(),
+            read_only: Some(true),
+            ..Default::default()
+        });
+        pod.containers.push(sidecar);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_artifacts() {
+        let main =
+            Container { name: "site".into(), image: Some("registry.local/site@sha256:1".into()), ..Default::default() };
+        let mut pod = PodSpec { containers: vec![main], ..Default::default() };
+        let artifact = SharedArtifact {
+            name: "public".into(),
+            build_path: "/app/dist".into(),
+            serve_path: "/usr/share/nginx/html".into(),
+            sidecar: Container { name: "nginx".into(), image: Some("nginx:stable".into()), ..Default::default() },
+        };
+
+        apply(&mut pod, &[artifact]);
+
+        let volumes = pod.volumes.unwrap();
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].name, "artifacts-public");
+        assert!(volumes[0].empty_dir.is_some());
+
+        let init = &pod.init_containers.unwrap()[0];
+        assert_eq!(init.name, "copy-public");
+        assert_eq!(init.image, Some("registry.local/site@sha256:1".into()));
+        assert_eq!(
+            init.command,
+            Some(vec!["cp".into(), "-a".into(), "/app/dist/.".into(), "/amp-artifacts/public".into()])
+        );
+        assert_eq!(init.volume_mounts.as_ref().unwrap()[0].mount_path, "/amp-artifacts/public");
+
+        assert_eq!(pod.containers.len(), 2);
+        assert!(pod.containers[0].volume_mounts.is_none());
+        let mount = &pod.containers[1].volume_mounts.as_ref().unwrap()[0];
+        assert_eq!(pod.containers[1].name, "nginx");
+        assert_eq!((mount.name.as_str(), mount.mount_path.as_str()), ("artifacts-public", "/usr/share/nginx/html"));
+        assert_eq!(mount.read_only, Some(true));
+    }
+
+    #[test]
+    fn test_without_shared_artifacts() {
+        let mut pod = PodSpec { containers: vec![Container::default()], ..Default::default() };
+        apply(&mut pod, &[]);
+
+        assert_eq!(pod, PodSpec { containers: vec![Container::default()], ..Default::default() });
+    }
+}
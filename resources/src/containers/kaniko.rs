@@ -12,16 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::path::PathBuf;
+use std::path::Path;
 
-use super::{docker_config_volume, git_sync, syncer, workspace_mount, workspace_volume, WORKSPACE_DIR};
+use super::{
+    build_secrets_mount, build_secrets_volume, docker_config_volume, git_sync, pre_build, syncer, workspace_mount,
+    workspace_volume, WORKSPACE_DIR,
+};
 use crate::args;
 use crate::error::Result;
+use crate::ext::{ActorExt, ActorSpecExt, BuildSpecExt};
 
 use amp_common::resource::{Actor, ActorSpec};
 use k8s_openapi::api::core::v1::{Container, PodSpec, Volume, VolumeMount};
 
 const DEFAULT_KANIKO_IMAGE: &str = "gcr.io/kaniko-project/executor:v1.15.0";
+const TERMINATION_MESSAGE_PATH: &str = "/dev/termination-log";
 
 pub fn pod(actor: &Actor) -> Result<PodSpec> {
     // Choose the syncer for source code synchronization
@@ -34,11 +39,53 @@ pub fn pod(actor: &Actor) -> Result<PodSpec> {
         volumes.push(git_source_volume());
     }
 
+    // Run the pre-build command after syncing the sources, if any
+    let mut init_containers = vec![syncer];
+    init_containers.extend(pre_build::container(actor));
+
+    // Push the additional tags along with the primary destination
+    let mut builder = container(&actor.spec);
+    let destinations = actor.resolved_post_tags().into_iter().map(|tag| format!("--destination={}", tag));
+    builder.args.get_or_insert_with(Vec::new).extend(destinations);
+
+    // Build the stage of a multi-stage Dockerfile instead of the last one
+    if let Some(target) = actor.dockerfile_target() {
+        builder.args.get_or_insert_with(Vec::new).push(format!("--target={}", target));
+    }
+
+    // Push the cached layers to the cache image, or build without cache when it is disabled
+    if let Some(cache) = actor.build_cache() {
+        let args = builder.args.get_or_insert_with(Vec::new);
+        args.retain(|arg| !arg.starts_with("--cache="));
+        args.push(format!("--cache={}", cache.enabled));
+        args.extend(actor.cache_image().map(|image| format!("--cache-repo={}", image)));
+    }
+
+    // Write the digest of the pushed image to the termination message, to be recorded
+    builder.args.get_or_insert_with(Vec::new).push(format!("--digest-file={}", TERMINATION_MESSAGE_PATH));
+
+    // Mount the build secrets, they are read from files instead of the build env
+    if let Some(volume) = build_secrets_volume(&actor.build_secrets()?) {
+        volumes.push(volume);
+        builder.volume_mounts.get_or_insert_with(Vec::new).push(build_secrets_mount());
+    }
+
+    // Kaniko does not create multi-arch manifests, it builds the first platform only,
+    // See https://github.com/GoogleContainerTools/kaniko#flag---custom-platform
+    if !actor.platforms().is_empty() {
+        let platform = &actor.platforms_or_default()[0];
+        builder.args.get_or_insert_with(Vec::new).push(format!("--custom-platform={}", platform));
+    }
+
+    // Pull the builder image from a private registry with the image pull secrets
+    let image_pull_secrets = Some(actor.image_pull_secrets()).filter(|secrets| !secrets.is_empty());
+
     Ok(PodSpec {
-        init_containers: Some(vec![syncer]),
-        containers: vec![container(&actor.spec)],
+        init_containers: Some(init_containers),
+        containers: vec![builder],
         restart_policy: Some("Never".into()),
         volumes: Some(volumes),
+        image_pull_secrets,
         ..Default::default()
     })
 }
@@ -48,35 +95,25 @@ pub fn container(spec: &ActorSpec) -> Container {
     let build = spec.character.build.clone().unwrap_or_default();
 
     // Set the working directory to context.
-    let mut workdir = PathBuf::from(WORKSPACE_DIR);
-    if let Some(context) = &build.context {
-        workdir.push(context);
-    }
+    let manifest = spec.normalized_manifest_path();
+    let workdir = build.resolved_context(&manifest, Path::new(WORKSPACE_DIR));
+    let context = workdir.to_string_lossy();
 
     // Parse the arguments for the container
     let destination = spec.image.clone();
     let mut arguments = vec![
-        ("context", workdir.to_str().unwrap()),
+        ("context", context.as_ref()),
         ("destination", destination.as_str()),
         ("verbosity", "info"),
         ("cache", "true"),
     ];
 
-    if let Some(config) = &build.dockerfile {
-        arguments.push(("dockerfile", &config.dockerfile));
+    // The Dockerfile is relative to the context, and never escapes it.
+    let dockerfile = build.dockerfile_path(&workdir).map(|path| path.to_string_lossy().into_owned());
+    if let Some(path) = &dockerfile {
+        arguments.push(("dockerfile", path.as_str()));
     }
 
-    // TODO: Kaniko: Add support for multiple platforms in the future.
-    // While Kaniko itself currently does not support creating multi-arch manifests,
-    // See https://github.com/GoogleContainerTools/kaniko#creating-multi-arch-container-manifests-using-kaniko-and-manifest-tool
-    // and https://github.com/GoogleContainerTools/kaniko#flag---custom-platform
-    //
-    // let custom_platform: String;
-    // if let Some(platforms) = &build.platforms {
-    //     custom_platform = platforms.join(",");
-    //     arguments.push(("custom-platform", &custom_platform));
-    // }
-
     let mut arguments = args(&arguments, 2);
     if let Some(args) = &build.args {
         arguments.extend(args.clone());
@@ -87,7 +124,7 @@ pub fn container(spec: &ActorSpec) -> Container {
         image: Some(DEFAULT_KANIKO_IMAGE.into()),
         image_pull_policy: Some("IfNotPresent".into()),
         args: Some(arguments),
-        env: build.env(),
+        env: build.sorted_env(),
         volume_mounts: Some(vec![docker_config_mount(), workspace_mount()]),
         ..Default::default()
     }
@@ -105,7 +142,11 @@ fn git_source_volume() -> Volume {
 
 #[cfg(test)]
 mod tests {
+    use amp_common::schema::GitReference;
+
     use super::*;
+    use crate::ext::{BuildCache, BuildSecret};
+    use crate::options::ActorOptions;
 
     #[test]
     fn test_kaniko_container() {
@@ -118,6 +159,84 @@ mod tests {
         assert_eq!(container.image_pull_policy, Some("IfNotPresent".into()));
     }
 
+    #[test]
+    fn test_kaniko_pod_with_target() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), source, ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+        actor.set_options(&ActorOptions { dockerfile_target: Some("runtime".into()), ..Default::default() });
+
+        let pod = pod(&actor).unwrap();
+        assert!(pod.containers[0].args.as_ref().unwrap().contains(&"--target=runtime".to_string()));
+    }
+
+    #[test]
+    fn test_kaniko_pod_with_platforms() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), source, ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+
+        let pod = pod(&actor).unwrap();
+        assert!(!pod.containers[0].args.as_ref().unwrap().iter().any(|arg| arg.starts_with("--custom-platform")));
+
+        let platforms = vec!["linux/arm64".into(), "linux/amd64".into()];
+        actor.set_options(&ActorOptions { platforms, ..Default::default() });
+        let pod = pod(&actor).unwrap();
+        assert!(pod.containers[0].args.as_ref().unwrap().contains(&"--custom-platform=linux/arm64".to_string()));
+    }
+
+    #[test]
+    fn test_kaniko_pod_writes_digest() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), source, ..Default::default() };
+
+        let pod = pod(&Actor::new("test", spec)).unwrap();
+        assert!(pod.containers[0].args.as_ref().unwrap().contains(&"--digest-file=/dev/termination-log".to_string()));
+    }
+
+    #[test]
+    fn test_kaniko_pod_with_cache() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec =
+            ActorSpec { name: "test".into(), image: "registry.local/api:v1".into(), source, ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+
+        let cache = |enabled| ActorOptions {
+            build_cache: Some(BuildCache { enabled, ..Default::default() }),
+            ..Default::default()
+        };
+        actor.set_options(&cache(true));
+        let args = pod(&actor).unwrap().containers[0].args.clone().unwrap();
+        assert!(args.contains(&"--cache=true".to_string()));
+        assert!(args.contains(&"--cache-repo=registry.local/api-cache".to_string()));
+
+        actor.set_options(&cache(false));
+        let args = pod(&actor).unwrap().containers[0].args.clone().unwrap();
+        assert!(args.contains(&"--cache=false".to_string()));
+        assert!(!args.iter().any(|arg| arg == "--cache=true" || arg.starts_with("--cache-repo")));
+    }
+
+    #[test]
+    fn test_kaniko_pod_with_secrets() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), source, ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+
+        let secret = |name: &str| BuildSecret { id: "npm".into(), name: name.into(), key: "token".into() };
+        actor.set_options(&ActorOptions { build_secrets: vec![secret("npm-token")], ..Default::default() });
+        let pod = pod(&actor).unwrap();
+        assert!(pod.volumes.unwrap().iter().any(|volume| volume.name == "build-secrets"));
+        assert!(pod.containers[0].volume_mounts.as_ref().unwrap().contains(&build_secrets_mount()));
+
+        actor.set_options(&ActorOptions { build_secrets: vec![secret("a"), secret("b")], ..Default::default() });
+        assert!(pod(&actor).is_err());
+    }
+
     #[test]
     fn test_docker_config_mount() {
         let mount = docker_config_mount();
@@ -16,35 +16,63 @@ use amp_common::resource::{Actor, ActorSpec};
 use k8s_openapi::api::core::v1::SecurityContext;
 use k8s_openapi::api::core::v1::{Container, EnvVar, PodSpec, VolumeMount};
 
-use super::{docker_config_volume, git_sync, syncer, workspace_mount, workspace_volume, WORKSPACE_DIR};
+use super::{
+    build_secrets_mount, build_secrets_volume, coalesce_env, docker_config_volume, git_sync, pre_build, syncer,
+    workspace_mount, workspace_volume, WORKSPACE_DIR,
+};
 use crate::args;
 
 use crate::error::Result;
+use crate::ext::{ActorExt, BuildSpecExt};
+use tracing::warn;
 
 const DEFAULT_RUN_AS_GROUP: i64 = 1000;
 const DEFAULT_RUN_AS_USER: i64 = 1001;
 
-pub fn pod(actor: &Actor) -> Result<PodSpec> {
+/// Build the pod running the lifecycle of the builder image of the actor, or
+/// of the `default_builder` of the controller if the actor does not set one.
+pub fn pod(actor: &Actor, default_builder: &str) -> Result<PodSpec> {
     // Get SecurityContext for the container
     let build = actor.spec.character.build.clone().unwrap_or_default();
-    let builder = build.buildpacks.clone().unwrap_or_default().builder;
+    let builder = build.effective_builder(default_builder);
     let security_context = security_context(&builder);
 
     // Choose the syncer for source code synchronization
     let syncer =
         if actor.spec.live { syncer::container(actor, &security_context)? } else { git_sync::container(actor) };
 
+    // Run the pre-build command after syncing the sources, if any
+    let mut init_containers = vec![syncer];
+    init_containers.extend(pre_build::container(actor));
+
+    // Restore and save the cached layers with the cache image, before the image to build
+    let mut builder = container(&actor.spec, default_builder, &security_context);
+    if let (Some(image), Some(args)) = (actor.cache_image(), builder.args.as_mut()) {
+        args.insert(args.len() - 1, format!("-cache-image={}", image));
+    }
+
+    // Mount the build secrets, they are read from files instead of the build env
+    let mut volumes = vec![workspace_volume(), docker_config_volume()];
+    if let Some(volume) = build_secrets_volume(&actor.build_secrets()?) {
+        volumes.push(volume);
+        builder.volume_mounts.get_or_insert_with(Vec::new).push(build_secrets_mount());
+    }
+
+    // Pull the builder image from a private registry with the image pull secrets
+    let image_pull_secrets = Some(actor.image_pull_secrets()).filter(|secrets| !secrets.is_empty());
+
     Ok(PodSpec {
-        init_containers: Some(vec![syncer]),
-        containers: vec![container(&actor.spec, &security_context)],
+        init_containers: Some(init_containers),
+        containers: vec![builder],
         restart_policy: Some("Never".into()),
-        volumes: Some(vec![workspace_volume(), docker_config_volume()]),
+        volumes: Some(volumes),
+        image_pull_secrets,
         ..Default::default()
     })
 }
 
 /// Build and return the container spec for the buildpacks container
-pub fn container(spec: &ActorSpec, security_context: &Option<SecurityContext>) -> Container {
+pub fn container(spec: &ActorSpec, default_builder: &str, security_context: &Option<SecurityContext>) -> Container {
     let build = spec.character.build.clone().unwrap_or_default();
 
     // Parse the arguments for the container
@@ -56,17 +84,19 @@ pub fn container(spec: &ActorSpec, security_context: &Option<SecurityContext>) -
     arguments.push(spec.image.clone());
 
     // Parse the environment variables for the container
-    let mut environment = vec![
+    let defaults = vec![
         EnvVar { name: "CNB_PLATFORM_API".into(), value: Some("0.11".into()), ..Default::default() },
         EnvVar { name: "DOCKER_CONFIG".into(), value: Some("/workspace/.docker".into()), ..Default::default() },
     ];
-    if let Some(env) = build.env() {
-        environment.extend(env)
+    let (environment, warnings) =
+        coalesce_env(&[("defaults", defaults), ("build", build.sorted_env().unwrap_or_default())]);
+    for warning in warnings {
+        warn!("{warning}");
     }
 
     Container {
         name: "builder".to_string(),
-        image: Some(build.buildpacks.unwrap_or_default().builder),
+        image: Some(build.effective_builder(default_builder)),
         command: Some(vec!["/cnb/lifecycle/creator".into()]),
         args: Some(arguments),
         env: Some(environment),
@@ -108,16 +138,20 @@ fn security_context(builder: &str) -> Option<SecurityContext> {
 
 #[cfg(test)]
 mod tests {
+    use amp_common::schema::GitReference;
+
     use super::*;
+    use crate::ext::{BuildCache, DEFAULT_BUILDER};
+    use crate::options::ActorOptions;
 
     #[test]
     fn test_lifecycle_container() {
         let spec = ActorSpec { name: "test".into(), image: "test".into(), ..Default::default() };
 
-        let container = container(&spec, &None);
+        let container = container(&spec, DEFAULT_BUILDER, &None);
 
         assert_eq!(container.name, "builder");
-        assert_eq!(container.image, Some("gcr.io/buildpacks/builder:v1".into()));
+        assert_eq!(container.image, Some(DEFAULT_BUILDER.into()));
         assert_eq!(container.command, Some(vec!["/cnb/lifecycle/creator".into()]));
         assert_eq!(container.args, Some(vec!["-app=/workspace".into(), "test".into()]));
         assert_eq!(
@@ -140,6 +174,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_lifecycle_pod_with_default_builder() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), source, ..Default::default() };
+
+        let pod = pod(&Actor::new("test", spec), "paketobuildpacks/builder:base").unwrap();
+        assert_eq!(pod.containers[0].image, Some("paketobuildpacks/builder:base".into()));
+    }
+
+    #[test]
+    fn test_lifecycle_pod_with_cache() {
+        let source =
+            Some(GitReference { repo: "https://github.com/amphitheatre-app/api.git".into(), ..Default::default() });
+        let spec =
+            ActorSpec { name: "test".into(), image: "registry.local/api:v1".into(), source, ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+        let build_cache = Some(BuildCache { enabled: true, ..Default::default() });
+        actor.set_options(&ActorOptions { build_cache, ..Default::default() });
+
+        let args = pod(&actor, DEFAULT_BUILDER).unwrap().containers[0].args.clone().unwrap();
+        assert_eq!(args, vec!["-app=/workspace", "-cache-image=registry.local/api-cache", "registry.local/api:v1"]);
+    }
+
     #[test]
     fn test_docker_config_mount() {
         let mount = docker_config_mount();
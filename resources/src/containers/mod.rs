@@ -13,14 +13,24 @@
 // limitations under the License.
 
 pub mod application;
+pub mod artifacts;
 pub mod devcontainer;
 pub mod git_sync;
 pub mod kaniko;
 pub mod lifecycle;
+pub mod pre_build;
 pub mod syncer;
 
-use k8s_openapi::api::core::v1::{KeyToPath, SecretVolumeSource, Volume, VolumeMount};
+use k8s_openapi::api::core::v1::{
+    ConfigMapKeySelector, EnvVar, EnvVarSource, KeyToPath, ProjectedVolumeSource, SecretKeySelector, SecretProjection,
+    SecretVolumeSource, Volume, VolumeMount, VolumeProjection,
+};
+use serde::{Deserialize, Serialize};
 
+use crate::error::{Error, Result};
+use crate::ext::BuildSecret;
+
+const BUILD_SECRETS_DIR: &str = "/run/secrets";
 const WORKSPACE_DIR: &str = "/workspace";
 
 /// volume for /workspace based on k8s emptyDir
@@ -53,6 +63,134 @@ pub fn docker_config_volume() -> Volume {
     }
 }
 
+/// volume projecting the keys of the build secrets as files named after their ids
+pub fn build_secrets_volume(secrets: &[BuildSecret]) -> Option<Volume> {
+    if secrets.is_empty() {
+        return None;
+    }
+
+    let sources = secrets.iter().map(|secret| VolumeProjection {
+        secret: Some(SecretProjection {
+            name: secret.name.clone(),
+            items: Some(vec![KeyToPath { key: secret.key.clone(), path: secret.id.clone(), ..Default::default() }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+
+    Some(Volume {
+        name: "build-secrets".to_string(),
+        projected: Some(ProjectedVolumeSource { sources: Some(sources.collect()), ..Default::default() }),
+        ..Default::default()
+    })
+}
+
+/// volume mount for the build secrets, at /run/secrets like BuildKit
+#[inline]
+pub fn build_secrets_mount() -> VolumeMount {
+    VolumeMount {
+        name: "build-secrets".to_string(),
+        mount_path: BUILD_SECRETS_DIR.to_string(),
+        read_only: Some(true),
+        ..Default::default()
+    }
+}
+
+/// Coalesce the environment variables from the named sources into a single
+/// list sorted by name, where the later sources take precedence, and a warning
+/// is returned for each shadowed variable. The sorting keeps the rendered pod
+/// stable whatever the order of the sources.
+pub fn coalesce_env(sources: &[(&str, Vec<EnvVar>)]) -> (Vec<EnvVar>, Vec<String>) {
+    let mut environments: Vec<(&str, EnvVar)> = vec![];
+    let mut warnings = vec![];
+
+    for (source, vars) in sources {
+        for var in vars {
+            match environments.iter_mut().find(|(_, existing)| existing.name == var.name) {
+                Some((origin, existing)) => {
+                    warnings.push(format!("{} from {} shadows the one from {}", var.name, source, origin));
+                    *origin = source;
+                    *existing = var.clone();
+                }
+                None => environments.push((source, var.clone())),
+            }
+        }
+    }
+
+    environments.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+    (environments.into_iter().map(|(_, var)| var).collect(), warnings)
+}
+
+/// A key of a secret or a config map.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct KeyRef {
+    pub name: String,
+    pub key: String,
+}
+
+/// The value of an environment variable, either a literal string, or a
+/// reference like `{"secretKeyRef": {"name": "db", "key": "password"}}`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EnvSource {
+    Value(String),
+    SecretKeyRef {
+        #[serde(rename = "secretKeyRef")]
+        secret_key_ref: KeyRef,
+    },
+    ConfigMapKeyRef {
+        #[serde(rename = "configMapKeyRef")]
+        config_map_key_ref: KeyRef,
+    },
+}
+
+impl EnvSource {
+    /// Build the environment variable, with its value from the reference if
+    /// any. References must name both the object and the key.
+    pub fn to_env_var(&self, name: &str) -> Result<EnvVar> {
+        let value_from = match self {
+            EnvSource::Value(value) => {
+                return Ok(EnvVar { name: name.into(), value: Some(value.clone()), ..Default::default() })
+            }
+            EnvSource::SecretKeyRef { secret_key_ref: reference } => {
+                validate_key_ref(name, reference)?;
+                EnvVarSource {
+                    secret_key_ref: Some(SecretKeySelector {
+                        name: reference.name.clone(),
+                        key: reference.key.clone(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+            }
+            EnvSource::ConfigMapKeyRef { config_map_key_ref: reference } => {
+                validate_key_ref(name, reference)?;
+                EnvVarSource {
+                    config_map_key_ref: Some(ConfigMapKeySelector {
+                        name: reference.name.clone(),
+                        key: reference.key.clone(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+            }
+        };
+
+        Ok(EnvVar { name: name.into(), value_from: Some(value_from), ..Default::default() })
+    }
+}
+
+fn validate_key_ref(name: &str, reference: &KeyRef) -> Result<()> {
+    if reference.name.is_empty() {
+        return Err(Error::InvalidEnvSource(name.into(), "the name of the reference is empty".into()));
+    }
+    if reference.key.is_empty() {
+        return Err(Error::InvalidEnvSource(name.into(), "the key of the reference is empty".into()));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,4 +225,97 @@ mod tests {
         assert_eq!(items[0].key, ".dockerconfigjson");
         assert_eq!(items[0].path, "config.json");
     }
+
+    #[test]
+    fn test_build_secrets_volume() {
+        assert_eq!(build_secrets_volume(&[]), None);
+
+        let secrets = [
+            BuildSecret { id: "npm".into(), name: "npm-token".into(), key: "token".into() },
+            BuildSecret { id: "pip".into(), name: "pip".into(), key: "pip.conf".into() },
+        ];
+        let volume = build_secrets_volume(&secrets).unwrap();
+        assert_eq!(volume.name, "build-secrets");
+
+        let sources = volume.projected.unwrap().sources.unwrap();
+        let secret = sources[0].secret.as_ref().unwrap();
+        assert_eq!(secret.name, "npm-token");
+        assert_eq!(
+            secret.items,
+            Some(vec![KeyToPath { key: "token".into(), path: "npm".into(), ..Default::default() }])
+        );
+        assert_eq!(sources[1].secret.as_ref().unwrap().name, "pip");
+    }
+
+    fn var(name: &str, value: &str) -> EnvVar {
+        EnvVar { name: name.into(), value: Some(value.into()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_coalesce_env_precedence() {
+        let sources = [("defaults", vec![var("A", "1"), var("B", "2")]), ("build", vec![var("C", "3"), var("A", "4")])];
+
+        let (environments, warnings) = coalesce_env(&sources);
+
+        assert_eq!(environments, vec![var("A", "4"), var("B", "2"), var("C", "3")]);
+        assert_eq!(warnings, vec!["A from build shadows the one from defaults"]);
+    }
+
+    #[test]
+    fn test_coalesce_env_sorted() {
+        let sources =
+            [("character", vec![var("LEVEL", "info"), var("PORT", "8080")]), ("options", vec![var("API", "1")])];
+
+        let (environments, _) = coalesce_env(&sources);
+
+        assert_eq!(environments, vec![var("API", "1"), var("LEVEL", "info"), var("PORT", "8080")]);
+    }
+
+    #[test]
+    fn test_coalesce_env_without_shadowing() {
+        let (environments, warnings) = coalesce_env(&[("deploy", vec![var("A", "1")]), ("build", vec![])]);
+
+        assert_eq!(environments, vec![var("A", "1")]);
+        assert!(warnings.is_empty());
+    }
+
+    fn env_var(name: &str, source: &str) -> Result<EnvVar> {
+        serde_json::from_str::<EnvSource>(source).unwrap().to_env_var(name)
+    }
+
+    #[test]
+    fn test_literal_env_source() {
+        let var = env_var("PORT", r#""8080""#).unwrap();
+
+        assert_eq!(var.value, Some("8080".into()));
+        assert_eq!(var.value_from, None);
+    }
+
+    #[test]
+    fn test_secret_env_source() {
+        let var = env_var("PASSWORD", r#"{"secretKeyRef": {"name": "db", "key": "password"}}"#).unwrap();
+
+        assert_eq!(var.value, None);
+        let selector = var.value_from.unwrap().secret_key_ref.unwrap();
+        assert_eq!(selector.name, "db");
+        assert_eq!(selector.key, "password");
+    }
+
+    #[test]
+    fn test_config_map_env_source() {
+        let var = env_var("LEVEL", r#"{"configMapKeyRef": {"name": "logging", "key": "level"}}"#).unwrap();
+
+        let source = var.value_from.unwrap();
+        assert_eq!(source.secret_key_ref, None);
+        let selector = source.config_map_key_ref.unwrap();
+        assert_eq!(selector.name, "logging");
+        assert_eq!(selector.key, "level");
+    }
+
+    #[test]
+    fn test_env_source_with_empty_key() {
+        let result = env_var("PASSWORD", r#"{"secretKeyRef": {"name": "db", "key": ""}}"#);
+
+        assert!(matches!(result, Err(Error::InvalidEnvSource(name, _)) if name == "PASSWORD"));
+    }
 }
@@ -0,0 +1,75 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::core::v1::Container;
+
+use super::{workspace_mount, WORKSPACE_DIR};
+use crate::ext::{ActorExt, ActorSpecExt, BuildSpecExt};
+
+pub const CONTAINER_NAME: &str = "pre-build";
+const DEFAULT_PRE_BUILD_IMAGE: &str = "busybox:stable";
+
+/// Build and return the container running the pre-build command of the actor
+/// in its build context, if any. It runs as an init container after syncing
+/// the sources, so that a non-zero exit stops the build before it starts.
+pub fn container(actor: &Actor) -> Option<Container> {
+    let command = actor.pre_build_command()?;
+
+    let build = actor.spec.character.build.clone().unwrap_or_default();
+    let manifest = actor.spec.normalized_manifest_path();
+    let workdir = build.resolved_context(&manifest, Path::new(WORKSPACE_DIR));
+
+    Some(Container {
+        name: CONTAINER_NAME.into(),
+        image: Some(actor.pre_build_image().unwrap_or_else(|| DEFAULT_PRE_BUILD_IMAGE.into())),
+        command: Some(command),
+        working_dir: Some(workdir.to_string_lossy().into()),
+        env: build.sorted_env(),
+        volume_mounts: Some(vec![workspace_mount()]),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+
+    use super::*;
+    use crate::options::ActorOptions;
+
+    fn actor(command: &[&str]) -> Actor {
+        let mut actor = Actor::new("test", ActorSpec { name: "test".into(), ..Default::default() });
+        let pre_build = command.iter().map(|arg| arg.to_string()).collect();
+        actor.set_options(&ActorOptions { pre_build, ..Default::default() });
+        actor
+    }
+
+    #[test]
+    fn test_pre_build_container() {
+        let container = container(&actor(&["make", "test"])).unwrap();
+
+        assert_eq!(container.name, "pre-build");
+        assert_eq!(container.image, Some("busybox:stable".into()));
+        assert_eq!(container.command, Some(vec!["make".into(), "test".into()]));
+        assert_eq!(container.working_dir, Some("/workspace".into()));
+    }
+
+    #[test]
+    fn test_without_pre_build_command() {
+        assert!(container(&actor(&[])).is_none());
+    }
+}
@@ -12,14 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::path::PathBuf;
+use std::path::Path;
 
 use super::{workspace_mount, WORKSPACE_DIR};
 use crate::args;
 use crate::error::{Error, Result};
+use crate::ext::{ActorExt, BuildSpecExt};
 use amp_common::resource::Actor;
 use k8s_openapi::api::core::v1::{Container, SecurityContext};
-use kube::ResourceExt;
 use lazy_static::lazy_static;
 
 // if release, use cargo pkg version, else use latest
@@ -33,14 +33,11 @@ lazy_static! {
 /// Build and return the container spec for the syncer.
 pub fn container(actor: &Actor, security_context: &Option<SecurityContext>) -> Result<Container> {
     let spec = &actor.spec;
-    let playbook = owner_reference(actor)?;
+    let playbook = actor.playbook_name()?;
 
     // Set the working directory to `workspace` argument.
     let build = spec.character.build.clone().unwrap_or_default();
-    let mut workdir = PathBuf::from(WORKSPACE_DIR);
-    if let Some(context) = &build.context {
-        workdir.push(context);
-    }
+    let workdir = build.resolved_context("", Path::new(WORKSPACE_DIR));
 
     // FIXME: get the nats url from the config of context.
     let once = spec.once.to_string();
@@ -64,16 +61,6 @@ pub fn container(actor: &Actor, security_context: &Option<SecurityContext>) -> R
     })
 }
 
-/// Get the playbook name from the owner reference.
-#[inline]
-fn owner_reference(actor: &Actor) -> Result<String> {
-    actor
-        .owner_references()
-        .iter()
-        .find_map(|owner| (owner.kind == "Playbook").then(|| owner.name.clone()))
-        .ok_or_else(|| Error::MissingObjectKey(".metadata.ownerReferences"))
-}
-
 #[cfg(test)]
 mod tests {
     use amp_common::resource::ActorSpec;
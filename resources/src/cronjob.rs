@@ -0,0 +1,217 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::batch::v1::{CronJob, CronJobSpec, JobSpec, JobTemplateSpec};
+use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
+use kube::api::{Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+use tracing::{debug, info};
+
+use super::error::{Error, Result};
+use super::ext::ActorExt;
+use super::{hash, LAST_APPLIED_HASH_KEY};
+
+/// The predefined schedules supported by the CronJob controller.
+const SCHEDULE_MACROS: &[&str] = &["@yearly", "@annually", "@monthly", "@weekly", "@daily", "@midnight", "@hourly"];
+
+/// The fields of a cron expression, with their bounds and names if any.
+const SCHEDULE_FIELDS: &[(&str, u32, u32, &[&str])] = &[
+    ("minute", 0, 59, &[]),
+    ("hour", 0, 23, &[]),
+    ("day of month", 1, 31, &[]),
+    ("month", 1, 12, &["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"]),
+    ("day of week", 0, 7, &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"]),
+];
+
+pub async fn exists(client: &Client, namespace: &str, name: &str) -> Result<bool> {
+    let api: Api<CronJob> = Api::namespaced(client.clone(), namespace);
+    Ok(api.get_opt(name).await.map_err(Error::KubeError)?.is_some())
+}
+
+pub async fn create(client: &Client, namespace: &str, resource: CronJob) -> Result<CronJob> {
+    let api: Api<CronJob> = Api::namespaced(client.clone(), namespace);
+    let cronjob = api.create(&PostParams::default(), &resource).await.map_err(Error::KubeError)?;
+    info!("Created CronJob: {}", cronjob.name_any());
+
+    Ok(cronjob)
+}
+
+pub async fn update(client: &Client, namespace: &str, name: &str, resource: CronJob) -> Result<CronJob> {
+    let api: Api<CronJob> = Api::namespaced(client.clone(), namespace);
+    let mut cronjob = api.get(name).await.map_err(Error::KubeError)?;
+    debug!("The CronJob {} already exists", name);
+
+    let expected_hash = resource.annotations().get(LAST_APPLIED_HASH_KEY);
+    if cronjob.annotations().get(LAST_APPLIED_HASH_KEY) == expected_hash {
+        debug!("The CronJob {} is already up-to-date", name);
+        return Ok(cronjob);
+    }
+
+    let params = &PatchParams::apply("amp-controllers").force();
+    cronjob = api.patch(name, params, &Patch::Apply(&resource)).await.map_err(Error::KubeError)?;
+
+    info!("Updated CronJob: {}", cronjob.name_any());
+    Ok(cronjob)
+}
+
+/// Build the CronJob running the pod of the actor on its schedule. The pods
+/// of a Job can not restart `Always`, so they are restarted on failure, and
+/// overlapping runs follow the concurrency policy of the actor.
+pub fn new(actor: &Actor, schedule: String, mut pod: PodSpec) -> Result<CronJob> {
+    let name = actor.name_any();
+
+    // Build the metadata for the cronjob
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = BTreeMap::from([
+        ("amphitheatre.app/character".into(), name.clone()),
+        ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
+    ]);
+    let concurrency_policy = actor.concurrency_policy();
+    let expected_hash = hash(&(&actor.spec, actor.render_cache_key(), &schedule, &concurrency_policy))?;
+    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), expected_hash)]);
+    let metadata = ObjectMeta {
+        name: Some(name),
+        owner_references: Some(vec![owner_reference]),
+        labels: Some(labels.clone()),
+        annotations: Some(annotations),
+        ..Default::default()
+    };
+
+    // Build the spec for the cronjob
+    pod.restart_policy = Some("OnFailure".into());
+    let template =
+        PodTemplateSpec { metadata: Some(ObjectMeta { labels: Some(labels), ..Default::default() }), spec: Some(pod) };
+    let spec = CronJobSpec {
+        schedule,
+        concurrency_policy: Some(concurrency_policy),
+        job_template: JobTemplateSpec { spec: Some(JobSpec { template, ..Default::default() }), ..Default::default() },
+        ..Default::default()
+    };
+
+    // Build and return the cronjob resource
+    Ok(CronJob { metadata, spec: Some(spec), ..Default::default() })
+}
+
+/// Validate a cron expression as accepted by the CronJob controller: five
+/// fields of values, names, ranges, lists and steps, or a predefined schedule
+/// like `@daily`. Time zones are set on the CronJob, not in the schedule.
+pub fn validate_schedule(schedule: &str) -> Result<(), String> {
+    let schedule = schedule.trim();
+    if schedule.starts_with('@') {
+        if SCHEDULE_MACROS.contains(&schedule) {
+            return Ok(());
+        }
+        return Err(format!("schedule `{}` is not a predefined schedule", schedule));
+    }
+
+    let fields: Vec<&str> = schedule.split_whitespace().collect();
+    if fields.len() != SCHEDULE_FIELDS.len() {
+        return Err(format!("schedule `{}` must have 5 fields, found {}", schedule, fields.len()));
+    }
+
+    for (field, (name, min, max, names)) in fields.iter().zip(SCHEDULE_FIELDS) {
+        let valid = field.split(',').all(|item| {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item, None),
+            };
+            if step.is_some_and(|step| !step.parse::<u32>().is_ok_and(|step| step > 0)) {
+                return false;
+            }
+
+            let value = |value: &str| -> Option<u32> {
+                value
+                    .parse()
+                    .ok()
+                    .or_else(|| {
+                        let index = names.iter().position(|name| name.eq_ignore_ascii_case(value))?;
+                        Some(*min + index as u32)
+                    })
+                    .filter(|value| (*min..=*max).contains(value))
+            };
+            match range {
+                "*" => true,
+                "?" => *name == "day of month" || *name == "day of week",
+                _ => match range.split_once('-') {
+                    Some((start, end)) => value(start).zip(value(end)).is_some_and(|(start, end)| start <= end),
+                    None => value(range).is_some(),
+                },
+            }
+        });
+        if !valid {
+            return Err(format!("schedule `{}` has an invalid {} field `{}`", schedule, name, field));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+
+    use super::*;
+    use crate::options::ActorOptions;
+
+    #[test]
+    fn test_validate_schedule() {
+        for schedule in
+            ["*/5 * * * *", "0 3 * * MON-FRI", "@daily", "0 0 1,15 * *", "30 2 ? jan-JUN 0", "15 10-12/2 * * 7"]
+        {
+            assert_eq!(validate_schedule(schedule), Ok(()), "{}", schedule);
+        }
+    }
+
+    #[test]
+    fn test_validate_invalid_schedule() {
+        assert_eq!(validate_schedule("* * * *"), Err("schedule `* * * *` must have 5 fields, found 4".into()));
+        assert_eq!(
+            validate_schedule("60 * * * *"),
+            Err("schedule `60 * * * *` has an invalid minute field `60`".into())
+        );
+        assert_eq!(validate_schedule("@every 5m"), Err("schedule `@every 5m` is not a predefined schedule".into()));
+        for schedule in ["", "* * 0 * *", "*/0 * * * *", "5-1 * * * *", "* * * FOO *", "? * * * *", "* * * * 8"] {
+            assert!(validate_schedule(schedule).is_err(), "{}", schedule);
+        }
+    }
+
+    fn actor(concurrency_policy: Option<&str>) -> Actor {
+        let mut actor = Actor::new("test", ActorSpec { name: "test".into(), ..Default::default() });
+        actor.metadata.uid = Some("uid".into());
+        let concurrency_policy = concurrency_policy.map(Into::into);
+        actor.set_options(&ActorOptions { concurrency_policy, ..Default::default() });
+        actor
+    }
+
+    #[test]
+    fn test_new() {
+        let cronjob = new(&actor(None), "@hourly".into(), PodSpec::default()).unwrap();
+        let spec = cronjob.spec.unwrap();
+        assert_eq!(spec.schedule, "@hourly");
+        assert_eq!(spec.concurrency_policy, Some("Forbid".into()));
+
+        let pod = spec.job_template.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod.restart_policy, Some("OnFailure".into()));
+    }
+
+    #[test]
+    fn test_new_with_concurrency_policy() {
+        let cronjob = new(&actor(Some("Replace")), "@hourly".into(), PodSpec::default()).unwrap();
+        assert_eq!(cronjob.spec.unwrap().concurrency_policy, Some("Replace".into()));
+    }
+}
@@ -12,25 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use amp_common::resource::Actor;
 use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
-use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
+use k8s_openapi::api::core::v1::{ConfigMap, EnvFromSource, PodSpec, PodTemplateSpec, Secret};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use kube::api::{Patch, PatchParams, PostParams};
 use kube::core::ObjectMeta;
 use kube::{Api, Client, Resource, ResourceExt};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use tracing::{debug, info};
 
 use super::error::{Error, Result};
+use super::ext::ActorExt;
 use super::{hash, LAST_APPLIED_HASH_KEY};
 
+const CONFIG_CHECKSUM_KEY: &str = "amphitheatre.app/config-checksum";
+
 pub async fn exists(client: &Client, namespace: &str, name: &str) -> Result<bool> {
     let api: Api<Deployment> = Api::namespaced(client.clone(), namespace);
     Ok(api.get_opt(name).await.map_err(Error::KubeError)?.is_some())
 }
 
+pub async fn get_opt(client: &Client, namespace: &str, name: &str) -> Result<Option<Deployment>> {
+    let api: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    api.get_opt(name).await.map_err(Error::KubeError)
+}
+
 pub async fn create(client: &Client, namespace: &str, resource: Deployment) -> Result<Deployment> {
     let api: Api<Deployment> = Api::namespaced(client.clone(), namespace);
     let deployment = api.create(&PostParams::default(), &resource).await.map_err(Error::KubeError)?;
@@ -66,6 +76,13 @@ pub async fn update(
     Ok(deployment)
 }
 
+/// Returns the hash of the inputs of the Deployment: the spec, the replicas,
+/// and the rendered workload of the actor, so that scaling it or changing any
+/// of its options updates the Deployment.
+pub fn expected_hash(actor: &Actor) -> Result<String> {
+    hash(&(&actor.spec, actor.replicas(), actor.render_cache_key()))
+}
+
 pub fn new(actor: &Actor, pod: PodSpec) -> Result<Deployment> {
     let name = actor.name_any();
 
@@ -75,7 +92,7 @@ pub fn new(actor: &Actor, pod: PodSpec) -> Result<Deployment> {
         ("amphitheatre.app/character".into(), name.clone()),
         ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
     ]);
-    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&actor.spec)?)]);
+    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), expected_hash(actor)?)]);
     let metadata = ObjectMeta {
         name: Some(name),
         owner_references: Some(vec![owner_reference]),
@@ -86,14 +103,197 @@ pub fn new(actor: &Actor, pod: PodSpec) -> Result<Deployment> {
 
     // Build the spec for the deployment
     let spec = DeploymentSpec {
+        replicas: Some(actor.replicas()),
         selector: LabelSelector { match_labels: Some(labels.clone()), ..Default::default() },
         template: PodTemplateSpec {
             metadata: Some(ObjectMeta { labels: Some(labels.clone()), ..Default::default() }),
             spec: Some(pod),
         },
+        progress_deadline_seconds: actor.deploy_timeout_seconds(),
         ..Default::default()
     };
 
     // Build and return the deployment resource
     Ok(Deployment { metadata, spec: Some(spec), ..Default::default() })
 }
+
+/// Check if the Deployment was last applied with the expected hash.
+pub fn up_to_date(deployment: &Deployment, expected_hash: &str) -> bool {
+    deployment.annotations().get(LAST_APPLIED_HASH_KEY).is_some_and(|hash| hash == expected_hash)
+}
+
+/// Set the number of replicas of the Deployment, leaving its pods as they are.
+pub async fn scale(client: &Client, namespace: &str, name: &str, replicas: i32) -> Result<()> {
+    let api: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let patch = json!({ "spec": { "replicas": replicas } });
+    api.patch(name, &PatchParams::default(), &Patch::Merge(&patch)).await.map_err(Error::KubeError)?;
+    info!("Scaled Deployment {} to {} replicas", name, replicas);
+
+    Ok(())
+}
+
+/// Fetch the data of the ConfigMaps and Secrets referenced by `envFrom`, keyed
+/// by `<kind>/<name>/<key>`. The values of Secrets are digested, so they are
+/// never kept around in plain. Missing references are skipped.
+pub async fn config_data(
+    client: &Client,
+    namespace: &str,
+    env_from: &[EnvFromSource],
+) -> Result<HashMap<String, String>> {
+    let mut data = HashMap::new();
+
+    for source in env_from {
+        if let Some(reference) = &source.config_map_ref {
+            let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+            if let Some(config_map) = api.get_opt(&reference.name).await.map_err(Error::KubeError)? {
+                for (key, value) in config_map.data.unwrap_or_default() {
+                    data.insert(format!("configmap/{}/{}", reference.name, key), value);
+                }
+            }
+        }
+        if let Some(reference) = &source.secret_ref {
+            let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+            if let Some(secret) = api.get_opt(&reference.name).await.map_err(Error::KubeError)? {
+                for (key, value) in secret.data.unwrap_or_default() {
+                    data.insert(format!("secret/{}/{}", reference.name, key), format!("{:x}", Sha256::digest(value.0)));
+                }
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Compute a checksum of the referenced configuration, which changes
+/// whenever a reference or any of the referenced values changes.
+pub fn config_checksum(env_from: &[EnvFromSource], data: &HashMap<String, String>) -> String {
+    let mut hasher = Sha256::new();
+
+    for source in env_from {
+        let prefix = source.prefix.as_deref().unwrap_or_default();
+        if let Some(reference) = &source.config_map_ref {
+            hasher.update(format!("configmap/{}/{}\n", reference.name, prefix));
+        }
+        if let Some(reference) = &source.secret_ref {
+            hasher.update(format!("secret/{}/{}\n", reference.name, prefix));
+        }
+    }
+
+    let data: BTreeMap<&String, &String> = data.iter().collect();
+    for (key, value) in data {
+        hasher.update(format!("{}={}\n", key, value));
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Stamp the config checksum on the pod template, so that the Deployment
+/// rolls out whenever the referenced configuration changes. The last applied
+/// hash covers the checksum too, otherwise the update would be skipped.
+pub fn set_config_checksum(deployment: &mut Deployment, actor: &Actor, checksum: String) -> Result<String> {
+    let expected_hash = hash(&(expected_hash(actor)?, &checksum))?;
+    deployment.annotations_mut().insert(LAST_APPLIED_HASH_KEY.into(), expected_hash.clone());
+
+    if let Some(spec) = deployment.spec.as_mut() {
+        let metadata = spec.template.metadata.get_or_insert_with(Default::default);
+        metadata.annotations.get_or_insert_with(Default::default).insert(CONFIG_CHECKSUM_KEY.into(), checksum);
+    }
+
+    Ok(expected_hash)
+}
+
+/// Check if the Deployment controller gave up on the rollout, i.e. the new
+/// ReplicaSet did not become ready within `progressDeadlineSeconds`.
+pub fn progress_deadline_exceeded(deployment: &Deployment) -> bool {
+    deployment.status.as_ref().and_then(|status| status.conditions.as_ref()).is_some_and(|conditions| {
+        conditions.iter().any(|condition| {
+            condition.type_ == "Progressing"
+                && condition.status == "False"
+                && condition.reason.as_deref() == Some("ProgressDeadlineExceeded")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::{DeploymentCondition, DeploymentStatus};
+    use k8s_openapi::api::core::v1::ConfigMapEnvSource;
+
+    use super::*;
+    use crate::options::ActorOptions;
+
+    fn deployment(status: &str, reason: &str) -> Deployment {
+        Deployment {
+            status: Some(DeploymentStatus {
+                conditions: Some(vec![DeploymentCondition {
+                    type_: "Progressing".into(),
+                    status: status.into(),
+                    reason: Some(reason.into()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_progress_deadline_exceeded() {
+        assert!(progress_deadline_exceeded(&deployment("False", "ProgressDeadlineExceeded")));
+        assert!(!progress_deadline_exceeded(&deployment("True", "NewReplicaSetAvailable")));
+        assert!(!progress_deadline_exceeded(&Deployment::default()));
+    }
+
+    #[test]
+    fn test_up_to_date() {
+        let mut deployment = Deployment::default();
+        deployment.annotations_mut().insert(LAST_APPLIED_HASH_KEY.into(), "abc".into());
+
+        assert!(up_to_date(&deployment, "abc"));
+        assert!(!up_to_date(&deployment, "def"));
+        assert!(!up_to_date(&Deployment::default(), "abc"));
+    }
+
+    #[test]
+    fn test_config_checksum() {
+        let env_from = vec![EnvFromSource {
+            config_map_ref: Some(ConfigMapEnvSource { name: "settings".into(), ..Default::default() }),
+            ..Default::default()
+        }];
+        let mut data = HashMap::from([("configmap/settings/LEVEL".to_string(), "info".to_string())]);
+
+        let checksum = config_checksum(&env_from, &data);
+        assert_eq!(checksum, config_checksum(&env_from, &data.clone()));
+
+        data.insert("configmap/settings/LEVEL".into(), "debug".into());
+        assert_ne!(checksum, config_checksum(&env_from, &data));
+        assert_ne!(checksum, config_checksum(&[], &HashMap::new()));
+    }
+
+    #[test]
+    fn test_replicas() {
+        let mut actor = Actor::new("api", Default::default());
+        actor.metadata.uid = Some("uid".into());
+        let hash = expected_hash(&actor).unwrap();
+        assert_eq!(new(&actor, Default::default()).unwrap().spec.unwrap().replicas, Some(1));
+
+        actor.set_options(&ActorOptions { replicas: Some(3), ..Default::default() });
+        let deployment = new(&actor, Default::default()).unwrap();
+        assert_eq!(deployment.spec.unwrap().replicas, Some(3));
+        assert_ne!(deployment.metadata.annotations.unwrap()[LAST_APPLIED_HASH_KEY], hash);
+    }
+
+    #[test]
+    fn test_expected_hash_covers_options() {
+        let mut actor = Actor::new("api", Default::default());
+        let hash = expected_hash(&actor).unwrap();
+
+        actor.set_options(&ActorOptions { image_pull_policy: Some("IfNotPresent".into()), ..Default::default() });
+        let pull_policy_hash = expected_hash(&actor).unwrap();
+        assert_ne!(pull_policy_hash, hash);
+
+        actor.set_options(&ActorOptions { container_name: Some("app".into()), ..Default::default() });
+        assert_ne!(expected_hash(&actor).unwrap(), hash);
+        assert_ne!(expected_hash(&actor).unwrap(), pull_policy_hash);
+    }
+}
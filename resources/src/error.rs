@@ -21,6 +21,9 @@ pub enum Error {
     #[error("SerializationError: {0}")]
     SerializationError(#[source] serde_json::Error),
 
+    #[error("YamlSerializationError: {0}")]
+    YamlSerializationError(#[source] serde_yaml::Error),
+
     #[error("Kube Error: {0}")]
     KubeError(#[source] kube::Error),
 
@@ -54,6 +57,33 @@ pub enum Error {
 
     #[error("ClusterStoreNotReady")]
     ClusterStoreNotReady,
+
+    #[error("MissingCapability: {0} is not installed in the cluster")]
+    MissingCapability(String),
+
+    #[error("DotenvParseError: line {0}: {1}")]
+    DotenvParseError(usize, String),
+
+    #[error("CommitNotOnRef: commit {0} is not reachable from {1}")]
+    CommitNotOnRef(String, String),
+
+    #[error("InvalidEnvSource: {0}: {1}")]
+    InvalidEnvSource(String, String),
+
+    #[error("InvalidServiceType: {0}")]
+    InvalidServiceType(String),
+
+    #[error("InvalidCommand: `{0}`: {1}")]
+    InvalidCommand(String, String),
+
+    #[error("InvalidBuild: {0}")]
+    InvalidBuild(String),
+
+    #[error("BuildPathMissing: {0}")]
+    BuildPathMissing(String),
+
+    #[error("InvalidOptions: {0}")]
+    InvalidOptions(String),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
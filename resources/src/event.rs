@@ -0,0 +1,45 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::resource::Actor;
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use kube::{Client, Resource, ResourceExt};
+use tracing::info;
+
+use super::error::{Error, Result};
+use super::ext::ActorExt;
+
+/// Publish a `Reconciled` event on the actor, unless one was already emitted
+/// for its desired state, so that `kubectl describe` only lists the
+/// reconciliations that changed something. Returns true if it was published.
+pub async fn reconciled(client: &Client, actor: &Actor) -> Result<bool> {
+    let fingerprint = actor.desired_fingerprint();
+    if actor.reconciled_fingerprint().as_deref() == Some(fingerprint.as_str()) {
+        return Ok(false);
+    }
+
+    let reporter = Reporter { controller: "amp-controllers".into(), instance: None };
+    let event = Event {
+        type_: EventType::Normal,
+        reason: "Reconciled".into(),
+        note: Some(format!("Reconciled the desired state {}", &fingerprint[..12.min(fingerprint.len())])),
+        action: "Reconcile".into(),
+        secondary: None,
+    };
+    Recorder::new(client.clone(), reporter).publish(&event, &actor.object_ref(&())).await.map_err(Error::KubeError)?;
+    info!("Published the Reconciled event of Actor {}", actor.name_any());
+
+    super::actor::record_reconciled_fingerprint(client, actor, &fingerprint).await?;
+    Ok(true)
+}
@@ -0,0 +1,227 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::resource::Actor;
+use kube::ResourceExt;
+
+use super::ext::actor::{LAST_BUILD_COMMIT_KEY, LAST_BUILD_FINGERPRINT_KEY};
+use super::ext::{ActorExt, ActorSpecExt, ActorStatusExt};
+
+/// Why the image of the actor is going to be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildReason {
+    /// Live actors are always rebuilt.
+    Live,
+    /// The commit of the source differs from the last build.
+    CommitChanged { from: String, to: String },
+    /// The build configuration differs from the last build.
+    BuildChanged,
+}
+
+/// What the controller will do next with the actor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NextAction {
+    /// The actor has no status yet, it will be initialized.
+    Initialize,
+    /// The image will be built.
+    Build(BuildReason),
+    /// The registry will be checked for the image, which is built if missing.
+    CheckImage,
+    /// The build is in progress, the controller waits for it to finish.
+    WaitForBuild,
+    /// The image will be deployed, on the schedule if any.
+    Deploy { schedule: Option<String> },
+    /// The actor ran to completion, nothing is left to do.
+    Completed,
+    /// The actor failed, nothing is done until its spec changes.
+    Nothing,
+}
+
+/// Returns what the controller will do next with the actor, from its status
+/// and the records of the last build.
+pub fn next_action(actor: &Actor) -> NextAction {
+    let Some(status) = &actor.status else {
+        return NextAction::Initialize;
+    };
+
+    if status.failed() {
+        NextAction::Nothing
+    } else if status.succeeded() {
+        NextAction::Completed
+    } else if (status.running() || status.scaling()) && status.build_succeeded() {
+        NextAction::Deploy { schedule: actor.cron_schedule() }
+    } else if status.building() {
+        NextAction::WaitForBuild
+    } else if status.pending() {
+        build_reason(actor).map_or(NextAction::CheckImage, NextAction::Build)
+    } else {
+        NextAction::Initialize
+    }
+}
+
+/// Returns a sentence describing what the controller will do next, like
+/// "Rebuilding because commit changed from abc123 to def456".
+pub fn explain(actor: &Actor) -> String {
+    match next_action(actor) {
+        NextAction::Initialize => format!("Initializing actor {}", actor.name_any()),
+        NextAction::Build(BuildReason::Live) => "Building because the actor is live".to_string(),
+        NextAction::Build(BuildReason::CommitChanged { from, to }) => {
+            format!("Rebuilding because commit changed from {} to {}", from, to)
+        }
+        NextAction::Build(BuildReason::BuildChanged) => {
+            "Rebuilding because the build configuration changed".to_string()
+        }
+        NextAction::CheckImage => {
+            format!("Checking if image {} exists, building it if not", actor.spec.image)
+        }
+        NextAction::WaitForBuild => format!("Waiting for the build of image {} to finish", actor.spec.image),
+        NextAction::Deploy { schedule: Some(schedule) } => {
+            format!("Deploying image {} as a CronJob on schedule \"{}\"", actor.spec.image, schedule)
+        }
+        NextAction::Deploy { schedule: None } => format!("Deploying image {}", actor.spec.image),
+        NextAction::Completed => "Nothing to do, the actor ran to completion".to_string(),
+        NextAction::Nothing => {
+            let condition = actor.status.as_ref().and_then(|status| {
+                status.conditions.iter().rev().find(|condition| condition.type_.eq_ignore_ascii_case("failed"))
+            });
+            match condition {
+                Some(condition) if !condition.message.is_empty() => {
+                    format!("Nothing to do, the actor failed with {}: {}", condition.reason, condition.message)
+                }
+                Some(condition) => format!("Nothing to do, the actor failed with {}", condition.reason),
+                None => "Nothing to do, the actor failed".to_string(),
+            }
+        }
+    }
+}
+
+/// Returns why the image is going to be built, or `None` if it depends on
+/// whether it already exists in the registry.
+fn build_reason(actor: &Actor) -> Option<BuildReason> {
+    if actor.spec.live {
+        return Some(BuildReason::Live);
+    }
+
+    let annotations = actor.annotations();
+    let commit = actor.spec.source.as_ref().map(|source| source.rev()).unwrap_or_default();
+    if let Some(last_commit) = annotations.get(LAST_BUILD_COMMIT_KEY).filter(|last| **last != commit) {
+        return Some(BuildReason::CommitChanged { from: last_commit.clone(), to: commit });
+    }
+
+    let fingerprint = actor.spec.build_fingerprint();
+    annotations.get(LAST_BUILD_FINGERPRINT_KEY).filter(|last| **last != fingerprint).map(|_| BuildReason::BuildChanged)
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::{ActorSpec, ActorState, ActorStatus};
+    use amp_common::schema::GitReference;
+
+    use super::*;
+    use crate::ext::ActorStateExt;
+
+    fn actor(condition: Option<k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition>) -> Actor {
+        let source = GitReference {
+            repo: "https://github.com/amphitheatre-app/amp-example-go".into(),
+            rev: Some("def456".into()),
+            ..Default::default()
+        };
+        let spec =
+            ActorSpec { name: "test".into(), image: "test:v1".into(), source: Some(source), ..Default::default() };
+        let mut actor = Actor::new("test", spec);
+        actor.status = condition.map(|condition| {
+            let mut status = ActorStatus::default();
+            status.conditions.push(condition);
+            status
+        });
+        actor
+    }
+
+    fn record(mut actor: Actor, commit: &str, fingerprint: Option<String>) -> Actor {
+        let fingerprint = fingerprint.unwrap_or_else(|| actor.spec.build_fingerprint());
+        actor.annotations_mut().insert(LAST_BUILD_COMMIT_KEY.into(), commit.into());
+        actor.annotations_mut().insert(LAST_BUILD_FINGERPRINT_KEY.into(), fingerprint);
+        actor
+    }
+
+    #[test]
+    fn test_explain_without_status() {
+        assert_eq!(next_action(&actor(None)), NextAction::Initialize);
+        assert_eq!(explain(&actor(None)), "Initializing actor test");
+    }
+
+    #[test]
+    fn test_explain_changed_commit() {
+        let actor = record(actor(Some(ActorState::pending())), "abc123", None);
+        assert_eq!(explain(&actor), "Rebuilding because commit changed from abc123 to def456");
+    }
+
+    #[test]
+    fn test_explain_changed_build() {
+        let actor = record(actor(Some(ActorState::pending())), "def456", Some("outdated".into()));
+        assert_eq!(next_action(&actor), NextAction::Build(BuildReason::BuildChanged));
+    }
+
+    #[test]
+    fn test_explain_unchanged_build() {
+        let actor = record(actor(Some(ActorState::pending())), "def456", None);
+        assert_eq!(explain(&actor), "Checking if image test:v1 exists, building it if not");
+    }
+
+    #[test]
+    fn test_explain_live_actor() {
+        let mut actor = record(actor(Some(ActorState::pending())), "def456", None);
+        actor.spec.live = true;
+        assert_eq!(next_action(&actor), NextAction::Build(BuildReason::Live));
+    }
+
+    #[test]
+    fn test_explain_building_and_running() {
+        assert_eq!(explain(&actor(Some(ActorState::building()))), "Waiting for the build of image test:v1 to finish");
+        assert_eq!(explain(&actor(Some(ActorState::running(true, "AutoRun", None)))), "Deploying image test:v1");
+    }
+
+    #[test]
+    fn test_deploy_blocked_until_built() {
+        use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+        use k8s_openapi::chrono::{TimeZone, Utc};
+
+        let at = |mut condition: k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition, seconds| {
+            condition.last_transition_time = Time(Utc.timestamp_opt(seconds, 0).unwrap());
+            condition
+        };
+
+        let mut actor = actor(Some(at(ActorState::running(true, "AutoRun", None), 10)));
+        actor.status.as_mut().unwrap().conditions.push(at(ActorState::building(), 20));
+        assert_eq!(next_action(&actor), NextAction::WaitForBuild);
+
+        actor.status.as_mut().unwrap().conditions.push(at(ActorState::running(true, "AutoRun", None), 30));
+        assert_eq!(next_action(&actor), NextAction::Deploy { schedule: None });
+    }
+
+    #[test]
+    fn test_explain_failed() {
+        let condition = ActorState::failed(true, "PreBuildFailed", Some("exit code 1".into()));
+        assert_eq!(
+            explain(&actor(Some(condition))),
+            "Nothing to do, the actor failed with PreBuildFailed: exit code 1"
+        );
+    }
+
+    #[test]
+    fn test_explain_succeeded() {
+        let actor = actor(Some(ActorState::succeeded("Completed", None)));
+        assert_eq!(next_action(&actor), NextAction::Completed);
+    }
+}
@@ -0,0 +1,1555 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::core::v1::{EnvVar, LocalObjectReference, Probe, ResourceRequirements};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::ResourceExt;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::blue_green::Color;
+use crate::canary::CanaryConfig;
+use crate::containers::artifacts::SharedArtifact;
+use crate::containers::{application, coalesce_env, EnvSource};
+use crate::cronjob::validate_schedule;
+use crate::error::{Error, Result};
+use crate::ext::{ActorSpecExt, ActorStatusExt, BuildCache, BuildSecret};
+use crate::footprint::ResourceFootprint;
+use crate::ingress::IngressTls;
+use crate::options::{ActorOptions, OPTIONS_KEY};
+use crate::ports::PortMapping;
+use crate::{
+    hash, is_digest, is_dns1123_subdomain, parse_duration, to_dns1123_label, validate_platform, DEFAULT_PLATFORM,
+};
+
+// The records of the controller, the options of the actor are in `amphitheatre.app/options`.
+pub(crate) const ACTIVE_COLOR_KEY: &str = "amphitheatre.app/active-color";
+pub(crate) const BUILD_FINISHED_AT_KEY: &str = "amphitheatre.app/build-finished-at";
+pub(crate) const BUILD_STARTED_AT_KEY: &str = "amphitheatre.app/build-started-at";
+pub(crate) const ENV_CONFIGMAP_KEY: &str = "amphitheatre.app/env-configmap";
+pub(crate) const FAILURE_COUNT_KEY: &str = "amphitheatre.app/failure-count";
+pub(crate) const IMAGE_DIGEST_KEY: &str = "amphitheatre.app/image-digest";
+pub(crate) const LAST_BUILD_COMMIT_KEY: &str = "amphitheatre.app/last-build-commit";
+pub(crate) const LAST_BUILD_FINGERPRINT_KEY: &str = "amphitheatre.app/last-build-fingerprint";
+pub(crate) const RECONCILED_FINGERPRINT_KEY: &str = "amphitheatre.app/reconciled-fingerprint";
+pub(crate) const REPLICAS_KEY: &str = "amphitheatre.app/replicas";
+
+/// How the actor is kept up to date with its repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagementMode {
+    /// Declarative, the actor only changes when its spec changes.
+    GitOps,
+    /// Push-driven, the actor is rebuilt on pushes to its repository.
+    Sync,
+}
+
+/// How a new version of the actor replaces the running one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeploymentStrategy {
+    /// The pods of the Deployment are replaced gradually.
+    Rolling,
+    /// The new version is deployed next to the running one, and the traffic
+    /// switched to it at once when it is ready.
+    BlueGreen,
+}
+
+pub trait ActorExt {
+    fn options(&self) -> Result<ActorOptions>;
+    fn set_options(&mut self, options: &ActorOptions);
+    fn active_color(&self) -> Option<Color>;
+    fn allow_reserved_env(&self) -> bool;
+    fn build_started_at(&self) -> Option<DateTime<Utc>>;
+    fn build_finished_at(&self) -> Option<DateTime<Utc>>;
+    fn build_duration(&self) -> Option<Duration>;
+    fn build_name(&self) -> String;
+    fn build_timeout_seconds(&self) -> Option<u64>;
+    fn build_timed_out(&self, now: DateTime<Utc>) -> bool;
+    fn build_cache(&self) -> Option<BuildCache>;
+    fn cache_image(&self) -> Option<String>;
+    fn build_secrets(&self) -> Result<Vec<BuildSecret>>;
+    fn canary(&self) -> Option<CanaryConfig>;
+    fn should_build(&self, fingerprint: &str, commit: &str) -> bool;
+    fn container_name(&self) -> String;
+    fn deploy_timeout_seconds(&self) -> Option<i32>;
+    fn deployment_strategy(&self) -> DeploymentStrategy;
+    fn desired_fingerprint(&self) -> String;
+    fn reconciled_fingerprint(&self) -> Option<String>;
+    fn docker_ref(&self) -> String;
+    fn dockerfile_target(&self) -> Option<String>;
+    fn image_digest(&self) -> Option<String>;
+    fn failure_count(&self) -> u32;
+    fn next_backoff(&self, errors: u32, base: Duration, max: Duration) -> Duration;
+    fn image_pull_policy(&self) -> String;
+    fn image_pull_secret_names(&self) -> Vec<String>;
+    fn image_pull_secrets(&self) -> Vec<LocalObjectReference>;
+    fn ingress_tls(&self) -> Option<IngressTls>;
+    fn liveness_probe(&self) -> Option<Probe>;
+    fn readiness_probe(&self) -> Option<Probe>;
+    fn probe_ports(&self) -> Vec<i32>;
+    fn pull_policy_warnings(&self) -> Vec<String>;
+    fn network_policy(&self) -> bool;
+    fn otel_attributes(&self) -> Vec<(&'static str, String)>;
+    fn metrics_labels(&self) -> BTreeMap<String, String>;
+    fn egress_policy(&self) -> bool;
+    fn env_file(&self) -> Option<String>;
+    fn env_sources(&self) -> BTreeMap<String, EnvSource>;
+    fn environments(&self) -> (Vec<EnvVar>, Vec<String>);
+    fn materialize_env(&self) -> bool;
+    fn env_configmap_name(&self) -> String;
+    fn platforms(&self) -> Vec<String>;
+    fn platforms_or_default(&self) -> Vec<String>;
+    fn playbook_name(&self) -> Result<String>;
+    fn port_mappings(&self) -> BTreeMap<i32, PortMapping>;
+    fn pre_build_command(&self) -> Option<Vec<String>>;
+    fn pre_build_image(&self) -> Option<String>;
+    fn publish_not_ready_addresses(&self) -> bool;
+    fn session_affinity(&self) -> Option<String>;
+    fn render_cache_key(&self) -> String;
+    fn replicas(&self) -> i32;
+    fn resource_requirements(&self) -> Option<ResourceRequirements>;
+    fn footprint(&self) -> ResourceFootprint;
+    fn resolved_post_tags(&self) -> Vec<String>;
+    fn versioned_tag(&self) -> Option<String>;
+    fn cron_schedule(&self) -> Option<String>;
+    fn shared_artifacts(&self) -> Vec<SharedArtifact>;
+    fn concurrency_policy(&self) -> String;
+    fn sync(&self) -> bool;
+    fn sync_refs(&self) -> Vec<String>;
+    fn management_mode(&self) -> ManagementMode;
+}
+
+impl ActorExt for Actor {
+    /// Returns the options of the actor, read from the `amphitheatre.app/options`
+    /// annotation, or the defaults without it. Fails if they are malformed, or
+    /// of another version, see [`ActorOptions`].
+    fn options(&self) -> Result<ActorOptions> {
+        match self.annotations().get(OPTIONS_KEY) {
+            Some(value) => ActorOptions::parse(value),
+            None => Ok(ActorOptions::default()),
+        }
+    }
+
+    /// Set the options of the actor, stamped with the supported version.
+    fn set_options(&mut self, options: &ActorOptions) {
+        self.annotations_mut().insert(OPTIONS_KEY.into(), options.to_annotation());
+    }
+
+    /// Returns the color serving the traffic of an actor deployed blue-green,
+    /// recorded by the controller in the `amphitheatre.app/active-color` annotation.
+    fn active_color(&self) -> Option<Color> {
+        self.annotations().get(ACTIVE_COLOR_KEY).and_then(|value| value.parse().ok())
+    }
+
+    /// Returns true if the user may override the reserved `AMP_*` variables,
+    /// enabled with the `allowReservedEnv` option.
+    fn allow_reserved_env(&self) -> bool {
+        options_or_default(self).allow_reserved_env
+    }
+
+    /// Returns the time the last build started at, recorded by the controller
+    /// in the `amphitheatre.app/build-started-at` annotation.
+    fn build_started_at(&self) -> Option<DateTime<Utc>> {
+        timestamp(self, BUILD_STARTED_AT_KEY)
+    }
+
+    /// Returns the time the last build finished at, recorded by the controller
+    /// in the `amphitheatre.app/build-finished-at` annotation.
+    fn build_finished_at(&self) -> Option<DateTime<Utc>> {
+        timestamp(self, BUILD_FINISHED_AT_KEY)
+    }
+
+    /// Returns how long the last build took, or `None` if it is still running.
+    fn build_duration(&self) -> Option<Duration> {
+        let started_at = self.build_started_at()?;
+        let finished_at = self.build_finished_at()?;
+        (finished_at - started_at).to_std().ok()
+    }
+
+    /// Returns the name of the build resources of the actor, the Job or the
+    /// kpack Image, sanitized to a valid RFC 1123 label.
+    fn build_name(&self) -> String {
+        to_dns1123_label(&format!("{}-builder", self.spec.name))
+    }
+
+    /// Returns the build timeout in seconds, read from the `buildTimeout`
+    /// option (e.g. `30m`). A missing, malformed or zero timeout yields `None`.
+    fn build_timeout_seconds(&self) -> Option<u64> {
+        options_or_default(self)
+            .build_timeout
+            .and_then(|value| parse_duration(&value))
+            .map(|duration| duration.as_secs())
+            .filter(|seconds| *seconds > 0)
+    }
+
+    /// Returns true if the current build started longer than its timeout ago.
+    fn build_timed_out(&self, now: DateTime<Utc>) -> bool {
+        let (Some(timeout), Some(started_at)) = (self.build_timeout_seconds(), self.build_started_at()) else {
+            return false;
+        };
+        (now - started_at).to_std().is_ok_and(|elapsed| elapsed.as_secs() >= timeout)
+    }
+
+    /// Returns the layer cache of the builds, read from the `buildCache` option
+    /// like `{"enabled": true, "repo": "registry.local/api-cache"}`.
+    fn build_cache(&self) -> Option<BuildCache> {
+        options_or_default(self).build_cache
+    }
+
+    /// Returns the image the build cache is pushed to when it is enabled: its
+    /// repo, or the repository of the image suffixed with `-cache` by default,
+    /// like `registry.local/api-cache` for `registry.local/api:v1`.
+    fn cache_image(&self) -> Option<String> {
+        let cache = self.build_cache().filter(|cache| cache.enabled)?;
+        Some(cache.repo.unwrap_or_else(|| format!("{}-cache", image_repository(&self.spec.image))))
+    }
+
+    /// Returns the secrets mounted in the builder, read from the `buildSecrets`
+    /// option like `[{"id": "npm", "name": "npm-token", "key": "token"}]`.
+    /// Fails if the ids are empty or repeated, as the secrets are mounted by their id.
+    fn build_secrets(&self) -> Result<Vec<BuildSecret>> {
+        let secrets = options_or_default(self).build_secrets;
+
+        let mut ids = HashSet::new();
+        for secret in &secrets {
+            if secret.id.trim().is_empty() {
+                return Err(Error::InvalidBuild("build secret id must not be empty".into()));
+            }
+            if secret.name.is_empty() || secret.key.is_empty() {
+                return Err(Error::InvalidBuild(format!(
+                    "build secret `{}` must name a secret and its key",
+                    secret.id
+                )));
+            }
+            if !ids.insert(&secret.id) {
+                return Err(Error::InvalidBuild(format!("build secret id `{}` is used more than once", secret.id)));
+            }
+        }
+
+        Ok(secrets)
+    }
+
+    /// Returns the canary rollout of the actor, read from the `canary` option
+    /// like `{"weight": 10, "steps": [25, 50, 100]}`.
+    fn canary(&self) -> Option<CanaryConfig> {
+        options_or_default(self).canary
+    }
+
+    /// Returns the image reference to deploy, pinned by the digest of the built
+    /// image once recorded, like `image@sha256:...`, or the image as is.
+    fn docker_ref(&self) -> String {
+        match self.image_digest() {
+            Some(digest) => format!("{}@{}", image_repository(&self.spec.image), digest),
+            None => self.spec.image.clone(),
+        }
+    }
+
+    /// Returns the stage of the multi-stage Dockerfile to build, read from the
+    /// `dockerfileTarget` option. The last stage is built by default.
+    fn dockerfile_target(&self) -> Option<String> {
+        let target = options_or_default(self).dockerfile_target?;
+        let target = target.trim();
+        (!target.is_empty()).then(|| target.to_string())
+    }
+
+    /// Returns the digest of the built image, recorded by the controller in the
+    /// `amphitheatre.app/image-digest` annotation once it is pushed. A malformed
+    /// digest is ignored with a warning.
+    fn image_digest(&self) -> Option<String> {
+        let digest = self.annotations().get(IMAGE_DIGEST_KEY)?;
+        if !is_digest(digest) {
+            warn!("Ignore the malformed image digest of actor {}: {}", self.name_any(), digest);
+            return None;
+        }
+        Some(digest.clone())
+    }
+
+    /// Returns true if the fingerprint of the build or the commit differs from
+    /// the last successful build, recorded by the controller. Without such
+    /// a record the actor was never built by the controller, so it is built,
+    /// even if an image of the same reference is in the registry already.
+    fn should_build(&self, fingerprint: &str, commit: &str) -> bool {
+        let annotations = self.annotations();
+        match (annotations.get(LAST_BUILD_FINGERPRINT_KEY), annotations.get(LAST_BUILD_COMMIT_KEY)) {
+            (Some(last_fingerprint), Some(last_commit)) => last_fingerprint != fingerprint || last_commit != commit,
+            _ => true,
+        }
+    }
+
+    /// Returns the name of the main container, which defaults to the actor name
+    /// and can be overridden with the `containerName` option, for sidecar
+    /// injectors and service meshes that key on container names.
+    fn container_name(&self) -> String {
+        options_or_default(self).container_name.unwrap_or_else(|| self.spec.name.clone())
+    }
+
+    /// Returns the deploy timeout in seconds, read from the `deployTimeout`
+    /// option (e.g. `10m`). A missing, malformed or zero timeout yields `None`.
+    fn deploy_timeout_seconds(&self) -> Option<i32> {
+        options_or_default(self)
+            .deploy_timeout
+            .and_then(|value| parse_duration(&value))
+            .and_then(|duration| i32::try_from(duration.as_secs()).ok())
+            .filter(|seconds| *seconds > 0)
+    }
+
+    /// Returns the fingerprint of the desired state of the actor, its spec and
+    /// the rendered workload, which changes exactly when a reconciliation is
+    /// worth an event. The records of the controller, and the status, are left out.
+    fn desired_fingerprint(&self) -> String {
+        hash(&(&self.spec, self.render_cache_key())).unwrap_or_default()
+    }
+
+    /// Returns the fingerprint of the desired state the last `Reconciled` event was
+    /// emitted for, recorded by the controller in the `amphitheatre.app/reconciled-fingerprint`
+    /// annotation.
+    fn reconciled_fingerprint(&self) -> Option<String> {
+        self.annotations().get(RECONCILED_FINGERPRINT_KEY).cloned()
+    }
+
+    /// Returns how a new version replaces the running one, read from the
+    /// `deploymentStrategy` option, one of `rolling` or `blue-green`.
+    /// The pods are rolled by default.
+    fn deployment_strategy(&self) -> DeploymentStrategy {
+        options_or_default(self).deployment_strategy.unwrap_or(DeploymentStrategy::Rolling)
+    }
+
+    /// Returns the number of consecutive failures of the actor, recorded by the
+    /// controller in the `amphitheatre.app/failure-count` annotation.
+    fn failure_count(&self) -> u32 {
+        self.annotations().get(FAILURE_COUNT_KEY).and_then(|value| value.parse().ok()).unwrap_or_default()
+    }
+
+    /// Returns the delay before retrying, `base * 2^(failures + errors)` capped
+    /// at `max`, where `errors` is the number of consecutive reconciles that
+    /// erred before, so persistently failing actors back off instead of
+    /// retrying tightly.
+    fn next_backoff(&self, errors: u32, base: Duration, max: Duration) -> Duration {
+        let exponent = self.failure_count().saturating_add(errors);
+        2u32.checked_pow(exponent).and_then(|factor| base.checked_mul(factor)).unwrap_or(max).min(max)
+    }
+
+    /// Returns the pull policy of the main container, `Always` by default, which
+    /// can be overridden with the `imagePullPolicy` option.
+    fn image_pull_policy(&self) -> String {
+        match options_or_default(self).image_pull_policy.as_deref() {
+            Some(policy @ ("Always" | "IfNotPresent" | "Never")) => policy.to_string(),
+            Some(policy) => {
+                warn!("Invalid image pull policy {} of actor {}, using Always", policy, self.name_any());
+                "Always".to_string()
+            }
+            None => "Always".to_string(),
+        }
+    }
+
+    /// Returns the names of the Secrets holding the credentials to pull the images
+    /// from private registries, read from the `imagePullSecrets` option like
+    /// `["registry-credentials"]`. They are not validated, see `image_pull_secrets`.
+    fn image_pull_secret_names(&self) -> Vec<String> {
+        options_or_default(self).image_pull_secrets
+    }
+
+    /// Returns the references to the Secrets to pull the images with, for the
+    /// pods of the actor and of its builds. Invalid names are ignored with a warning.
+    fn image_pull_secrets(&self) -> Vec<LocalObjectReference> {
+        self.image_pull_secret_names()
+            .into_iter()
+            .filter(|name| {
+                let valid = is_dns1123_subdomain(name);
+                if !valid {
+                    warn!("Ignore the image pull secret of actor {}: {} is not a valid name", self.name_any(), name);
+                }
+                valid
+            })
+            .map(|name| LocalObjectReference { name })
+            .collect()
+    }
+
+    /// Returns the TLS configuration of the Ingress, read from the `tls` option like
+    /// `{"secretName": "api-tls", "hosts": ["api.example.com"], "issuer": "letsencrypt"}`.
+    fn ingress_tls(&self) -> Option<IngressTls> {
+        options_or_default(self).tls
+    }
+
+    /// Returns the liveness probe of the main container, read from the
+    /// `livenessProbe` option as a Kubernetes `Probe`, like
+    /// `{"httpGet": {"path": "/healthz", "port": 8080}}`.
+    fn liveness_probe(&self) -> Option<Probe> {
+        options_or_default(self).liveness_probe
+    }
+
+    /// Returns the readiness probe of the main container, read from the
+    /// `readinessProbe` option like the liveness probe.
+    fn readiness_probe(&self) -> Option<Probe> {
+        options_or_default(self).readiness_probe
+    }
+
+    /// Returns the port numbers targeted by the probes, sorted and deduplicated.
+    /// Ports referenced by name are left out, they must be declared anyway.
+    fn probe_ports(&self) -> Vec<i32> {
+        let mut ports = vec![];
+        for probe in [self.liveness_probe(), self.readiness_probe()].into_iter().flatten() {
+            let targets = [probe.http_get.map(|action| action.port), probe.tcp_socket.map(|action| action.port)];
+            ports.extend(targets.into_iter().flatten().filter_map(|port| match port {
+                IntOrString::Int(number) => Some(number),
+                IntOrString::String(_) => None,
+            }));
+            ports.extend(probe.grpc.map(|action| action.port));
+        }
+
+        ports.sort();
+        ports.dedup();
+        ports
+    }
+
+    /// Returns the warnings for the pull policies that do not suit the image:
+    /// pulling an immutable digest `Always` is wasteful, while a `latest` image
+    /// pulled `IfNotPresent` goes stale on the nodes that already have it.
+    fn pull_policy_warnings(&self) -> Vec<String> {
+        let image = &self.spec.image;
+        let policy = self.image_pull_policy();
+
+        let mut warnings = vec![];
+        if image.contains('@') && policy == "Always" {
+            warnings.push(format!(
+                "image `{}` is pinned by digest, pulling it `Always` is wasteful, consider `IfNotPresent`",
+                image
+            ));
+        }
+        if matches!(image_tag(image), None | Some("latest")) && policy == "IfNotPresent" {
+            warnings.push(format!(
+                "image `{}` uses the `latest` tag, pulling it `IfNotPresent` may run a stale image",
+                image
+            ));
+        }
+        warnings
+    }
+
+    /// Returns true if the traffic to the actor should be restricted by a NetworkPolicy,
+    /// enabled with the `networkPolicy` option.
+    fn network_policy(&self) -> bool {
+        options_or_default(self).network_policy
+    }
+
+    /// Returns the OpenTelemetry span attributes describing the actor, as plain
+    /// key-value pairs following the semantic conventions, so they can be
+    /// attached to spans regardless of the exporter.
+    fn otel_attributes(&self) -> Vec<(&'static str, String)> {
+        let mut attributes = vec![("service.name", self.spec.name.clone())];
+
+        let repository = self.spec.source.as_ref().map_or(&self.spec.character.meta.repository, |source| &source.repo);
+        if !repository.is_empty() {
+            attributes.push(("vcs.repository.url.full", repository.clone()));
+        }
+        if let Some(commit) = self.spec.source.as_ref().and_then(|source| source.rev.clone()) {
+            attributes.push(("vcs.repository.ref.revision", commit));
+        }
+        if let Some(phase) = self.status.as_ref().and_then(|status| status.phase()) {
+            attributes.push(("amphitheatre.actor.state", phase.to_string()));
+        }
+
+        attributes
+    }
+
+    /// Returns the labels identifying the actor in the scraped metrics, set on
+    /// its Service so that Prometheus can relabel the targets from them: the
+    /// actor name, its namespace, and the commit it runs if any.
+    fn metrics_labels(&self) -> BTreeMap<String, String> {
+        let mut labels = BTreeMap::from([("amphitheatre.app/actor".to_string(), self.spec.name.clone())]);
+        if let Some(namespace) = self.namespace() {
+            labels.insert("amphitheatre.app/namespace".into(), namespace);
+        }
+        if let Some(commit) = self.spec.source.as_ref().and_then(|source| source.rev.clone()) {
+            labels.insert("amphitheatre.app/commit".into(), commit);
+        }
+
+        labels
+    }
+
+    /// Returns true if the egress traffic of the actor should be restricted to its
+    /// partners, enabled with the `egressPolicy` option.
+    /// It only takes effect together with the network policy.
+    fn egress_policy(&self) -> bool {
+        options_or_default(self).egress_policy
+    }
+
+    /// Returns the path of the `.env` file to load into the environment, relative
+    /// to the repository root, read from the `envFile` option.
+    /// Its variables rank below the ones declared in the character, once loaded.
+    fn env_file(&self) -> Option<String> {
+        let path = options_or_default(self).env_file?;
+        let path = path.trim().trim_start_matches('/');
+        (!path.is_empty()).then(|| path.to_string())
+    }
+
+    /// Returns the environment variables of the `env` option, whose values
+    /// are literal strings or references to a key of a secret or a config map,
+    /// like `{"PASSWORD": {"secretKeyRef": {"name": "db", "key": "password"}}}`.
+    fn env_sources(&self) -> BTreeMap<String, EnvSource> {
+        options_or_default(self).env
+    }
+
+    /// Resolve the environment variables of the application container, where
+    /// the ones of the options take precedence over the character's, with
+    /// the warnings for the shadowed and the invalid ones, which are skipped.
+    fn environments(&self) -> (Vec<EnvVar>, Vec<String>) {
+        let (character, mut warnings) = self.spec.resolve_env();
+
+        let mut options = vec![];
+        for (name, source) in self.env_sources() {
+            match source.to_env_var(&name) {
+                Ok(var) => options.push(var),
+                Err(err) => warnings.push(err.to_string()),
+            }
+        }
+
+        let (environments, shadowed) = coalesce_env(&[("character", character), ("options", options)]);
+        warnings.extend(shadowed);
+        (environments, warnings)
+    }
+
+    /// Returns true if the resolved environment should be materialized in a
+    /// ConfigMap for troubleshooting, enabled with the `materializeEnv` option.
+    fn materialize_env(&self) -> bool {
+        options_or_default(self).materialize_env
+    }
+
+    /// Returns the name of the ConfigMap the resolved environment is
+    /// materialized in, sanitized to a valid RFC 1123 label.
+    fn env_configmap_name(&self) -> String {
+        to_dns1123_label(&format!("{}-env", self.spec.name))
+    }
+
+    /// Returns the platforms to build the image for, read from the `platforms`
+    /// option like `["linux/amd64", "linux/arm64"]`, without the repeated ones.
+    /// They are not validated, see `platforms_or_default`.
+    fn platforms(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        options_or_default(self).platforms.into_iter().filter(|platform| seen.insert(platform.clone())).collect()
+    }
+
+    /// Returns the supported platforms to build the image for, or `linux/amd64`
+    /// if none is set. Malformed or unsupported platforms are ignored with a warning.
+    fn platforms_or_default(&self) -> Vec<String> {
+        let platforms: Vec<String> = self
+            .platforms()
+            .into_iter()
+            .filter(|platform| match validate_platform(platform) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Ignore the platform of actor {}: {}", self.name_any(), err);
+                    false
+                }
+            })
+            .collect();
+
+        if platforms.is_empty() {
+            vec![DEFAULT_PLATFORM.to_string()]
+        } else {
+            platforms
+        }
+    }
+
+    /// Returns the name of the playbook owning the actor, from its owner reference.
+    fn playbook_name(&self) -> Result<String> {
+        self.owner_references()
+            .iter()
+            .find_map(|owner| (owner.kind == "Playbook").then(|| owner.name.clone()))
+            .ok_or_else(|| Error::MissingObjectKey(".metadata.ownerReferences"))
+    }
+
+    /// Returns the target and node ports of the service ports, read from the
+    /// `ports` option keyed by the exposed port, like
+    /// `{"80": {"targetPort": 8080, "nodePort": 30080}}`.
+    fn port_mappings(&self) -> BTreeMap<i32, PortMapping> {
+        options_or_default(self).ports
+    }
+
+    /// Returns the command to run before building the image, e.g. the tests,
+    /// read from the `preBuild` option like `["make", "test"]`.
+    /// A missing or empty command yields `None`.
+    fn pre_build_command(&self) -> Option<Vec<String>> {
+        Some(options_or_default(self).pre_build).filter(|command| !command.is_empty())
+    }
+
+    /// Returns the image to run the pre-build command in, read from
+    /// the `preBuildImage` option.
+    fn pre_build_image(&self) -> Option<String> {
+        options_or_default(self).pre_build_image
+    }
+
+    /// Returns true if a headless companion of the Service should publish the
+    /// addresses of the pods that are not ready yet, for peer discovery before
+    /// readiness, enabled with the `publishNotReadyAddresses` option.
+    fn publish_not_ready_addresses(&self) -> bool {
+        options_or_default(self).publish_not_ready_addresses
+    }
+
+    /// Returns the session affinity of the Service, `ClientIP` or `None`, as set
+    /// by the `sessionAffinity` option.
+    fn session_affinity(&self) -> Option<String> {
+        options_or_default(self).session_affinity
+    }
+
+    /// Returns a key for caching the rendered manifests, derived from the inputs
+    /// of the Deployment and Service builders only, so it changes exactly when
+    /// the rendered output would. The env is resolved first, as a map does not
+    /// serialize in a stable order.
+    fn render_cache_key(&self) -> String {
+        let spec = &self.spec;
+        let services = spec.character.deploy.as_ref().and_then(|deploy| deploy.services.as_ref());
+        let inputs = (
+            spec.build_fingerprint(),
+            self.docker_ref(),
+            self.container_name(),
+            (self.image_pull_policy(), self.image_pull_secrets()),
+            self.resource_requirements(),
+            (self.liveness_probe(), self.readiness_probe()),
+            self.environments().0,
+            (services, self.port_mappings()),
+            self.deploy_timeout_seconds(),
+            (self.publish_not_ready_addresses(), self.session_affinity()),
+            (self.replicas(), self.canary()),
+            self.shared_artifacts(),
+        );
+        hash(&inputs).unwrap_or_default()
+    }
+
+    /// Returns the number of pods the workload of the actor is sized to: the
+    /// replicas the playbook overrides, recorded by the controller in the
+    /// `amphitheatre.app/replicas` annotation, or else the `replicas` option,
+    /// one by default. A negative value is ignored with a warning.
+    fn replicas(&self) -> i32 {
+        let recorded = self.annotations().get(REPLICAS_KEY).and_then(|value| value.parse::<i32>().ok());
+        match recorded.or(options_or_default(self).replicas) {
+            Some(replicas) if replicas >= 0 => replicas,
+            Some(replicas) => {
+                warn!("Ignore the replicas of actor {}: {} is negative", self.name_any(), replicas);
+                1
+            }
+            None => 1,
+        }
+    }
+
+    /// Returns the compute resources of the main container, read from the
+    /// `resources` option as a Kubernetes `ResourceRequirements`, like
+    /// `{"requests": {"cpu": "100m"}}`.
+    fn resource_requirements(&self) -> Option<ResourceRequirements> {
+        options_or_default(self).resources
+    }
+
+    /// Returns the compute resources requested by all the replicas of the actor,
+    /// over all the containers of its pod.
+    fn footprint(&self) -> ResourceFootprint {
+        ResourceFootprint::of_pod(&application::pod(self), self.replicas())
+    }
+
+    /// Returns the additional image references to push after the primary one,
+    /// from the tags of the `postBuildTags` option like `["latest", "${reference}"]`,
+    /// and the versioned tag if any. `${reference}` is the tag or branch of the
+    /// source, and `${commit}` its revision. Tags that can not be resolved are skipped.
+    fn resolved_post_tags(&self) -> Vec<String> {
+        let mut tags = options_or_default(self).post_build_tags;
+        tags.extend(self.versioned_tag());
+
+        let source = self.spec.source.as_ref();
+        let reference = source.and_then(|source| source.tag.as_ref().or(source.branch.as_ref()));
+        let commit = source.and_then(|source| source.rev.as_ref());
+        let repository = image_repository(&self.spec.image);
+
+        let mut references: Vec<String> = vec![];
+        for tag in tags {
+            if (tag.contains("${reference}") && reference.is_none()) || (tag.contains("${commit}") && commit.is_none())
+            {
+                continue;
+            }
+
+            let tag =
+                tag.replace("${reference}", reference.map_or("", |v| v)).replace("${commit}", commit.map_or("", |v| v));
+            let tag = sanitize_tag(&tag);
+            let reference = format!("{}:{}", repository, tag);
+            if !tag.is_empty() && reference != self.spec.image && !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+
+        references
+    }
+
+    /// Returns the semantic version of the source tag without the leading `v`,
+    /// e.g. `1.2.3` for `v1.2.3`, enabled with the `tagFromReference` option.
+    /// When the reference is not a version, the image keeps the commit tag only,
+    /// and a warning is logged.
+    fn versioned_tag(&self) -> Option<String> {
+        if !options_or_default(self).tag_from_reference {
+            return None;
+        }
+
+        let source = self.spec.source.as_ref()?;
+        let reference = source.tag.as_ref().or(source.branch.as_ref())?;
+        let version = reference.strip_prefix('v').unwrap_or(reference);
+        if source.tag.is_some() && is_semver(version) {
+            return Some(version.to_string());
+        }
+
+        warn!(
+            "The reference {} of actor {} is not a semantic version, tagging with the commit",
+            reference,
+            self.name_any()
+        );
+        None
+    }
+
+    /// Returns the cron schedule of the actor, read from the `schedule` option,
+    /// like `*/5 * * * *` or `@daily`. Scheduled actors run as a CronJob instead
+    /// of a Deployment. An invalid schedule yields `None`.
+    fn cron_schedule(&self) -> Option<String> {
+        let schedule = options_or_default(self).schedule?;
+        let schedule = schedule.trim();
+        match validate_schedule(schedule) {
+            Ok(()) => Some(schedule.to_string()),
+            Err(err) => {
+                warn!("Ignore the schedule of actor {}: {}", self.name_any(), err);
+                None
+            }
+        }
+    }
+
+    /// Returns the artifacts of the build served by the sidecars of the actor,
+    /// read from the `sharedArtifacts` option, see [`SharedArtifact`].
+    fn shared_artifacts(&self) -> Vec<SharedArtifact> {
+        options_or_default(self).shared_artifacts
+    }
+
+    /// Returns how the CronJob of a scheduled actor treats overlapping runs, read
+    /// from the `concurrencyPolicy` option, one of `Allow`, `Forbid` or `Replace`.
+    /// Runs do not overlap by default.
+    fn concurrency_policy(&self) -> String {
+        match options_or_default(self).concurrency_policy.as_deref() {
+            Some(policy @ ("Allow" | "Forbid" | "Replace")) => policy.to_string(),
+            Some(policy) => {
+                warn!("Invalid concurrency policy {} of actor {}, using Forbid", policy, self.name_any());
+                "Forbid".to_string()
+            }
+            None => "Forbid".to_string(),
+        }
+    }
+
+    /// Returns true if the actor is rebuilt on pushes to its repository,
+    /// enabled with the `sync` option.
+    fn sync(&self) -> bool {
+        options_or_default(self).sync
+    }
+
+    /// Returns the patterns of the references whose pushes rebuild the actor,
+    /// read from the `syncRefs` option like `["main", "release/*"]`.
+    /// Empty if only the source reference is tracked.
+    fn sync_refs(&self) -> Vec<String> {
+        options_or_default(self).sync_refs
+    }
+
+    /// Returns the management mode of the actor, derived from the sync flag.
+    fn management_mode(&self) -> ManagementMode {
+        if self.sync() {
+            ManagementMode::Sync
+        } else {
+            ManagementMode::GitOps
+        }
+    }
+}
+
+/// Check if the value is a semantic version like `1.2.3`, `1.0.0-rc.1` or `1.0.0+build.5`.
+fn is_semver(value: &str) -> bool {
+    let (version, build) = value.split_once('+').map_or((value, None), |(version, build)| (version, Some(build)));
+    let (core, pre) = version.split_once('-').map_or((version, None), |(core, pre)| (core, Some(pre)));
+
+    let numbers: Vec<&str> = core.split('.').collect();
+    let valid_number =
+        |n: &&str| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) && (n.len() == 1 || !n.starts_with('0'));
+    let valid_identifiers =
+        |s: &str| s.split('.').all(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+
+    numbers.len() == 3
+        && numbers.iter().all(valid_number)
+        && pre.into_iter().all(valid_identifiers)
+        && build.into_iter().all(valid_identifiers)
+}
+
+/// Returns the options of the actor, or the defaults if they are malformed,
+/// which the validation of the actor rejects before it is reconciled.
+fn options_or_default(actor: &Actor) -> ActorOptions {
+    actor.options().unwrap_or_else(|err| {
+        warn!("Ignore the options of actor {}: {}", actor.name_any(), err);
+        ActorOptions::default()
+    })
+}
+
+/// Parse the RFC 3339 timestamp in the annotation of the actor.
+fn timestamp(actor: &Actor, key: &str) -> Option<DateTime<Utc>> {
+    let value = actor.annotations().get(key)?;
+    DateTime::parse_from_rfc3339(value).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// Strip the tag and digest from an image reference, keeping the registry port.
+fn image_repository(image: &str) -> &str {
+    let image = image.split('@').next().unwrap_or_default();
+    match image.rfind(':') {
+        Some(index) if !image[index..].contains('/') => &image[..index],
+        _ => image,
+    }
+}
+
+/// Returns the tag of the image reference, if any. Digest references have
+/// no tag to go stale, so they yield their digest instead.
+fn image_tag(image: &str) -> Option<&str> {
+    if let Some((_, digest)) = image.split_once('@') {
+        return Some(digest);
+    }
+    let repository = image_repository(image);
+    image.get(repository.len() + 1..)
+}
+
+/// Replace the characters not allowed in an image tag, such as the `/` of
+/// a branch like `feature/login`, and limit it to 128 characters.
+fn sanitize_tag(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' { c } else { '-' })
+        .take(128)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::{ActorSpec, ActorState, ActorStatus};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    use amp_common::schema::{Deploy, GitReference};
+
+    use super::*;
+    use crate::options::OPTIONS_VERSION;
+
+    fn blank() -> Actor {
+        Actor::new("test", ActorSpec::default())
+    }
+
+    /// Returns an actor with the given options, as written by the user.
+    fn actor(mut options: Value) -> Actor {
+        options["version"] = OPTIONS_VERSION.into();
+        let mut actor = blank();
+        actor.annotations_mut().insert(OPTIONS_KEY.into(), options.to_string());
+        actor
+    }
+
+    /// Returns an actor with the given record of the controller.
+    fn recorded(key: &str, value: &str) -> Actor {
+        let mut actor = blank();
+        actor.annotations_mut().insert(key.into(), value.into());
+        actor
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(blank().options().unwrap(), ActorOptions::default());
+
+        let mut actor = blank();
+        actor.set_options(&ActorOptions { replicas: Some(3), ..Default::default() });
+        assert_eq!(actor.options().unwrap().replicas, Some(3));
+        assert_eq!(actor.replicas(), 3);
+    }
+
+    #[test]
+    fn test_malformed_options() {
+        let mut actor = actor(json!({"replicas": 3, "sink": true}));
+        assert!(actor.options().unwrap_err().to_string().starts_with("InvalidOptions: unknown field `sink`"));
+        assert_eq!(actor.replicas(), 1);
+
+        actor.annotations_mut().insert(OPTIONS_KEY.into(), r#"{"replicas": 3}"#.into());
+        assert_eq!(actor.options().unwrap_err().to_string(), "InvalidOptions: version is required, expected `v1`");
+    }
+
+    #[test]
+    fn test_build_name() {
+        let mut actor = blank();
+        actor.spec.name = "MyApp_v2".into();
+        assert_eq!(actor.build_name(), "myapp-v2-builder");
+
+        actor.spec.name = "a".repeat(64);
+        assert_eq!(actor.build_name().len(), 63);
+    }
+
+    #[test]
+    fn test_metrics_labels() {
+        let mut actor = blank();
+        actor.spec.name = "api".into();
+        actor.metadata.namespace = Some("default".into());
+        actor.spec.source = Some(GitReference { rev: Some("abc123".into()), ..Default::default() });
+
+        let labels = actor.metrics_labels();
+        let keys: Vec<_> = labels.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["amphitheatre.app/actor", "amphitheatre.app/commit", "amphitheatre.app/namespace"]);
+        assert_eq!(labels["amphitheatre.app/commit"], "abc123");
+    }
+
+    #[test]
+    fn test_docker_ref() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+
+        let mut actor = blank();
+        actor.spec.image = "registry.example.com:5000/team/app:abc123".into();
+        assert_eq!(actor.image_digest(), None);
+        assert_eq!(actor.docker_ref(), "registry.example.com:5000/team/app:abc123");
+
+        actor.annotations_mut().insert(IMAGE_DIGEST_KEY.into(), digest.clone());
+        assert_eq!(actor.image_digest(), Some(digest.clone()));
+        assert_eq!(actor.docker_ref(), format!("registry.example.com:5000/team/app@{}", digest));
+    }
+
+    #[test]
+    fn test_docker_ref_with_malformed_digest() {
+        let mut actor = recorded(IMAGE_DIGEST_KEY, "sha256:not-a-digest");
+        actor.spec.image = "app:abc123".into();
+
+        assert_eq!(actor.image_digest(), None);
+        assert_eq!(actor.docker_ref(), "app:abc123");
+    }
+
+    #[test]
+    fn test_container_name() {
+        let mut actor = blank();
+        actor.spec.name = "test".into();
+        assert_eq!(actor.container_name(), "test");
+
+        actor.set_options(&ActorOptions { container_name: Some("app".into()), ..Default::default() });
+        assert_eq!(actor.container_name(), "app");
+    }
+
+    #[test]
+    fn test_dockerfile_target() {
+        assert_eq!(blank().dockerfile_target(), None);
+        assert_eq!(actor(json!({"dockerfileTarget": "runtime"})).dockerfile_target(), Some("runtime".into()));
+        assert_eq!(actor(json!({"dockerfileTarget": " "})).dockerfile_target(), None);
+    }
+
+    #[test]
+    fn test_platforms_or_default() {
+        assert_eq!(blank().platforms_or_default(), vec!["linux/amd64"]);
+        assert_eq!(
+            actor(json!({"platforms": ["linux/arm64", "linux/amd64", "linux/arm64"]})).platforms_or_default(),
+            vec!["linux/arm64", "linux/amd64"]
+        );
+        assert_eq!(actor(json!({"platforms": []})).platforms_or_default(), vec!["linux/amd64"]);
+    }
+
+    #[test]
+    fn test_platforms() {
+        assert!(blank().platforms().is_empty());
+        assert_eq!(
+            actor(json!({"platforms": ["arm64", "linux/arm/v7", "arm64"]})).platforms(),
+            vec!["arm64", "linux/arm/v7"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_platforms() {
+        assert_eq!(actor(json!({"platforms": "linux/arm64"})).platforms_or_default(), vec!["linux/amd64"]);
+        assert_eq!(actor(json!({"platforms": ["arm64", "linux/arm64"]})).platforms_or_default(), vec!["linux/arm64"]);
+    }
+
+    #[test]
+    fn test_build_timeout_seconds() {
+        let timeout = |value: &str| actor(json!({ "buildTimeout": value })).build_timeout_seconds();
+
+        assert_eq!(blank().build_timeout_seconds(), None);
+        assert_eq!(timeout("30m"), Some(1800));
+        assert_eq!(timeout("1h30m"), Some(5400));
+        assert_eq!(timeout("90"), Some(90));
+        assert_eq!(timeout("45s"), Some(45));
+    }
+
+    #[test]
+    fn test_invalid_build_timeout() {
+        let timeout = |value: &str| actor(json!({ "buildTimeout": value })).build_timeout_seconds();
+
+        assert_eq!(timeout("abc"), None);
+        assert_eq!(timeout("-5m"), None);
+        assert_eq!(timeout("0s"), None);
+    }
+
+    #[test]
+    fn test_build_timed_out() {
+        let time = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc);
+
+        let mut actor = actor(json!({"buildTimeout": "30m"}));
+        assert!(!actor.build_timed_out(time("2024-01-01T11:00:00Z")));
+
+        actor.annotations_mut().insert(BUILD_STARTED_AT_KEY.into(), "2024-01-01T10:00:00Z".into());
+        assert!(!actor.build_timed_out(time("2024-01-01T10:29:59Z")));
+        assert!(actor.build_timed_out(time("2024-01-01T10:30:00Z")));
+    }
+
+    #[test]
+    fn test_deploy_timeout_seconds() {
+        let timeout = |value: &str| actor(json!({ "deployTimeout": value })).deploy_timeout_seconds();
+
+        assert_eq!(blank().deploy_timeout_seconds(), None);
+        assert_eq!(timeout("10m"), Some(600));
+        assert_eq!(timeout("1h30m"), Some(5400));
+        assert_eq!(timeout("0s"), None);
+        assert_eq!(timeout("soon"), None);
+    }
+
+    #[test]
+    fn test_deployment_strategy() {
+        let strategy = |value: &str| actor(json!({ "deploymentStrategy": value })).deployment_strategy();
+
+        assert_eq!(blank().deployment_strategy(), DeploymentStrategy::Rolling);
+        assert_eq!(strategy("rolling"), DeploymentStrategy::Rolling);
+        assert_eq!(strategy("blue-green"), DeploymentStrategy::BlueGreen);
+        assert_eq!(strategy("canary"), DeploymentStrategy::Rolling);
+    }
+
+    #[test]
+    fn test_replicas() {
+        assert_eq!(blank().replicas(), 1);
+        assert_eq!(actor(json!({"replicas": 3})).replicas(), 3);
+        assert_eq!(actor(json!({"replicas": 0})).replicas(), 0);
+        assert_eq!(actor(json!({"replicas": -2})).replicas(), 1);
+        assert_eq!(actor(json!({"replicas": "many"})).replicas(), 1);
+    }
+
+    #[test]
+    fn test_replicas_overridden_by_playbook() {
+        let mut actor = actor(json!({"replicas": 3}));
+        actor.annotations_mut().insert(REPLICAS_KEY.into(), "5".into());
+        assert_eq!(actor.replicas(), 5);
+
+        actor.annotations_mut().insert(REPLICAS_KEY.into(), "many".into());
+        assert_eq!(actor.replicas(), 3);
+    }
+
+    #[test]
+    fn test_cache_image() {
+        let actor = |cache: Value| {
+            let mut actor = actor(json!({ "buildCache": cache }));
+            actor.spec.image = "registry.local/api:v1".into();
+            actor
+        };
+
+        assert_eq!(actor(Value::Null).cache_image(), None);
+        assert_eq!(actor(json!({"enabled": false})).cache_image(), None);
+        assert_eq!(actor(json!(true)).cache_image(), None);
+        assert_eq!(actor(json!({"enabled": true})).cache_image(), Some("registry.local/api-cache".into()));
+        assert_eq!(
+            actor(json!({"enabled": true, "repo": "registry.local/cache/api"})).cache_image(),
+            Some("registry.local/cache/api".into())
+        );
+    }
+
+    #[test]
+    fn test_build_secrets() {
+        assert_eq!(blank().build_secrets().unwrap(), vec![]);
+
+        let secrets = actor(json!({"buildSecrets": [
+            {"id": "npm", "name": "npm-token", "key": "token"},
+            {"id": "pip", "name": "pip", "key": "conf"},
+        ]}));
+        assert_eq!(
+            secrets.build_secrets().unwrap(),
+            vec![
+                BuildSecret { id: "npm".into(), name: "npm-token".into(), key: "token".into() },
+                BuildSecret { id: "pip".into(), name: "pip".into(), key: "conf".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_build_secrets() {
+        let error = |secrets: Value| actor(json!({ "buildSecrets": secrets })).build_secrets().unwrap_err().to_string();
+
+        assert_eq!(
+            error(json!([{"id": " ", "name": "npm-token", "key": "token"}])),
+            "InvalidBuild: build secret id must not be empty"
+        );
+        assert_eq!(
+            error(json!([{"id": "npm", "name": "", "key": "token"}])),
+            "InvalidBuild: build secret `npm` must name a secret and its key"
+        );
+        assert_eq!(
+            error(json!([{"id": "npm", "name": "a", "key": "token"}, {"id": "npm", "name": "b", "key": "token"}])),
+            "InvalidBuild: build secret id `npm` is used more than once"
+        );
+    }
+
+    #[test]
+    fn test_image_pull_secrets() {
+        assert_eq!(blank().image_pull_secrets(), vec![]);
+        assert_eq!(
+            actor(json!({"imagePullSecrets": ["registry-credentials", "ghcr.io"]})).image_pull_secrets(),
+            vec![
+                LocalObjectReference { name: "registry-credentials".into() },
+                LocalObjectReference { name: "ghcr.io".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_image_pull_secrets() {
+        let actor = actor(json!({"imagePullSecrets": ["Registry_Credentials", "registry"]}));
+        assert_eq!(actor.image_pull_secret_names(), vec!["Registry_Credentials", "registry"]);
+        assert_eq!(actor.image_pull_secrets(), vec![LocalObjectReference { name: "registry".into() }]);
+    }
+
+    #[test]
+    fn test_canary() {
+        assert_eq!(blank().canary(), None);
+        assert_eq!(
+            actor(json!({"canary": {"weight": 10, "steps": [50, 100]}})).canary(),
+            Some(CanaryConfig { weight: 10, steps: vec![50, 100] })
+        );
+    }
+
+    #[test]
+    fn test_active_color() {
+        assert_eq!(blank().active_color(), None);
+        assert_eq!(recorded(ACTIVE_COLOR_KEY, "green").active_color(), Some(Color::Green));
+        assert_eq!(recorded(ACTIVE_COLOR_KEY, "red").active_color(), None);
+    }
+
+    #[test]
+    fn test_network_policy() {
+        assert!(!blank().network_policy());
+        assert!(actor(json!({"networkPolicy": true})).network_policy());
+    }
+
+    #[test]
+    fn test_egress_policy() {
+        assert!(!blank().egress_policy());
+        assert!(!actor(json!({"egressPolicy": false})).egress_policy());
+        assert!(actor(json!({"egressPolicy": true})).egress_policy());
+    }
+
+    #[test]
+    fn test_port_mappings() {
+        assert!(blank().port_mappings().is_empty());
+        assert!(actor(json!({"ports": "80:8080"})).port_mappings().is_empty());
+
+        let mappings = actor(json!({"ports": {"80": {"targetPort": 8080}}})).port_mappings();
+        assert_eq!(mappings[&80], PortMapping { target_port: Some(8080), node_port: None });
+    }
+
+    #[test]
+    fn test_pre_build_command() {
+        assert_eq!(blank().pre_build_command(), None);
+        assert_eq!(
+            actor(json!({"preBuild": ["make", "test"]})).pre_build_command(),
+            Some(vec!["make".into(), "test".into()])
+        );
+        assert_eq!(actor(json!({"preBuild": []})).pre_build_command(), None);
+        assert_eq!(actor(json!({"preBuild": "make test"})).pre_build_command(), None);
+    }
+
+    #[test]
+    fn test_resolved_post_tags() {
+        let mut actor = actor(json!({"postBuildTags": ["latest", "${reference}", "sha-${commit}"]}));
+        actor.spec.image = "registry.local:5000/amp/api:abc123".into();
+        actor.spec.source = Some(GitReference {
+            branch: Some("feature/login".into()),
+            rev: Some("abc123".into()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            actor.resolved_post_tags(),
+            vec![
+                "registry.local:5000/amp/api:latest",
+                "registry.local:5000/amp/api:feature-login",
+                "registry.local:5000/amp/api:sha-abc123",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolved_post_tags_unresolved() {
+        assert!(blank().resolved_post_tags().is_empty());
+
+        let mut actor = actor(json!({"postBuildTags": ["${reference}", "${commit}", "latest"]}));
+        actor.spec.image = "amp/api".into();
+
+        assert_eq!(actor.resolved_post_tags(), vec!["amp/api:latest"]);
+    }
+
+    #[test]
+    fn test_build_duration() {
+        let mut actor = recorded(BUILD_STARTED_AT_KEY, "2024-01-01T10:00:00Z");
+        assert_eq!(actor.build_duration(), None);
+
+        actor.annotations_mut().insert(BUILD_FINISHED_AT_KEY.into(), "2024-01-01T10:02:30Z".into());
+        assert_eq!(actor.build_duration(), Some(Duration::from_secs(150)));
+    }
+
+    fn built(fingerprint: &str, commit: &str) -> Actor {
+        let mut actor = recorded(LAST_BUILD_FINGERPRINT_KEY, fingerprint);
+        actor.annotations_mut().insert(LAST_BUILD_COMMIT_KEY.into(), commit.into());
+        actor
+    }
+
+    #[test]
+    fn test_should_build() {
+        assert!(built("f1", "abc123").should_build("f1", "def456"));
+        assert!(built("f1", "abc123").should_build("f2", "abc123"));
+        assert!(!built("f1", "abc123").should_build("f1", "abc123"));
+        assert!(blank().should_build("f1", "abc123"));
+        assert!(recorded(LAST_BUILD_FINGERPRINT_KEY, "f1").should_build("f1", "abc123"));
+    }
+
+    #[test]
+    fn test_build_duration_invalid() {
+        let mut actor = recorded(BUILD_STARTED_AT_KEY, "2024-01-01T10:00:00Z");
+
+        // Finished before the build started, e.g. left over from a previous build
+        actor.annotations_mut().insert(BUILD_FINISHED_AT_KEY.into(), "2024-01-01T09:00:00Z".into());
+        assert_eq!(actor.build_duration(), None);
+
+        actor.annotations_mut().insert(BUILD_FINISHED_AT_KEY.into(), "yesterday".into());
+        assert_eq!(actor.build_duration(), None);
+    }
+
+    #[test]
+    fn test_otel_attributes() {
+        let mut actor = blank();
+        actor.spec.name = "test".into();
+        actor.spec.source = Some(GitReference {
+            repo: "https://github.com/amphitheatre-app/amphitheatre.git".into(),
+            rev: Some("abc123".into()),
+            ..Default::default()
+        });
+        let mut status = ActorStatus::default();
+        status.conditions.push(ActorState::building());
+        actor.status = Some(status);
+
+        let attributes = actor.otel_attributes();
+        let keys: Vec<_> = attributes.iter().map(|(key, _)| *key).collect();
+        assert_eq!(
+            keys,
+            vec!["service.name", "vcs.repository.url.full", "vcs.repository.ref.revision", "amphitheatre.actor.state"]
+        );
+        assert_eq!(attributes[0].1, "test");
+        assert_eq!(attributes[2].1, "abc123");
+        assert_eq!(attributes[3].1, "Building");
+    }
+
+    #[test]
+    fn test_otel_attributes_after_rebuild() {
+        let mut actor = blank();
+        let mut status = ActorStatus::default();
+        status.set_condition(ActorState::building());
+        status.set_condition(ActorState::running(true, "AutoRun", None));
+        status.set_condition(ActorState::building());
+        actor.status = Some(status);
+
+        // The last condition is the one of Running, which turned false
+        let conditions = &actor.status.as_ref().unwrap().conditions;
+        assert_eq!(conditions.last().unwrap().status, "False");
+
+        let attributes = actor.otel_attributes();
+        assert_eq!(attributes.last().unwrap(), &("amphitheatre.actor.state", "Building".to_string()));
+    }
+
+    #[test]
+    fn test_publish_not_ready_addresses() {
+        assert!(!blank().publish_not_ready_addresses());
+        assert!(actor(json!({"publishNotReadyAddresses": true})).publish_not_ready_addresses());
+    }
+
+    #[test]
+    fn test_session_affinity() {
+        assert_eq!(blank().session_affinity(), None);
+        assert_eq!(actor(json!({"sessionAffinity": "ClientIP"})).session_affinity(), Some("ClientIP".into()));
+    }
+
+    fn tagged(reference: GitReference) -> Actor {
+        let mut actor = actor(json!({"tagFromReference": true}));
+        actor.spec.source = Some(reference);
+        actor
+    }
+
+    #[test]
+    fn test_versioned_tag() {
+        let actor = tagged(GitReference { tag: Some("v1.2.3".into()), ..Default::default() });
+        assert_eq!(actor.versioned_tag(), Some("1.2.3".into()));
+
+        let actor = tagged(GitReference { tag: Some("2.0.0-rc.1".into()), ..Default::default() });
+        assert_eq!(actor.versioned_tag(), Some("2.0.0-rc.1".into()));
+    }
+
+    #[test]
+    fn test_versioned_tag_fallback() {
+        let actor = tagged(GitReference { tag: Some("release-1".into()), ..Default::default() });
+        assert_eq!(actor.versioned_tag(), None);
+
+        let actor = tagged(GitReference { branch: Some("v1.2.3".into()), ..Default::default() });
+        assert_eq!(actor.versioned_tag(), None);
+
+        let mut actor = tagged(GitReference { tag: Some("v1.2.3".into()), ..Default::default() });
+        actor.set_options(&ActorOptions::default());
+        assert_eq!(actor.versioned_tag(), None);
+    }
+
+    #[test]
+    fn test_is_semver() {
+        assert!(is_semver("1.2.3"));
+        assert!(is_semver("10.0.0+build.5"));
+        assert!(!is_semver("1.2"));
+        assert!(!is_semver("01.2.3"));
+        assert!(!is_semver("1.2.3-"));
+        assert!(!is_semver("main"));
+    }
+
+    #[test]
+    fn test_sync() {
+        assert!(!blank().sync());
+        assert!(!actor(json!({"sync": false})).sync());
+        assert!(actor(json!({"sync": true})).sync());
+    }
+
+    #[test]
+    fn test_sync_refs() {
+        assert!(blank().sync_refs().is_empty());
+        assert_eq!(actor(json!({"syncRefs": ["main", "release/*"]})).sync_refs(), vec!["main", "release/*"]);
+    }
+
+    #[test]
+    fn test_management_mode() {
+        assert_eq!(blank().management_mode(), ManagementMode::GitOps);
+        assert_eq!(actor(json!({"sync": false})).management_mode(), ManagementMode::GitOps);
+        assert_eq!(actor(json!({"sync": true})).management_mode(), ManagementMode::Sync);
+    }
+
+    #[test]
+    fn test_render_cache_key() {
+        let mut actor = blank();
+        actor.spec.character.deploy = Some(Deploy {
+            env: Some(HashMap::from([("A".into(), "1".into()), ("B".into(), "2".into())])),
+            ..Default::default()
+        });
+        let key = actor.render_cache_key();
+
+        actor.spec.character.meta.description = Some("Updated description".into());
+        assert_eq!(actor.render_cache_key(), key);
+
+        actor.spec.character.deploy.as_mut().unwrap().env.as_mut().unwrap().insert("B".into(), "3".into());
+        assert_ne!(actor.render_cache_key(), key);
+
+        // The artifacts shared with the sidecars are rendered into the pod too
+        let key = actor.render_cache_key();
+        let artifact = SharedArtifact { name: "docs".into(), ..Default::default() };
+        actor.set_options(&ActorOptions { shared_artifacts: vec![artifact], ..Default::default() });
+        assert_ne!(actor.render_cache_key(), key);
+    }
+
+    #[test]
+    fn test_desired_fingerprint() {
+        let mut actor = blank();
+        actor.spec.image = "registry.local/amp/api:abc123".into();
+        let fingerprint = actor.desired_fingerprint();
+
+        // The records of the controller and the status are not worth an event
+        actor.annotations_mut().insert(FAILURE_COUNT_KEY.into(), "2".into());
+        actor.annotations_mut().insert(BUILD_STARTED_AT_KEY.into(), "2024-01-01T10:00:00Z".into());
+        actor.annotations_mut().insert(RECONCILED_FINGERPRINT_KEY.into(), fingerprint.clone());
+        actor.status = Some(ActorStatus::default());
+        assert_eq!(actor.desired_fingerprint(), fingerprint);
+        assert_eq!(actor.reconciled_fingerprint(), Some(fingerprint.clone()));
+
+        actor.spec.image = "registry.local/amp/api:def456".into();
+        assert_ne!(actor.desired_fingerprint(), fingerprint);
+
+        actor.spec.image = "registry.local/amp/api:abc123".into();
+        actor.set_options(&ActorOptions { container_name: Some("app".into()), ..Default::default() });
+        assert_ne!(actor.desired_fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_env_file() {
+        let env_file = |value: &str| actor(json!({ "envFile": value })).env_file();
+
+        assert_eq!(blank().env_file(), None);
+        assert_eq!(env_file(" "), None);
+        assert_eq!(env_file(".env"), Some(".env".into()));
+        assert_eq!(env_file("/config/.env"), Some("config/.env".into()));
+    }
+
+    #[test]
+    fn test_image_pull_policy() {
+        let policy = |value: &str| actor(json!({ "imagePullPolicy": value })).image_pull_policy();
+
+        assert_eq!(blank().image_pull_policy(), "Always");
+        assert_eq!(policy("IfNotPresent"), "IfNotPresent");
+        assert_eq!(policy("Sometimes"), "Always");
+    }
+
+    #[test]
+    fn test_pull_policy_warnings_with_digest() {
+        let mut actor = blank();
+        actor.spec.image = "registry.example.com/app@sha256:abcdef".into();
+        assert_eq!(
+            actor.pull_policy_warnings(),
+            vec![
+                "image `registry.example.com/app@sha256:abcdef` is pinned by digest, \
+                pulling it `Always` is wasteful, consider `IfNotPresent`"
+            ]
+        );
+
+        actor.set_options(&ActorOptions { image_pull_policy: Some("IfNotPresent".into()), ..Default::default() });
+        assert!(actor.pull_policy_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_pull_policy_warnings_with_latest() {
+        let mut actor = actor(json!({"imagePullPolicy": "IfNotPresent"}));
+        actor.spec.image = "registry.example.com:5000/app:latest".into();
+        assert_eq!(
+            actor.pull_policy_warnings(),
+            vec![
+                "image `registry.example.com:5000/app:latest` uses the `latest` tag, \
+                pulling it `IfNotPresent` may run a stale image"
+            ]
+        );
+
+        actor.spec.image = "registry.example.com:5000/app".into();
+        assert_eq!(actor.pull_policy_warnings().len(), 1);
+
+        actor.spec.image = "registry.example.com:5000/app:v1.0.0".into();
+        assert!(actor.pull_policy_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_next_backoff() {
+        let base = Duration::from_secs(60);
+        let max = Duration::from_secs(3600);
+
+        let backoff = |count: &str| recorded(FAILURE_COUNT_KEY, count).next_backoff(0, base, max);
+        assert_eq!(blank().next_backoff(0, base, max), Duration::from_secs(60));
+        assert_eq!(backoff("1"), Duration::from_secs(120));
+        assert_eq!(backoff("3"), Duration::from_secs(480));
+        assert_eq!(backoff("6"), max);
+        assert_eq!(backoff("64"), max);
+        assert_eq!(backoff("-1"), base);
+
+        // The consecutive errors of the reconciles add to the failures
+        assert_eq!(blank().next_backoff(2, base, max), Duration::from_secs(240));
+        assert_eq!(recorded(FAILURE_COUNT_KEY, "1").next_backoff(2, base, max), Duration::from_secs(480));
+        assert_eq!(blank().next_backoff(u32::MAX, base, max), max);
+        assert_eq!(recorded(FAILURE_COUNT_KEY, "1").next_backoff(u32::MAX, base, max), max);
+    }
+
+    #[test]
+    fn test_cron_schedule() {
+        let schedule = |value: &str| actor(json!({ "schedule": value })).cron_schedule();
+
+        assert_eq!(blank().cron_schedule(), None);
+        assert_eq!(schedule(" 0 3 * * MON-FRI "), Some("0 3 * * MON-FRI".into()));
+        assert_eq!(schedule("@daily"), Some("@daily".into()));
+        assert_eq!(schedule("every day"), None);
+    }
+
+    #[test]
+    fn test_resource_requirements() {
+        use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+        assert_eq!(blank().resource_requirements(), None);
+        assert_eq!(actor(json!({"resources": "100m"})).resource_requirements(), None);
+
+        let value = json!({"requests": {"cpu": "100m", "memory": "128Mi"}, "limits": {"memory": "256Mi"}});
+        let resources = actor(json!({ "resources": value })).resource_requirements().unwrap();
+        let requests = resources.requests.unwrap();
+        assert_eq!(requests["cpu"], Quantity("100m".into()));
+        assert_eq!(requests["memory"], Quantity("128Mi".into()));
+        assert_eq!(resources.limits.unwrap()["memory"], Quantity("256Mi".into()));
+    }
+
+    #[test]
+    fn test_footprint() {
+        let actor = actor(json!({"replicas": 3, "resources": {"requests": {"cpu": "100m", "memory": "128Mi"}}}));
+
+        assert_eq!(actor.footprint(), ResourceFootprint { cpu_millis: 300, memory_bytes: 3 * 128 * 1024 * 1024 });
+    }
+
+    #[test]
+    fn test_probes() {
+        use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+
+        assert_eq!(blank().liveness_probe(), None);
+        assert_eq!(actor(json!({"readinessProbe": "/healthz"})).readiness_probe(), None);
+
+        let value = r#"{"httpGet":{"path":"/healthz","port":8080},"initialDelaySeconds":5,"periodSeconds":10}"#;
+        let probe = actor(json!({ "livenessProbe": serde_json::from_str::<Value>(value).unwrap() }));
+        let probe = probe.liveness_probe().unwrap();
+        let http_get = probe.http_get.as_ref().unwrap();
+        assert_eq!(http_get.path, Some("/healthz".into()));
+        assert_eq!(http_get.port, IntOrString::Int(8080));
+        assert_eq!(probe.initial_delay_seconds, Some(5));
+        assert_eq!(probe.period_seconds, Some(10));
+        assert_eq!(serde_json::to_string(&probe).unwrap(), value);
+
+        let probe = actor(json!({"readinessProbe": {"tcpSocket": {"port": "tcp-8080"}}})).readiness_probe();
+        assert_eq!(probe.unwrap().tcp_socket.unwrap().port, IntOrString::String("tcp-8080".into()));
+    }
+
+    #[test]
+    fn test_environments() {
+        let mut actor = actor(json!({"env": {
+            "LEVEL": "debug",
+            "PASSWORD": {"secretKeyRef": {"name": "db", "key": "password"}},
+        }}));
+        let env = HashMap::from([("LEVEL".to_string(), "info".to_string()), ("PORT".to_string(), "8080".to_string())]);
+        actor.spec.character.deploy = Some(Deploy { env: Some(env), ..Default::default() });
+
+        let (environments, warnings) = actor.environments();
+        let names: Vec<_> = environments.iter().map(|var| var.name.as_str()).collect();
+        assert_eq!(names, vec!["LEVEL", "PASSWORD", "PORT"]);
+        assert_eq!(environments[0].value, Some("debug".into()));
+        assert_eq!(environments[1].value, None);
+        assert_eq!(environments[1].value_from.as_ref().unwrap().secret_key_ref.as_ref().unwrap().key, "password");
+        assert_eq!(environments[2].value, Some("8080".into()));
+        assert_eq!(warnings, vec!["LEVEL from options shadows the one from character"]);
+    }
+
+    #[test]
+    fn test_environments_with_invalid_sources() {
+        let actor = actor(json!({"env": {"PASSWORD": {"secretKeyRef": {"name": "db", "key": ""}}, "PORT": "8080"}}));
+        let (environments, warnings) = actor.environments();
+
+        assert_eq!(environments.len(), 1);
+        assert_eq!(environments[0].name, "PORT");
+        assert_eq!(warnings, vec!["InvalidEnvSource: PASSWORD: the key of the reference is empty"]);
+    }
+
+    #[test]
+    fn test_probe_ports() {
+        assert!(blank().probe_ports().is_empty());
+
+        let probes = |liveness: Value| {
+            actor(json!({"livenessProbe": liveness, "readinessProbe": {"grpc": {"port": 8081}}})).probe_ports()
+        };
+        assert_eq!(probes(json!({"httpGet": {"port": 9090}})), vec![8081, 9090]);
+        assert_eq!(probes(json!({"tcpSocket": {"port": "tcp-8080"}})), vec![8081]);
+    }
+}
@@ -0,0 +1,509 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Component, Path, PathBuf};
+
+use amp_common::resource::ActorSpec;
+use amp_common::schema::{Build, BuildMethod};
+use k8s_openapi::api::core::v1::EnvVar;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::ActorSpecExt;
+use crate::error::{Error, Result};
+
+/// The dependency manifests and lock files of the common ecosystems,
+/// which decide whether the cached layers can be reused.
+const DEPENDENCY_MANIFESTS: &[&str] = &[
+    "package.json",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "Cargo.toml",
+    "Cargo.lock",
+    "go.mod",
+    "go.sum",
+    "requirements.txt",
+    "Pipfile.lock",
+    "poetry.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "pom.xml",
+    "build.gradle",
+];
+
+/// The builder image used for Buildpacks when none is configured.
+pub const DEFAULT_BUILDER: &str = "gcr.io/buildpacks/builder:v1";
+
+/// The file names of a Dockerfile, in order of preference.
+const DOCKERFILE_NAMES: &[&str] = &["Dockerfile", "Containerfile", "dockerfile"];
+
+/// The layer cache of the builds of an actor, pushed to a cache image in
+/// the registry so that the next builds reuse the unchanged layers.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct BuildCache {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+}
+
+/// A secret the builder mounts at `/run/secrets/<id>` for the duration of the
+/// build, like the `--secret id=...` of BuildKit, from the key of a Secret.
+/// Unlike the build env, its value never appears in the spec of the actor.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct BuildSecret {
+    pub id: String,
+    pub name: String,
+    pub key: String,
+}
+
+pub trait BuildSpecExt {
+    fn resolved_context(&self, manifest_path: &str, repo_root: &Path) -> PathBuf;
+    fn cache_key(&self, spec: &ActorSpec, read: &dyn Fn(&Path) -> Option<Vec<u8>>) -> String;
+    fn dockerfile_path(&self, context: &Path) -> Option<PathBuf>;
+    fn detect_dockerfile(&self, context_root: &Path) -> Option<String>;
+    fn resolve_strategy(&self, context_root: &Path) -> BuildMethod;
+    fn strategy(&self) -> Result<BuildMethod>;
+    fn validate_paths(&self, manifest_path: &str, repo_root: &Path) -> Result<()>;
+    fn effective_builder(&self, default: &str) -> String;
+    fn sorted_env(&self) -> Option<Vec<EnvVar>>;
+}
+
+impl BuildSpecExt for Build {
+    /// Returns the build context resolved against the repository root.
+    ///
+    /// An explicit `context` is always relative to the repository root, not to
+    /// the directory of the manifest. When it is not set, the directory that
+    /// contains the manifest is used instead. The result never escapes the
+    /// repository root: absolute paths are treated as relative, and `..`
+    /// components can not climb above the root.
+    fn resolved_context(&self, manifest_path: &str, repo_root: &Path) -> PathBuf {
+        let relative = match &self.context {
+            Some(context) => normalize(Path::new(context)),
+            None => normalize(Path::new(manifest_path).parent().unwrap_or_else(|| Path::new(""))),
+        };
+
+        if relative.as_os_str().is_empty() {
+            return repo_root.to_path_buf();
+        }
+
+        repo_root.join(relative)
+    }
+
+    /// Returns a build cache key derived from the dependency manifests in the
+    /// build context, rather than from the commit, so that commits sharing the
+    /// same dependencies share the cache too. The files are read with `read`,
+    /// given their path relative to the repository root, as the sources live
+    /// in the repository or the build pod rather than in the controller.
+    fn cache_key(&self, spec: &ActorSpec, read: &dyn Fn(&Path) -> Option<Vec<u8>>) -> String {
+        let manifest = spec.normalized_manifest_path();
+        let context = self.resolved_context(&manifest, Path::new(""));
+
+        let mut hasher = Sha256::new();
+        if let Some(config) = &self.dockerfile {
+            hasher.update(format!("dockerfile={}\n", config.dockerfile));
+        }
+        if let Some(config) = &self.buildpacks {
+            hasher.update(format!("builder={}\n", config.builder));
+        }
+        for name in DEPENDENCY_MANIFESTS {
+            let path = context.join(name);
+            if let Some(content) = read(&path) {
+                hasher.update(format!("{}={:x}\n", path.display(), Sha256::digest(content)));
+            }
+        }
+
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
+
+    /// Returns the configured Dockerfile resolved against the build context.
+    /// The `dockerfile` is a path relative to the context, so it is rejected
+    /// when it is absolute or contains `..`, as it would escape the context.
+    fn dockerfile_path(&self, context: &Path) -> Option<PathBuf> {
+        let path = Path::new(&self.dockerfile.as_ref()?.dockerfile);
+        let contained =
+            path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        (contained && !normalize(path).as_os_str().is_empty()).then(|| context.join(path))
+    }
+
+    /// Returns the path of the Dockerfile relative to the context root, the
+    /// explicitly configured one if it exists, or else a detected one.
+    fn detect_dockerfile(&self, context_root: &Path) -> Option<String> {
+        if let Some(config) = &self.dockerfile {
+            let exists = self.dockerfile_path(context_root).is_some_and(|path| path.is_file());
+            return exists.then(|| config.dockerfile.clone());
+        }
+
+        DOCKERFILE_NAMES.iter().find(|name| context_root.join(name).is_file()).map(|name| name.to_string())
+    }
+
+    /// Picks the build method from the layout of the context: explicitly
+    /// configured Buildpacks win, otherwise a Dockerfile is used when one is
+    /// detected, and Buildpacks are the fallback for everything else.
+    fn resolve_strategy(&self, context_root: &Path) -> BuildMethod {
+        if self.buildpacks.is_none() && self.detect_dockerfile(context_root).is_some() {
+            return BuildMethod::Dockerfile;
+        }
+
+        BuildMethod::Buildpacks
+    }
+
+    /// Returns the build method the build is configured for: kaniko with a
+    /// Dockerfile, or else Cloud Native Buildpacks. Configuring both, or the
+    /// buildpacks without their builder image, is rejected as contradictory.
+    fn strategy(&self) -> Result<BuildMethod> {
+        match (&self.dockerfile, &self.buildpacks) {
+            (Some(_), Some(_)) => Err(Error::InvalidBuild(
+                "dockerfile and buildpacks are both set, an actor builds with one of them".into(),
+            )),
+            (None, Some(config)) if config.builder.trim().is_empty() => {
+                Err(Error::InvalidBuild("buildpacks require a builder image".into()))
+            }
+            (Some(_), None) => Ok(BuildMethod::Dockerfile),
+            (None, _) => Ok(BuildMethod::Buildpacks),
+        }
+    }
+
+    /// Check the build context and the configured Dockerfile exist in the
+    /// checkout of the repository, so that a typo in their paths fails with a
+    /// clear reason rather than late in the build. Without a Dockerfile, the
+    /// context is left to the detection of the build method.
+    fn validate_paths(&self, manifest_path: &str, repo_root: &Path) -> Result<()> {
+        let Some(config) = &self.dockerfile else { return Ok(()) };
+        let context = self.resolved_context(manifest_path, repo_root);
+        let relative = context.strip_prefix(repo_root).unwrap_or(&context);
+
+        if !context.is_dir() {
+            return Err(Error::BuildPathMissing(format!("build context `{}` does not exist", relative.display())));
+        }
+
+        let dockerfile = self.dockerfile_path(&context).ok_or_else(|| {
+            Error::InvalidBuild(format!(
+                "dockerfile `{}` must be a path relative to the build context, without `..`",
+                config.dockerfile
+            ))
+        })?;
+        if !dockerfile.is_file() {
+            return Err(Error::BuildPathMissing(format!(
+                "dockerfile `{}` does not exist in the build context `{}`",
+                config.dockerfile,
+                relative.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configured builder image, or else the given default if it
+    /// is a valid image reference, falling back to [`DEFAULT_BUILDER`].
+    fn effective_builder(&self, default: &str) -> String {
+        if let Some(builder) = self.buildpacks.as_ref().map(|config| &config.builder).filter(|b| !b.is_empty()) {
+            return builder.clone();
+        }
+
+        if is_image_reference(default) {
+            default.to_string()
+        } else {
+            DEFAULT_BUILDER.to_string()
+        }
+    }
+
+    /// Returns the environment variables of the build sorted by name, so that
+    /// the build containers stay the same between reconciliations.
+    fn sorted_env(&self) -> Option<Vec<EnvVar>> {
+        let mut env = self.env()?;
+        env.sort_by(|a, b| a.name.cmp(&b.name));
+        Some(env)
+    }
+}
+
+/// Check if the value looks like an image reference, `[registry/]path[:tag][@digest]`
+/// with lowercase path components.
+fn is_image_reference(value: &str) -> bool {
+    let name = value.split('@').next().unwrap_or_default();
+    let path = match name.rfind(':') {
+        Some(index) if !name[index..].contains('/') => &name[..index],
+        _ => name,
+    };
+
+    !path.is_empty()
+        && !value.chars().any(char::is_whitespace)
+        && path.split('/').enumerate().all(|(index, component)| {
+            !component.is_empty()
+                && (index == 0
+                    || component.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c)))
+        })
+}
+
+/// Lexically normalizes the path into a relative one, dropping the root,
+/// `.` components and any `..` component that would climb above the start.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+
+    parts.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use amp_common::schema::{BuildpacksConfig, DockerfileConfig};
+
+    use super::*;
+
+    fn build(context: Option<&str>) -> Build {
+        Build { context: context.map(Into::into), ..Default::default() }
+    }
+
+    #[test]
+    fn test_resolved_context_without_context() {
+        let root = Path::new("/workspace");
+
+        assert_eq!(build(None).resolved_context(".amp.toml", root), PathBuf::from("/workspace"));
+        assert_eq!(build(None).resolved_context("", root), PathBuf::from("/workspace"));
+        assert_eq!(
+            build(None).resolved_context("services/api/.amp.toml", root),
+            PathBuf::from("/workspace/services/api")
+        );
+    }
+
+    #[test]
+    fn test_resolved_context_relative_to_repo_root() {
+        let root = Path::new("/workspace");
+
+        assert_eq!(
+            build(Some("services/web")).resolved_context("services/api/.amp.toml", root),
+            PathBuf::from("/workspace/services/web")
+        );
+        assert_eq!(build(Some("./")).resolved_context("services/api/.amp.toml", root), PathBuf::from("/workspace"));
+    }
+
+    #[test]
+    fn test_resolved_context_traversal() {
+        let root = Path::new("/workspace");
+
+        assert_eq!(build(Some("../../etc")).resolved_context(".amp.toml", root), PathBuf::from("/workspace/etc"));
+        assert_eq!(
+            build(Some("/etc/passwd")).resolved_context(".amp.toml", root),
+            PathBuf::from("/workspace/etc/passwd")
+        );
+        assert_eq!(build(Some("a/../../b")).resolved_context(".amp.toml", root), PathBuf::from("/workspace/b"));
+    }
+
+    fn reader(files: &[(&str, &str)]) -> impl Fn(&Path) -> Option<Vec<u8>> {
+        let files: HashMap<PathBuf, Vec<u8>> =
+            files.iter().map(|(path, content)| (PathBuf::from(path), content.as_bytes().to_vec())).collect();
+        move |path| files.get(path).cloned()
+    }
+
+    #[test]
+    fn test_cache_key_shared_dependencies() {
+        let spec = ActorSpec::default();
+
+        // Two commits changing the sources only, but not the dependencies.
+        let first = reader(&[("package.json", r#"{"name":"web"}"#), ("index.js", "v1")]);
+        let second = reader(&[("package.json", r#"{"name":"web"}"#), ("index.js", "v2")]);
+        assert_eq!(build(None).cache_key(&spec, &first), build(None).cache_key(&spec, &second));
+
+        // A third commit changing the dependencies.
+        let third = reader(&[("package.json", r#"{"name":"web","dependencies":{}}"#), ("index.js", "v2")]);
+        assert_ne!(build(None).cache_key(&spec, &first), build(None).cache_key(&spec, &third));
+    }
+
+    #[test]
+    fn test_cache_key_in_context() {
+        let spec = ActorSpec::default();
+        let files = reader(&[("web/package.json", "{}"), ("api/go.mod", "module api")]);
+
+        assert_ne!(build(Some("web")).cache_key(&spec, &files), build(Some("api")).cache_key(&spec, &files));
+        assert_eq!(build(None).cache_key(&spec, &files).len(), 16);
+    }
+
+    #[test]
+    fn test_detect_dockerfile() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(build(None).detect_dockerfile(root.path()), None);
+
+        std::fs::write(root.path().join("Dockerfile"), "FROM scratch").unwrap();
+        assert_eq!(build(None).detect_dockerfile(root.path()), Some("Dockerfile".into()));
+    }
+
+    #[test]
+    fn test_detect_configured_dockerfile() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("docker")).unwrap();
+        std::fs::write(root.path().join("docker/api.Dockerfile"), "FROM scratch").unwrap();
+
+        let mut build = build(None);
+        build.dockerfile = Some(DockerfileConfig { dockerfile: "docker/api.Dockerfile".into() });
+        assert_eq!(build.detect_dockerfile(root.path()), Some("docker/api.Dockerfile".into()));
+
+        build.dockerfile = Some(DockerfileConfig { dockerfile: "missing.Dockerfile".into() });
+        assert_eq!(build.detect_dockerfile(root.path()), None);
+    }
+
+    #[test]
+    fn test_dockerfile_path() {
+        let context = Path::new("/workspace/services/api");
+        let dockerfile_path = |dockerfile: &str| {
+            let build = Build { dockerfile: Some(DockerfileConfig { dockerfile: dockerfile.into() }), ..build(None) };
+            build.dockerfile_path(context)
+        };
+
+        assert_eq!(dockerfile_path("Dockerfile"), Some(context.join("Dockerfile")));
+        assert_eq!(dockerfile_path("docker/Dockerfile.prod"), Some(context.join("docker/Dockerfile.prod")));
+        assert_eq!(dockerfile_path("../../etc/passwd"), None);
+        assert_eq!(dockerfile_path("docker/../../Dockerfile"), None);
+        assert_eq!(dockerfile_path("/etc/passwd"), None);
+        assert_eq!(dockerfile_path(""), None);
+        assert_eq!(build(None).dockerfile_path(context), None);
+    }
+
+    #[test]
+    fn test_resolve_strategy() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(matches!(build(None).resolve_strategy(root.path()), BuildMethod::Buildpacks));
+
+        std::fs::write(root.path().join("Dockerfile"), "FROM scratch").unwrap();
+        assert!(matches!(build(None).resolve_strategy(root.path()), BuildMethod::Dockerfile));
+    }
+
+    #[test]
+    fn test_resolve_strategy_with_buildpacks() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("Dockerfile"), "FROM scratch").unwrap();
+
+        let build = Build { buildpacks: Some(Default::default()), ..Default::default() };
+        assert!(matches!(build.resolve_strategy(root.path()), BuildMethod::Buildpacks));
+    }
+
+    fn with(dockerfile: Option<&str>, builder: Option<&str>) -> Build {
+        Build {
+            dockerfile: dockerfile.map(|dockerfile| DockerfileConfig { dockerfile: dockerfile.into() }),
+            buildpacks: builder.map(|builder| BuildpacksConfig { builder: builder.into(), ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_strategy() {
+        assert!(matches!(with(Some("Dockerfile"), None).strategy(), Ok(BuildMethod::Dockerfile)));
+        assert!(matches!(with(None, Some("paketobuildpacks/builder:base")).strategy(), Ok(BuildMethod::Buildpacks)));
+        assert!(matches!(with(None, None).strategy(), Ok(BuildMethod::Buildpacks)));
+    }
+
+    #[test]
+    fn test_strategy_with_dockerfile_and_buildpacks() {
+        let err = with(Some("Dockerfile"), Some("paketobuildpacks/builder:base")).strategy().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "InvalidBuild: dockerfile and buildpacks are both set, an actor builds with one of them"
+        );
+
+        let err = with(Some("Dockerfile"), Some("")).strategy().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "InvalidBuild: dockerfile and buildpacks are both set, an actor builds with one of them"
+        );
+    }
+
+    #[test]
+    fn test_strategy_with_buildpacks_without_builder() {
+        let err = with(None, Some(" ")).strategy().unwrap_err();
+        assert_eq!(err.to_string(), "InvalidBuild: buildpacks require a builder image");
+    }
+
+    #[test]
+    fn test_validate_paths() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("services/api/docker")).unwrap();
+        std::fs::write(root.path().join("services/api/docker/Dockerfile"), "FROM scratch").unwrap();
+
+        let build = Build { context: Some("services/api".into()), ..with(Some("docker/Dockerfile"), None) };
+        assert!(build.validate_paths(".amp.toml", root.path()).is_ok());
+
+        let build = Build { context: None, ..build };
+        assert!(build.validate_paths("services/api/.amp.toml", root.path()).is_ok());
+        assert!(with(None, None).validate_paths("missing/.amp.toml", root.path()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_paths_with_missing_context() {
+        let root = tempfile::tempdir().unwrap();
+        let build = Build { context: Some("services/web".into()), ..with(Some("Dockerfile"), None) };
+
+        let err = build.validate_paths(".amp.toml", root.path()).unwrap_err();
+        assert!(matches!(err, Error::BuildPathMissing(_)));
+        assert_eq!(err.to_string(), "BuildPathMissing: build context `services/web` does not exist");
+    }
+
+    #[test]
+    fn test_validate_paths_with_missing_dockerfile() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("api")).unwrap();
+        let build = Build { context: Some("api".into()), ..with(Some("Dockerfile.prod"), None) };
+
+        let err = build.validate_paths(".amp.toml", root.path()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "BuildPathMissing: dockerfile `Dockerfile.prod` does not exist in the build context `api`"
+        );
+
+        let build = Build { context: Some("api".into()), ..with(Some("../Dockerfile"), None) };
+        assert!(matches!(build.validate_paths(".amp.toml", root.path()), Err(Error::InvalidBuild(_))));
+    }
+
+    #[test]
+    fn test_effective_builder() {
+        let build = Build {
+            buildpacks: Some(BuildpacksConfig {
+                builder: "paketobuildpacks/builder:base".into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(build.effective_builder(DEFAULT_BUILDER), "paketobuildpacks/builder:base");
+    }
+
+    #[test]
+    fn test_effective_builder_default() {
+        assert_eq!(build(None).effective_builder(DEFAULT_BUILDER), DEFAULT_BUILDER);
+        assert_eq!(build(None).effective_builder("heroku/builder:22"), "heroku/builder:22");
+        assert_eq!(build(None).effective_builder("Not An Image"), DEFAULT_BUILDER);
+        assert_eq!(build(None).effective_builder(""), DEFAULT_BUILDER);
+    }
+
+    #[test]
+    fn test_sorted_env() {
+        assert_eq!(build(None).sorted_env(), None);
+
+        let env = HashMap::from([("C", "3"), ("A", "1"), ("D", "4"), ("B", "2")]);
+        let build = Build {
+            env: Some(env.into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()),
+            ..Default::default()
+        };
+        let names: Vec<_> = build.sorted_env().unwrap().into_iter().map(|var| var.name).collect();
+        assert_eq!(names, vec!["A", "B", "C", "D"]);
+    }
+}
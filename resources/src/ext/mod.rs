@@ -0,0 +1,28 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub(crate) mod actor;
+pub use self::actor::{ActorExt, DeploymentStrategy, ManagementMode};
+
+mod build;
+pub use self::build::{BuildCache, BuildSecret, BuildSpecExt, DEFAULT_BUILDER};
+
+pub(crate) mod playbook;
+pub use self::playbook::PlaybookExt;
+
+mod spec;
+pub use self::spec::ActorSpecExt;
+
+mod status;
+pub use self::status::{ActorPhase, ActorStateExt, ActorStatusExt, ConditionExt};
@@ -0,0 +1,205 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use amp_common::resource::{ActorSpec, Playbook};
+use kube::ResourceExt;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::is_dns1123_label;
+
+const OVERRIDES_KEY: &str = "amphitheatre.app/overrides";
+pub(crate) const PARTNER_NAMESPACE_KEY: &str = "amphitheatre.app/partner-namespace";
+
+/// What a playbook changes of the actor of one of its characters, like the
+/// image or the env of an environment, over what the character declares.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct ActorOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<HashMap<String, String>>,
+}
+
+pub trait PlaybookExt {
+    fn overrides(&self) -> HashMap<String, ActorOverride>;
+    fn apply_overrides(&self, spec: &mut ActorSpec, name: &str);
+    fn partner_namespace(&self) -> Option<String>;
+    fn target_namespace(&self, name: &str) -> String;
+}
+
+impl PlaybookExt for Playbook {
+    /// Returns the overrides of the actors keyed by their name, read from the
+    /// `amphitheatre.app/overrides` annotation as a JSON object like
+    /// `{"api": {"image": "registry.local/api:v2", "replicas": 3, "env": {"LOG_LEVEL": "debug"}}}`.
+    /// A malformed value is ignored with a warning.
+    fn overrides(&self) -> HashMap<String, ActorOverride> {
+        let Some(value) = self.annotations().get(OVERRIDES_KEY) else { return HashMap::new() };
+        match serde_json::from_str(value) {
+            Ok(overrides) => overrides,
+            Err(err) => {
+                warn!("Ignore the overrides of playbook {}: {}", self.name_any(), err);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Merge the override of the actor into its spec: the image replaces the
+    /// one of the character, and the env is merged into its deploy env, the
+    /// override taking precedence. The replicas are an annotation of the actor,
+    /// they are left to the caller.
+    fn apply_overrides(&self, spec: &mut ActorSpec, name: &str) {
+        let Some(overrides) = self.overrides().remove(name) else { return };
+
+        if let Some(image) = overrides.image {
+            spec.image = image;
+        }
+        if let Some(env) = overrides.env {
+            let deploy = spec.character.deploy.get_or_insert_with(Default::default);
+            deploy.env.get_or_insert_with(Default::default).extend(env);
+        }
+    }
+
+    /// Returns the namespace the partners of the characters are created in,
+    /// read from the `amphitheatre.app/partner-namespace` annotation.
+    /// An invalid namespace is ignored with a warning.
+    fn partner_namespace(&self) -> Option<String> {
+        let namespace = self.annotations().get(PARTNER_NAMESPACE_KEY)?;
+        if !is_dns1123_label(namespace) {
+            warn!("Ignore the partner namespace of playbook {}: {} is not a valid name", self.name_any(), namespace);
+            return None;
+        }
+        Some(namespace.clone())
+    }
+
+    /// Returns the namespace of the actor of the character: the partner namespace
+    /// if the character is a partner of another one and the namespace is set,
+    /// otherwise the namespace of the playbook, the same as the other actors.
+    fn target_namespace(&self, name: &str) -> String {
+        let characters = self.spec.characters.iter().flatten();
+        let partner = characters
+            .filter(|character| character.meta.name != name)
+            .any(|character| character.partners.as_ref().is_some_and(|partners| partners.contains_key(name)));
+
+        match self.partner_namespace() {
+            Some(namespace) if partner => namespace,
+            _ => self.spec.namespace(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::{CharacterSpec, Partner, PlaybookSpec};
+    use amp_common::schema::{Deploy, GitReference, Metadata};
+
+    use super::*;
+
+    fn character(name: &str, partners: &[&str]) -> CharacterSpec {
+        let partners = partners.iter().map(|name| (name.to_string(), Partner::Repository(GitReference::default())));
+        CharacterSpec {
+            meta: Metadata { name: name.into(), ..Default::default() },
+            partners: Some(partners.collect()),
+            ..Default::default()
+        }
+    }
+
+    fn playbook(partner_namespace: Option<&str>) -> Playbook {
+        let characters = vec![character("api", &["postgres"]), character("postgres", &[])];
+        let mut playbook = Playbook::new(
+            "demo",
+            PlaybookSpec { id: "demo".into(), characters: Some(characters), ..Default::default() },
+        );
+        if let Some(namespace) = partner_namespace {
+            playbook.annotations_mut().insert(PARTNER_NAMESPACE_KEY.into(), namespace.into());
+        }
+        playbook
+    }
+
+    #[test]
+    fn test_default_target_namespace() {
+        let playbook = playbook(None);
+
+        assert_eq!(playbook.target_namespace("api"), playbook.spec.namespace());
+        assert_eq!(playbook.target_namespace("postgres"), playbook.spec.namespace());
+    }
+
+    #[test]
+    fn test_explicit_target_namespace() {
+        let playbook = playbook(Some("shared"));
+
+        assert_eq!(playbook.target_namespace("api"), playbook.spec.namespace());
+        assert_eq!(playbook.target_namespace("postgres"), "shared");
+    }
+
+    #[test]
+    fn test_invalid_partner_namespace() {
+        let playbook = playbook(Some("Shared_DB"));
+
+        assert_eq!(playbook.partner_namespace(), None);
+        assert_eq!(playbook.target_namespace("postgres"), playbook.spec.namespace());
+    }
+
+    fn actor(env: &[(&str, &str)]) -> ActorSpec {
+        let env = env.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        let character =
+            CharacterSpec { deploy: Some(Deploy { env: Some(env), ..Default::default() }), ..Default::default() };
+        ActorSpec { name: "api".into(), image: "registry.local/api:v1".into(), character, ..Default::default() }
+    }
+
+    #[test]
+    fn test_override_image() {
+        let mut playbook = playbook(None);
+        let overrides = r#"{"api": {"image": "registry.local/api:v2", "replicas": 3}}"#;
+        playbook.annotations_mut().insert(OVERRIDES_KEY.into(), overrides.into());
+
+        let mut spec = actor(&[("LOG_LEVEL", "info")]);
+        playbook.apply_overrides(&mut spec, "api");
+        assert_eq!(spec.image, "registry.local/api:v2");
+        assert_eq!(spec, ActorSpec { image: "registry.local/api:v2".into(), ..actor(&[("LOG_LEVEL", "info")]) });
+        assert_eq!(playbook.overrides()["api"].replicas, Some(3));
+
+        let mut spec = actor(&[]);
+        playbook.apply_overrides(&mut spec, "postgres");
+        assert_eq!(spec, actor(&[]));
+    }
+
+    #[test]
+    fn test_override_env() {
+        let mut playbook = playbook(None);
+        let overrides = r#"{"api": {"env": {"LOG_LEVEL": "debug", "REGION": "eu"}}}"#;
+        playbook.annotations_mut().insert(OVERRIDES_KEY.into(), overrides.into());
+
+        let mut spec = actor(&[("LOG_LEVEL", "info"), ("PORT", "8080")]);
+        playbook.apply_overrides(&mut spec, "api");
+        assert_eq!(spec, actor(&[("LOG_LEVEL", "debug"), ("PORT", "8080"), ("REGION", "eu")]));
+
+        let mut spec = ActorSpec { name: "api".into(), ..Default::default() };
+        playbook.apply_overrides(&mut spec, "api");
+        let env = spec.character.deploy.unwrap().env.unwrap();
+        assert_eq!(env.len(), 2);
+    }
+
+    #[test]
+    fn test_malformed_overrides() {
+        let mut playbook = playbook(None);
+        playbook.annotations_mut().insert(OVERRIDES_KEY.into(), r#"{"api": {"replicas": "many"}}"#.into());
+
+        assert!(playbook.overrides().is_empty());
+    }
+}
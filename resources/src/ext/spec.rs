@@ -0,0 +1,500 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use amp_common::resource::{ActorSpec, Playbook};
+use amp_common::schema::{BuildMethod, Metadata};
+use k8s_openapi::api::core::v1::EnvVar;
+use k8s_openapi::api::networking::v1::NetworkPolicyPeer;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+
+use super::{BuildSpecExt, PlaybookExt};
+use crate::capability::Capability;
+use crate::containers::coalesce_env;
+use crate::error::{Error, Result};
+use crate::service::ServiceKind;
+use crate::{hash, normalize_manifest_path, split_command};
+
+pub trait ActorSpecExt {
+    fn affected_by(&self, changed_files: &[String]) -> bool;
+    fn build_fingerprint(&self) -> String;
+    fn command_args(&self) -> Result<Option<Vec<String>>>;
+    fn external_urls(&self, host_template: &str) -> Vec<String>;
+    fn minimize(&self) -> ActorSpec;
+    fn normalized_manifest_path(&self) -> String;
+    fn required_build_capabilities(&self) -> Vec<Capability>;
+    fn partner_egress_targets(&self, playbook: &Playbook) -> Vec<NetworkPolicyPeer>;
+    fn resolve_env(&self) -> (Vec<EnvVar>, Vec<String>);
+    fn service_type(&self) -> Result<ServiceKind>;
+    fn spec_eq(&self, live: &ActorSpec) -> bool;
+    fn validate_commit_on_ref<F>(&self, reachable: F) -> Result<()>
+    where
+        F: Fn(&str, &str) -> bool;
+}
+
+impl ActorSpecExt for ActorSpec {
+    /// Check if any of the changed files falls under the subtree of the actor,
+    /// that is its build context, or the directory of its manifest. An empty
+    /// list of files is treated as affected, as we can not tell otherwise.
+    fn affected_by(&self, changed_files: &[String]) -> bool {
+        if changed_files.is_empty() {
+            return true;
+        }
+
+        let manifest = self.normalized_manifest_path();
+        let build = self.character.build.clone().unwrap_or_default();
+        let prefix = build.resolved_context(&manifest, Path::new(""));
+
+        changed_files.iter().any(|file| Path::new(file.trim_start_matches('/')).starts_with(&prefix))
+    }
+
+    /// Returns the fingerprint of the build configuration, which changes when
+    /// the image would be built differently from the same commit.
+    fn build_fingerprint(&self) -> String {
+        let path = self.source.as_ref().and_then(|source| source.path.as_ref());
+        hash(&(&self.character.build, path)).unwrap_or_default()
+    }
+
+    /// Returns the command overriding the CMD of the image, split into the
+    /// arguments of the container like a shell would, see `split_command`.
+    /// A missing or blank command yields `None`.
+    fn command_args(&self) -> Result<Option<Vec<String>>> {
+        let command = self.character.deploy.as_ref().and_then(|deploy| deploy.command.as_deref());
+        let args = split_command(command.unwrap_or_default())?;
+        Ok((!args.is_empty()).then_some(args))
+    }
+
+    /// Returns the externally reachable URLs of the exposed ports, with the host
+    /// rendered from the template by substituting `{name}` with the actor name
+    /// and `{port}` with the port. When several ports share a host, they are
+    /// routed by path, like `https://<host>/<port>/`.
+    fn external_urls(&self, host_template: &str) -> Vec<String> {
+        let ports: Vec<i32> = self
+            .character
+            .deploy
+            .as_ref()
+            .and_then(|deploy| deploy.services.as_ref())
+            .into_iter()
+            .flatten()
+            .flat_map(|service| service.ports.iter())
+            .filter(|port| port.expose.unwrap_or_default())
+            .map(|port| port.port)
+            .collect();
+
+        let host = |port: i32| host_template.replace("{name}", &self.name).replace("{port}", &port.to_string());
+        let shared = ports.len() > 1 && !host_template.contains("{port}");
+
+        ports
+            .iter()
+            .map(|port| {
+                if shared {
+                    format!("https://{}/{}/", host(*port), port)
+                } else {
+                    format!("https://{}/", host(*port))
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a copy stripped down to what the build and the deploy depend on,
+    /// for inclusion in issue reports. The cosmetic metadata of the character
+    /// is cleared, keeping only its name and repository.
+    fn minimize(&self) -> ActorSpec {
+        let meta = &self.character.meta;
+        let mut spec = self.clone();
+        spec.character.meta =
+            Metadata { name: meta.name.clone(), repository: meta.repository.clone(), ..Default::default() };
+        spec
+    }
+
+    /// Returns the path of the manifest of the actor relative to the repository
+    /// root, see `normalize_manifest_path`. An invalid path, rejected by the
+    /// validation, is only trimmed of its leading slashes.
+    fn normalized_manifest_path(&self) -> String {
+        let path = self.source.as_ref().and_then(|source| source.path.as_deref());
+        normalize_manifest_path(path).unwrap_or_else(|_| path.unwrap_or_default().trim_start_matches('/').to_string())
+    }
+
+    /// Returns the build subsystems the actor needs in the cluster, from the
+    /// method it is built with, so that they can be checked before building.
+    /// A build configured for both methods needs none, as it is rejected.
+    fn required_build_capabilities(&self) -> Vec<Capability> {
+        match self.character.build.clone().unwrap_or_default().strategy() {
+            Ok(BuildMethod::Dockerfile) => vec![Capability::Kaniko],
+            Ok(BuildMethod::Buildpacks) => vec![Capability::Buildpacks],
+            Err(_) => vec![],
+        }
+    }
+
+    /// Returns the peers matching the pods of the partners, which are deployed
+    /// as actors named after them in their target namespace of the playbook,
+    /// the partner namespace if it is set.
+    fn partner_egress_targets(&self, playbook: &Playbook) -> Vec<NetworkPolicyPeer> {
+        let mut names: Vec<&String> = self.character.partners.iter().flat_map(|partners| partners.keys()).collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .map(|name| NetworkPolicyPeer {
+                namespace_selector: Some(LabelSelector {
+                    match_labels: Some(BTreeMap::from([(
+                        "kubernetes.io/metadata.name".into(),
+                        playbook.target_namespace(name),
+                    )])),
+                    ..Default::default()
+                }),
+                pod_selector: Some(LabelSelector {
+                    match_labels: Some(BTreeMap::from([("amphitheatre.app/character".into(), name.clone())])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Resolve the final, ordered environment variables of the application
+    /// container, with the warnings for the shadowed ones. The variables of the
+    /// deploy spec are sorted by name, so that the pod template stays the same
+    /// between reconciliations.
+    fn resolve_env(&self) -> (Vec<EnvVar>, Vec<String>) {
+        let mut deploy = self.character.deploy.as_ref().and_then(|deploy| deploy.env()).unwrap_or_default();
+        deploy.sort_by(|a, b| a.name.cmp(&b.name));
+
+        coalesce_env(&[("deploy", deploy)])
+    }
+
+    /// Returns the type of the Service exposing the actor, from the kind of its
+    /// services, `ClusterIP` if none is set. All the services are exposed by a
+    /// single Service, so they can not declare different kinds.
+    fn service_type(&self) -> Result<ServiceKind> {
+        let services = self.character.deploy.as_ref().and_then(|deploy| deploy.services.as_ref());
+
+        let mut service_type = None;
+        for kind in services.into_iter().flatten().filter_map(|service| service.kind.as_deref()) {
+            let kind: ServiceKind = kind.parse()?;
+            match service_type {
+                Some(existing) if existing != kind => {
+                    return Err(Error::InvalidServiceType(format!(
+                        "services declare both `{}` and `{}`, they are exposed by a single Service of one type",
+                        existing, kind
+                    )));
+                }
+                _ => service_type = Some(kind),
+            }
+        }
+
+        Ok(service_type.unwrap_or_default())
+    }
+
+    /// Check if the live spec of an actor matches this one, the intent of its
+    /// character: the same name, character and live mode, and the same image
+    /// unless the intent leaves it to the resolver, which derives it from the
+    /// source when building. The source is left out, as it is pinned to the
+    /// resolved revision.
+    fn spec_eq(&self, live: &ActorSpec) -> bool {
+        self.name == live.name
+            && self.character == live.character
+            && self.live == live.live
+            && (self.image.is_empty() || self.image == live.image)
+    }
+
+    /// Check that the pinned commit of the source is reachable from its tag or
+    /// branch. The check itself needs the repository, so it is left to the
+    /// `reachable(commit, reference)` function, called once the sources have
+    /// been cloned. Sources without a commit or a reference are always valid.
+    fn validate_commit_on_ref<F>(&self, reachable: F) -> Result<()>
+    where
+        F: Fn(&str, &str) -> bool,
+    {
+        let Some(source) = &self.source else { return Ok(()) };
+        let (Some(commit), Some(reference)) = (&source.rev, source.tag.as_ref().or(source.branch.as_ref())) else {
+            return Ok(());
+        };
+
+        if !reachable(commit, reference) {
+            return Err(Error::CommitNotOnRef(commit.clone(), reference.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use amp_common::resource::{CharacterSpec, Partner, PlaybookSpec};
+    use amp_common::schema::{Build, BuildpacksConfig, Deploy, DockerfileConfig, GitReference, Port, Service};
+    use kube::ResourceExt;
+
+    use super::*;
+    use crate::ext::playbook::PARTNER_NAMESPACE_KEY;
+
+    fn spec(path: &str, context: Option<&str>) -> ActorSpec {
+        ActorSpec {
+            name: "api".into(),
+            source: Some(GitReference { path: Some(path.into()), ..Default::default() }),
+            character: CharacterSpec {
+                build: Some(Build { context: context.map(Into::into), ..Default::default() }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn files(files: &[&str]) -> Vec<String> {
+        files.iter().map(|file| file.to_string()).collect()
+    }
+
+    #[test]
+    fn test_spec_eq() {
+        let intent = ActorSpec { name: "api".into(), image: "registry.local/api:v1".into(), ..Default::default() };
+        let mut live = intent.clone();
+        live.source = Some(GitReference { rev: Some("abc123".into()), ..Default::default() });
+        assert!(intent.spec_eq(&live));
+
+        live.image = "registry.local/api:v2".into();
+        assert!(!intent.spec_eq(&live));
+
+        let intent = ActorSpec { image: String::new(), ..intent };
+        assert!(intent.spec_eq(&live));
+
+        live.character.deploy = Some(Deploy { command: Some("api --debug".into()), ..Default::default() });
+        assert!(!intent.spec_eq(&live));
+    }
+
+    #[test]
+    fn test_affected_by_changes_in_subtree() {
+        let spec = spec("services/api/.amp.toml", None);
+
+        assert!(spec.affected_by(&files(&["services/api/main.go"])));
+        assert!(spec.affected_by(&files(&["README.md", "services/api/go.mod"])));
+    }
+
+    #[test]
+    fn test_not_affected_by_changes_outside_subtree() {
+        let spec = spec("services/api/.amp.toml", None);
+
+        assert!(!spec.affected_by(&files(&["services/web/index.js"])));
+        assert!(!spec.affected_by(&files(&["services/api-gateway/main.go"])));
+    }
+
+    #[test]
+    fn test_affected_by_changes_in_context() {
+        let spec = spec("services/api/.amp.toml", Some("services"));
+
+        assert!(spec.affected_by(&files(&["services/web/index.js"])));
+        assert!(!spec.affected_by(&files(&["docs/index.md"])));
+    }
+
+    #[test]
+    fn test_build_fingerprint() {
+        let fingerprint = spec("services/api/.amp.toml", None).build_fingerprint();
+
+        assert_eq!(fingerprint, spec("services/api/.amp.toml", None).build_fingerprint());
+        assert_ne!(fingerprint, spec("services/api/.amp.toml", Some("services")).build_fingerprint());
+    }
+
+    fn with_command(command: &str) -> ActorSpec {
+        let mut spec = spec("services/api/.amp.toml", None);
+        spec.character.deploy = Some(Deploy { command: Some(command.into()), ..Default::default() });
+        spec
+    }
+
+    #[test]
+    fn test_command_args() {
+        assert_eq!(spec("services/api/.amp.toml", None).command_args().unwrap(), None);
+        assert_eq!(with_command("  ").command_args().unwrap(), None);
+        assert_eq!(
+            with_command(r#"myapp --flag "a b" c\ d"#).command_args().unwrap(),
+            Some(vec!["myapp".into(), "--flag".into(), "a b".into(), "c d".into()])
+        );
+        assert!(matches!(with_command("myapp 'a b").command_args(), Err(Error::InvalidCommand(..))));
+    }
+
+    #[test]
+    fn test_affected_by_empty_changes() {
+        assert!(spec("services/api/.amp.toml", None).affected_by(&[]));
+    }
+
+    fn exposed(ports: &[i32]) -> ActorSpec {
+        let ports = ports.iter().map(|port| Port { port: *port, expose: Some(true), ..Default::default() }).collect();
+        let services = vec![Service { ports, ..Default::default() }];
+
+        let mut spec = spec("services/api/.amp.toml", None);
+        spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+        spec
+    }
+
+    #[test]
+    fn test_external_urls_single_service() {
+        let spec = exposed(&[8080]);
+
+        assert_eq!(spec.external_urls("{name}.apps.example.com"), vec!["https://api.apps.example.com/"]);
+    }
+
+    #[test]
+    fn test_external_urls_multiple_services() {
+        let spec = exposed(&[8080, 9090]);
+
+        assert_eq!(
+            spec.external_urls("{name}.apps.example.com"),
+            vec!["https://api.apps.example.com/8080/", "https://api.apps.example.com/9090/"]
+        );
+        assert_eq!(
+            spec.external_urls("{name}-{port}.apps.example.com"),
+            vec!["https://api-8080.apps.example.com/", "https://api-9090.apps.example.com/"]
+        );
+    }
+
+    #[test]
+    fn test_partner_egress_targets() {
+        let mut playbook = Playbook::new("demo", PlaybookSpec { id: "demo".into(), ..Default::default() });
+        let mut spec = spec("services/api/.amp.toml", None);
+        assert!(spec.partner_egress_targets(&playbook).is_empty());
+
+        let partner = Partner::Repository(GitReference::default());
+        spec.character.partners =
+            Some(HashMap::from([("redis".into(), partner.clone()), ("postgres".into(), partner.clone())]));
+
+        // Only postgres is a partner of a character of the playbook, so it is
+        // the only one deployed in the partner namespace
+        let api = CharacterSpec {
+            meta: Metadata { name: "api".into(), ..Default::default() },
+            partners: Some(HashMap::from([("postgres".into(), partner)])),
+            ..Default::default()
+        };
+        playbook.spec.characters = Some(vec![api]);
+        playbook.annotations_mut().insert(PARTNER_NAMESPACE_KEY.into(), "shared-db".into());
+
+        let targets: Vec<_> = spec
+            .partner_egress_targets(&playbook)
+            .into_iter()
+            .map(|peer| {
+                (
+                    peer.namespace_selector.unwrap().match_labels.unwrap()["kubernetes.io/metadata.name"].clone(),
+                    peer.pod_selector.unwrap().match_labels.unwrap()["amphitheatre.app/character"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(targets, vec![("shared-db".into(), "postgres".into()), (playbook.spec.namespace(), "redis".into())]);
+    }
+
+    #[test]
+    fn test_resolve_env_sorted() {
+        let mut spec = spec("services/api/.amp.toml", None);
+        assert_eq!(spec.resolve_env(), (vec![], vec![]));
+
+        let env = HashMap::from([("B".to_string(), "2".to_string()), ("A".to_string(), "1".to_string())]);
+        spec.character.deploy = Some(Deploy { env: Some(env), ..Default::default() });
+
+        let (environments, warnings) = spec.resolve_env();
+        let names: Vec<_> = environments.iter().map(|var| var.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B"]);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_validate_commit_on_ref() {
+        let mut spec = spec("services/api/.amp.toml", None);
+        spec.source =
+            Some(GitReference { branch: Some("main".into()), rev: Some("abc123".into()), ..Default::default() });
+
+        assert!(spec.validate_commit_on_ref(|commit, reference| commit == "abc123" && reference == "main").is_ok());
+        assert!(matches!(
+            spec.validate_commit_on_ref(|_, _| false),
+            Err(Error::CommitNotOnRef(commit, reference)) if commit == "abc123" && reference == "main"
+        ));
+    }
+
+    fn with_kinds(kinds: &[Option<&str>]) -> ActorSpec {
+        let services = kinds
+            .iter()
+            .map(|kind| Service { kind: kind.map(Into::into), ports: vec![Port::default()], ..Default::default() })
+            .collect();
+
+        let mut spec = spec("services/api/.amp.toml", None);
+        spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+        spec
+    }
+
+    #[test]
+    fn test_service_type() {
+        assert_eq!(spec("services/api/.amp.toml", None).service_type().unwrap(), ServiceKind::ClusterIP);
+        assert_eq!(with_kinds(&[None]).service_type().unwrap(), ServiceKind::ClusterIP);
+        assert_eq!(with_kinds(&[None, Some("NodePort")]).service_type().unwrap(), ServiceKind::NodePort);
+        assert_eq!(with_kinds(&[Some("NodePort"), Some("NodePort")]).service_type().unwrap(), ServiceKind::NodePort);
+    }
+
+    #[test]
+    fn test_invalid_service_type() {
+        assert!(matches!(with_kinds(&[Some("nodeport")]).service_type(), Err(Error::InvalidServiceType(_))));
+        assert_eq!(
+            with_kinds(&[Some("NodePort"), Some("LoadBalancer")]).service_type().unwrap_err().to_string(),
+            "InvalidServiceType: services declare both `NodePort` and `LoadBalancer`, \
+            they are exposed by a single Service of one type"
+        );
+    }
+
+    #[test]
+    fn test_validate_commit_without_ref() {
+        let spec = spec("services/api/.amp.toml", None);
+
+        assert!(spec.validate_commit_on_ref(|_, _| false).is_ok());
+    }
+
+    #[test]
+    fn test_minimize() {
+        let mut spec = spec("services/api/.amp.toml", Some("services/api"));
+        spec.character.meta = Metadata {
+            name: "api".into(),
+            repository: "https://github.com/amphitheatre-app/amphitheatre.git".into(),
+            description: Some("The API service".into()),
+            ..Default::default()
+        };
+
+        let minimized = spec.minimize();
+        assert_eq!(minimized.build_fingerprint(), spec.build_fingerprint());
+        assert_eq!(minimized.character.meta.name, "api");
+        assert_eq!(minimized.character.meta.repository, spec.character.meta.repository);
+        assert_eq!(minimized.character.meta.description, None);
+    }
+
+    #[test]
+    fn test_required_build_capabilities() {
+        let mut spec = spec(".amp.toml", None);
+
+        spec.character.build = Some(Build {
+            dockerfile: Some(DockerfileConfig { dockerfile: "Dockerfile".into() }),
+            ..Default::default()
+        });
+        assert_eq!(spec.required_build_capabilities(), vec![Capability::Kaniko]);
+
+        spec.character.build = Some(Build {
+            buildpacks: Some(BuildpacksConfig { builder: "amp-buildpacks/sample-builder:v1".into(), buildpacks: None }),
+            ..Default::default()
+        });
+        assert_eq!(spec.required_build_capabilities(), vec![Capability::Buildpacks]);
+    }
+
+    #[test]
+    fn test_normalized_manifest_path() {
+        assert_eq!(spec("./services/api/.amp.toml", None).normalized_manifest_path(), "services/api/.amp.toml");
+        assert_eq!(spec("services/api/", None).normalized_manifest_path(), "services/api/.amp.toml");
+        assert_eq!(spec("/services/api/.amp.toml", None).normalized_manifest_path(), "services/api/.amp.toml");
+        assert_eq!(ActorSpec::default().normalized_manifest_path(), ".amp.toml");
+    }
+}
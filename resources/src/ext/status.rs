@@ -0,0 +1,562 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use amp_common::resource::{ActorState, ActorStatus};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::chrono::Utc;
+
+/// The order of the states in the lifecycle of an actor.
+const STATE_ORDER: &[&str] = &["Pending", "Building", "Running", "Scaling", "Succeeded", "Failed"];
+
+/// The condition type of the actors that ran to completion.
+const SUCCEEDED: &str = "Succeeded";
+
+/// The condition type of the running actors whose replicas are being resized.
+const SCALING: &str = "Scaling";
+
+/// The condition type of the actors running with reduced service.
+const DEGRADED: &str = "Degraded";
+
+/// The overall phase of an actor, ordered by precedence when the conditions
+/// of several phases transitioned at the same time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ActorPhase {
+    Pending,
+    Building,
+    Running,
+    Scaling,
+    Succeeded,
+    Failed,
+}
+
+impl ActorPhase {
+    /// Returns the phase of the condition type, if it is one of a phase.
+    fn from_type(type_: &str) -> Option<Self> {
+        [Self::Pending, Self::Building, Self::Running, Self::Scaling, Self::Succeeded, Self::Failed]
+            .into_iter()
+            .find(|phase| phase.to_string().eq_ignore_ascii_case(type_))
+    }
+}
+
+impl fmt::Display for ActorPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActorPhase::Pending => write!(f, "Pending"),
+            ActorPhase::Building => write!(f, "Building"),
+            ActorPhase::Running => write!(f, "Running"),
+            ActorPhase::Scaling => write!(f, "Scaling"),
+            ActorPhase::Succeeded => write!(f, "Succeeded"),
+            ActorPhase::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+/// The terminal state of the one-shot actors, like batch jobs, that ran to
+/// completion. It is mutually exclusive with Running and Failed, as setting
+/// the condition of a state turns the ones of the other states false.
+///
+/// The `Scaling` state is the one of a running actor whose workload is being
+/// resized to its desired replicas, it goes back to Running once they are ready.
+///
+/// The condition of any state can be built with `condition`, e.g. to report
+/// why an actor stays in a state, like a build waiting for a free slot.
+pub trait ActorStateExt {
+    fn condition(phase: ActorPhase, reason: &str, message: Option<String>) -> Condition;
+    fn scaling(reason: &str, message: Option<String>) -> Condition;
+    fn succeeded(reason: &str, message: Option<String>) -> Condition;
+}
+
+impl ActorStateExt for ActorState {
+    /// Returns the true condition of the state with the reason and message.
+    fn condition(phase: ActorPhase, reason: &str, message: Option<String>) -> Condition {
+        Condition {
+            type_: phase.to_string(),
+            status: "True".into(),
+            reason: reason.into(),
+            message: message.unwrap_or_default(),
+            last_transition_time: Time(Utc::now()),
+            observed_generation: None,
+        }
+    }
+
+    /// Returns the `Scaling` condition with the reason and message.
+    fn scaling(reason: &str, message: Option<String>) -> Condition {
+        Self::condition(ActorPhase::Scaling, reason, message)
+    }
+
+    /// Returns the `Succeeded` condition with the reason and message.
+    fn succeeded(reason: &str, message: Option<String>) -> Condition {
+        Self::condition(ActorPhase::Succeeded, reason, message)
+    }
+}
+
+/// The conditions of the states are created without the generation of the
+/// actor, which the reconciler stamps on them, so that clients can tell
+/// whether the status reflects the latest spec.
+pub trait ConditionExt {
+    fn observed(self, generation: Option<i64>) -> Self;
+}
+
+impl ConditionExt for Condition {
+    /// Returns the condition observing the given generation of the actor.
+    fn observed(self, generation: Option<i64>) -> Self {
+        Condition { observed_generation: generation, ..self }
+    }
+}
+
+pub trait ActorStatusExt {
+    fn badge(&self) -> (String, String);
+    fn build_succeeded(&self) -> bool;
+    fn fully_ready(&self, generation: i64) -> bool;
+    fn is_current(&self, generation: i64) -> bool;
+    fn phase(&self) -> Option<ActorPhase>;
+    fn scaling(&self) -> bool;
+    fn set_condition(&mut self, condition: Condition);
+    fn succeeded(&self) -> bool;
+    fn timeline(&self) -> Vec<&Condition>;
+}
+
+impl ActorStatusExt for ActorStatus {
+    /// Returns the label and color of a shields.io style badge for the state.
+    fn badge(&self) -> (String, String) {
+        let (label, color) = match self.phase() {
+            Some(ActorPhase::Failed) => ("failed", "red"),
+            Some(ActorPhase::Succeeded) => ("succeeded", "brightgreen"),
+            Some(ActorPhase::Scaling) => ("scaling", "yellowgreen"),
+            Some(ActorPhase::Running) => ("running", "green"),
+            Some(ActorPhase::Building) => ("building", "yellow"),
+            Some(ActorPhase::Pending) => ("pending", "blue"),
+            None => ("unknown", "lightgrey"),
+        };
+
+        (label.into(), color.into())
+    }
+
+    /// Returns true if the image of the actor is built, that is the actor went
+    /// on to Running (or Scaling, or ran to completion) after its last build, which
+    /// is only set once the build succeeded or the image was found in the registry.
+    fn build_succeeded(&self) -> bool {
+        matches!(self.phase(), Some(ActorPhase::Running | ActorPhase::Scaling | ActorPhase::Succeeded))
+    }
+
+    /// Returns true only if the actor is running, and not degraded, and its
+    /// Running condition observed the given generation or a later one. It is
+    /// stricter than `running()`, for dependents that wait on the actor.
+    fn fully_ready(&self, generation: i64) -> bool {
+        let running = self.running();
+        let degraded =
+            self.conditions.iter().any(|condition| condition.type_ == DEGRADED && condition.status == "True");
+        let observed = self
+            .conditions
+            .iter()
+            .filter(|condition| condition.type_.eq_ignore_ascii_case("running") && condition.status == "True")
+            .any(|condition| condition.observed_generation.is_some_and(|observed| observed >= generation));
+
+        running && !degraded && observed
+    }
+
+    /// Returns true if the newest condition observed the given generation or a
+    /// later one, that is the status reflects the latest spec of the actor.
+    fn is_current(&self, generation: i64) -> bool {
+        let newest = self.timeline().pop();
+        newest.and_then(|condition| condition.observed_generation).is_some_and(|observed| observed >= generation)
+    }
+
+    /// Returns the authoritative phase of the actor, that of the true condition
+    /// which transitioned last. On ties, the terminal phases take precedence,
+    /// then Running over Building over Pending.
+    fn phase(&self) -> Option<ActorPhase> {
+        self.conditions
+            .iter()
+            .filter(|condition| condition.status == "True")
+            .filter_map(|condition| {
+                ActorPhase::from_type(&condition.type_).map(|phase| (condition.last_transition_time.0, phase))
+            })
+            .max()
+            .map(|(_, phase)| phase)
+    }
+
+    /// Returns true if the replicas of the actor are being resized.
+    fn scaling(&self) -> bool {
+        self.conditions.iter().any(|condition| condition.type_ == SCALING && condition.status == "True")
+    }
+
+    /// Set the condition, replacing the one of the same type in place, or
+    /// appending it. As for the conditions of Kubernetes, the transition time
+    /// only changes with the status, the one of an unchanged status is kept.
+    /// The states of the lifecycle are exclusive, entering one leaves the
+    /// others, whose conditions turn false, while `Degraded` is left as is.
+    fn set_condition(&mut self, mut condition: Condition) {
+        let is_state = |condition: &Condition| ActorPhase::from_type(&condition.type_).is_some();
+        if condition.status == "True" && is_state(&condition) {
+            let others = self.conditions.iter_mut().filter(|existing| existing.type_ != condition.type_);
+            for existing in others.filter(|existing| existing.status == "True" && is_state(existing)) {
+                existing.status = "False".into();
+                existing.last_transition_time = condition.last_transition_time.clone();
+                existing.observed_generation = condition.observed_generation;
+            }
+        }
+
+        match self.conditions.iter_mut().find(|existing| existing.type_ == condition.type_) {
+            Some(existing) => {
+                if existing.status == condition.status {
+                    condition.last_transition_time = existing.last_transition_time.clone();
+                }
+                *existing = condition;
+            }
+            None => self.conditions.push(condition),
+        }
+    }
+
+    /// Returns true if the actor ran to completion.
+    fn succeeded(&self) -> bool {
+        self.conditions.iter().any(|condition| condition.type_ == SUCCEEDED && condition.status == "True")
+    }
+
+    /// Returns the conditions sorted ascending by their last transition time,
+    /// conditions of the same time are ordered as the states of the lifecycle.
+    fn timeline(&self) -> Vec<&Condition> {
+        let order = |condition: &Condition| {
+            STATE_ORDER
+                .iter()
+                .position(|state| state.eq_ignore_ascii_case(&condition.type_))
+                .unwrap_or(STATE_ORDER.len())
+        };
+
+        let mut conditions: Vec<&Condition> = self.conditions.iter().collect();
+        conditions.sort_by(|a, b| {
+            a.last_transition_time
+                .0
+                .cmp(&b.last_transition_time.0)
+                .then_with(|| order(a).cmp(&order(b)))
+                .then_with(|| a.type_.cmp(&b.type_))
+        });
+
+        conditions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorState;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn status(condition: Option<Condition>) -> ActorStatus {
+        let mut status = ActorStatus::default();
+        status.conditions.extend(condition);
+        status
+    }
+
+    fn badge(condition: Option<Condition>) -> (String, String) {
+        status(condition).badge()
+    }
+
+    #[test]
+    fn test_badge() {
+        assert_eq!(badge(Some(ActorState::pending())), ("pending".into(), "blue".into()));
+        assert_eq!(badge(Some(ActorState::building())), ("building".into(), "yellow".into()));
+        assert_eq!(badge(Some(ActorState::running(true, "AutoRun", None))), ("running".into(), "green".into()));
+        assert_eq!(badge(Some(ActorState::failed(true, "BuildFailed", None))), ("failed".into(), "red".into()));
+    }
+
+    #[test]
+    fn test_succeeded() {
+        let status = status(Some(ActorState::succeeded("Completed", Some("exit code 0".into()))));
+
+        assert!(status.succeeded());
+        assert!(!status.running());
+        assert!(!status.failed());
+        assert_eq!(status.conditions[0].type_, "Succeeded");
+        assert_eq!(status.badge(), ("succeeded".into(), "brightgreen".into()));
+    }
+
+    #[test]
+    fn test_scaling() {
+        let status = status(Some(ActorState::scaling("ReplicasChanged", Some("from 1 to 3 replicas".into()))));
+
+        assert!(status.scaling());
+        assert!(!status.running());
+        assert!(!status.succeeded());
+        assert!(status.build_succeeded());
+        assert_eq!(status.conditions[0].type_, "Scaling");
+        assert_eq!(status.badge(), ("scaling".into(), "yellowgreen".into()));
+    }
+
+    #[test]
+    fn test_condition() {
+        let condition = ActorState::condition(ActorPhase::Building, "BuildQueued", Some("waiting".into()));
+
+        assert_eq!(condition.type_, ActorState::building().type_);
+        assert_eq!((condition.status.as_str(), condition.reason.as_str()), ("True", "BuildQueued"));
+        assert_eq!(condition.message, "waiting");
+        assert!(status(Some(condition)).building());
+    }
+
+    #[test]
+    fn test_wrappers_delegate_to_condition() {
+        let without_time = |condition: Condition| Condition {
+            last_transition_time: Time(Utc.timestamp_opt(0, 0).unwrap()),
+            ..condition
+        };
+
+        assert_eq!(
+            without_time(ActorState::scaling("ReplicasChanged", Some("3 replicas".into()))),
+            without_time(ActorState::condition(ActorPhase::Scaling, "ReplicasChanged", Some("3 replicas".into())))
+        );
+        assert_eq!(
+            without_time(ActorState::succeeded("Completed", None)),
+            without_time(ActorState::condition(ActorPhase::Succeeded, "Completed", None))
+        );
+    }
+
+    #[test]
+    fn test_running_is_not_scaling() {
+        assert!(!status(Some(ActorState::running(true, "AutoRun", None))).scaling());
+        assert!(!status(None).scaling());
+    }
+
+    #[test]
+    fn test_running_is_not_succeeded() {
+        assert!(!status(Some(ActorState::running(true, "AutoRun", None))).succeeded());
+        assert!(!status(None).succeeded());
+    }
+
+    fn running(observed_generation: Option<i64>) -> Condition {
+        ActorState::running(true, "AutoRun", None).observed(observed_generation)
+    }
+
+    #[test]
+    fn test_fully_ready() {
+        assert!(status(Some(running(Some(2)))).fully_ready(2));
+        assert!(status(Some(running(Some(3)))).fully_ready(2));
+    }
+
+    #[test]
+    fn test_running_but_stale_generation() {
+        assert!(!status(Some(running(Some(1)))).fully_ready(2));
+        assert!(!status(Some(running(None))).fully_ready(2));
+    }
+
+    #[test]
+    fn test_running_but_degraded() {
+        let mut status = status(Some(running(Some(2))));
+        let mut degraded = running(Some(2));
+        degraded.type_ = "Degraded".into();
+        degraded.reason = "ReplicasUnavailable".into();
+        status.conditions.push(degraded);
+
+        assert!(status.running());
+        assert!(!status.fully_ready(2));
+    }
+
+    #[test]
+    fn test_not_running_is_not_fully_ready() {
+        assert!(!status(Some(ActorState::building())).fully_ready(0));
+        assert!(!status(None).fully_ready(0));
+    }
+
+    #[test]
+    fn test_is_current() {
+        assert!(status(Some(running(Some(2)))).is_current(2));
+        assert!(status(Some(ActorState::building().observed(Some(3)))).is_current(2));
+
+        let mut status = status(Some(at(running(Some(1)), 10)));
+        status.conditions.push(at(ActorState::failed(true, "InvalidBuild", None).observed(Some(2)), 20));
+        assert!(status.is_current(2));
+    }
+
+    #[test]
+    fn test_is_current_with_stale_generation() {
+        assert!(!status(Some(running(Some(1)))).is_current(2));
+        assert!(!status(Some(running(None))).is_current(2));
+        assert!(!status(None).is_current(0));
+
+        let mut status = status(Some(at(running(Some(2)), 10)));
+        status.conditions.push(at(ActorState::pending().observed(Some(1)), 20));
+        assert!(!status.is_current(2));
+    }
+
+    #[test]
+    fn test_badge_without_state() {
+        assert_eq!(badge(None), ("unknown".into(), "lightgrey".into()));
+    }
+
+    fn at(mut condition: Condition, seconds: i64) -> Condition {
+        condition.last_transition_time = Time(Utc.timestamp_opt(seconds, 0).unwrap());
+        condition
+    }
+
+    #[test]
+    fn test_set_condition_with_same_status() {
+        let mut status = self::status(Some(at(ActorState::running(true, "AutoRun", None), 10)));
+        status.set_condition(at(ActorState::running(true, "Scaled", Some("3 replicas".into())), 20));
+
+        assert_eq!(status.conditions.len(), 1);
+        assert_eq!(status.conditions[0].reason, "Scaled");
+        assert_eq!(status.conditions[0].message, "3 replicas");
+        assert_eq!(status.conditions[0].last_transition_time.0.timestamp(), 10);
+    }
+
+    #[test]
+    fn test_set_condition_with_flipped_status() {
+        let mut status = self::status(Some(at(ActorState::running(true, "AutoRun", None), 10)));
+        status.set_condition(at(ActorState::running(false, "NotReady", None), 20));
+
+        assert_eq!(status.conditions.len(), 1);
+        assert_eq!(status.conditions[0].status, "False");
+        assert_eq!(status.conditions[0].last_transition_time.0.timestamp(), 20);
+    }
+
+    #[test]
+    fn test_set_condition_of_new_type() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([at(ActorState::pending(), 10), at(ActorState::building(), 20)]);
+        status.set_condition(at(ActorState::building(), 30));
+        status.set_condition(at(ActorState::running(true, "AutoRun", None), 40));
+
+        let types: Vec<_> = status.conditions.iter().map(|condition| condition.type_.clone()).collect();
+        assert_eq!(
+            types,
+            vec![ActorState::pending().type_, ActorState::building().type_, ActorState::running(true, "", None).type_]
+        );
+        assert_eq!(status.conditions[2].last_transition_time.0.timestamp(), 40);
+    }
+
+    #[test]
+    fn test_set_condition_leaves_the_other_states() {
+        let degraded = Condition { type_: DEGRADED.into(), status: "True".into(), ..at(ActorState::pending(), 10) };
+        let mut status = ActorStatus::default();
+        status.conditions.extend([at(ActorState::pending(), 10), at(ActorState::building(), 20), degraded]);
+        status.set_condition(at(ActorState::running(true, "AutoRun", None), 30));
+
+        let states: Vec<_> = status.conditions.iter().map(|condition| condition.status.as_str()).collect();
+        assert_eq!(states, vec!["False", "False", "True", "True"]);
+        assert_eq!(status.conditions[1].last_transition_time.0.timestamp(), 30);
+        assert_eq!(status.phase(), Some(ActorPhase::Running));
+        assert!(status.running() && !status.building() && !status.pending());
+    }
+
+    #[test]
+    fn test_phase_of_the_latest_condition() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([
+            at(ActorState::failed(true, "BuildFailed", None), 10),
+            at(ActorState::running(true, "AutoRun", None), 30),
+            at(ActorState::building(), 20),
+        ]);
+
+        assert_eq!(status.phase(), Some(ActorPhase::Running));
+        assert_eq!(status.badge(), ("running".into(), "green".into()));
+    }
+
+    #[test]
+    fn test_phase_with_equal_times() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([
+            at(ActorState::pending(), 10),
+            at(ActorState::running(true, "AutoRun", None), 10),
+            at(ActorState::building(), 10),
+        ]);
+        assert_eq!(status.phase(), Some(ActorPhase::Running));
+
+        status.conditions.push(at(ActorState::succeeded("Completed", None), 10));
+        assert_eq!(status.phase(), Some(ActorPhase::Succeeded));
+
+        status.conditions.push(at(ActorState::failed(true, "Crashed", None), 10));
+        assert_eq!(status.phase(), Some(ActorPhase::Failed));
+    }
+
+    #[test]
+    fn test_phase_ignores_false_and_unknown_conditions() {
+        let mut degraded = at(ActorState::running(true, "AutoRun", None), 20);
+        degraded.type_ = "Degraded".into();
+
+        let mut status = ActorStatus::default();
+        status.conditions.extend([
+            at(ActorState::building(), 10),
+            at(ActorState::running(false, "NotReady", None), 30),
+            degraded,
+        ]);
+
+        assert_eq!(status.phase(), Some(ActorPhase::Building));
+        assert_eq!(ActorStatus::default().phase(), None);
+        assert_eq!(ActorPhase::Succeeded.to_string(), "Succeeded");
+    }
+
+    #[test]
+    fn test_build_succeeded() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([at(ActorState::building(), 10), at(ActorState::running(true, "AutoRun", None), 20)]);
+        assert!(status.build_succeeded());
+
+        assert!(!self::status(Some(ActorState::building())).build_succeeded());
+        assert!(!self::status(Some(ActorState::failed(true, "BuildFailed", None))).build_succeeded());
+        assert!(!self::status(None).build_succeeded());
+    }
+
+    #[test]
+    fn test_build_not_succeeded_after_rebuild() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([at(ActorState::running(true, "AutoRun", None), 10), at(ActorState::building(), 20)]);
+
+        assert!(status.running());
+        assert!(!status.build_succeeded());
+    }
+
+    #[test]
+    fn test_timeline() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([
+            at(ActorState::running(true, "AutoRun", None), 30),
+            at(ActorState::pending(), 10),
+            at(ActorState::building(), 20),
+        ]);
+
+        let times: Vec<_> =
+            status.timeline().iter().map(|condition| condition.last_transition_time.0.timestamp()).collect();
+        assert_eq!(times, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn test_timeline_with_equal_times() {
+        let mut status = ActorStatus::default();
+        status.conditions.extend([
+            at(ActorState::failed(true, "BuildFailed", None), 10),
+            at(ActorState::building(), 10),
+            at(ActorState::pending(), 10),
+        ]);
+
+        let types: Vec<_> = status.timeline().iter().map(|condition| condition.type_.clone()).collect();
+        assert_eq!(
+            types,
+            vec![ActorState::pending().type_, ActorState::building().type_, ActorState::failed(true, "", None).type_]
+        );
+    }
+
+    #[test]
+    fn test_conditions_serialize_in_camel_case() {
+        let mut condition = at(ActorState::running(true, "AutoRun", None), 0);
+        condition.observed_generation = Some(1);
+
+        let value = serde_json::to_value(status(Some(condition))).unwrap();
+        let mut keys: Vec<_> = value["conditions"][0].as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["lastTransitionTime", "message", "observedGeneration", "reason", "status", "type"]);
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::iter::Sum;
+use std::ops::Add;
+
+use k8s_openapi::api::core::v1::{PodSpec, ResourceRequirements};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+/// The compute resources an actor reserves on the cluster: the CPU in
+/// millicores and the memory in bytes requested by all its replicas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceFootprint {
+    pub cpu_millis: i64,
+    pub memory_bytes: i64,
+}
+
+impl ResourceFootprint {
+    /// Returns the footprint of the replicas of a container with the resources.
+    /// The requests default to the limits, as they do for the scheduler, and
+    /// the resources that are not set or can not be parsed count as zero.
+    pub fn new(resources: &ResourceRequirements, replicas: i32) -> Self {
+        let quantity = |name: &str| {
+            let requests = resources.requests.as_ref().and_then(|requests| requests.get(name));
+            requests.or_else(|| resources.limits.as_ref().and_then(|limits| limits.get(name)))
+        };
+
+        let replicas = replicas.max(0) as f64;
+        ResourceFootprint {
+            cpu_millis: quantity("cpu")
+                .and_then(parse_quantity)
+                .map_or(0, |cpu| (cpu * 1000.0 * replicas).round() as i64),
+            memory_bytes: quantity("memory")
+                .and_then(parse_quantity)
+                .map_or(0, |memory| (memory * replicas).round() as i64),
+        }
+    }
+
+    /// Returns the footprint of the replicas of a pod, summed over its main
+    /// container and sidecars. The init containers are not counted, as they
+    /// are done before the others start.
+    pub fn of_pod(pod: &PodSpec, replicas: i32) -> Self {
+        pod.containers
+            .iter()
+            .map(|container| Self::new(&container.resources.clone().unwrap_or_default(), replicas))
+            .sum()
+    }
+}
+
+impl Add for ResourceFootprint {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        ResourceFootprint {
+            cpu_millis: self.cpu_millis + other.cpu_millis,
+            memory_bytes: self.memory_bytes + other.memory_bytes,
+        }
+    }
+}
+
+impl Sum for ResourceFootprint {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(ResourceFootprint::default(), Add::add)
+    }
+}
+
+/// Parse a Kubernetes quantity, like `100m`, `1.5`, `128Mi` or `1e3`, to its
+/// value in the base unit. Returns `None` for a malformed or negative quantity.
+pub fn parse_quantity(quantity: &Quantity) -> Option<f64> {
+    let value = quantity.0.trim();
+    let split = value.find(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E').unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+
+    let multiplier = match suffix {
+        "" => 1.0,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 1024.0,
+        "Mi" => 1024f64.powi(2),
+        "Gi" => 1024f64.powi(3),
+        "Ti" => 1024f64.powi(4),
+        "Pi" => 1024f64.powi(5),
+        "Ei" => 1024f64.powi(6),
+        _ => return None,
+    };
+
+    let number: f64 = number.parse().ok()?;
+    if !number.is_finite() || number < 0.0 {
+        return None;
+    }
+    Some(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::Container;
+
+    use super::*;
+
+    fn resources(requests: &[(&str, &str)], limits: &[(&str, &str)]) -> ResourceRequirements {
+        let quantities = |pairs: &[(&str, &str)]| -> Option<BTreeMap<String, Quantity>> {
+            Some(pairs.iter().map(|(name, value)| (name.to_string(), Quantity(value.to_string()))).collect())
+        };
+        ResourceRequirements { requests: quantities(requests), limits: quantities(limits), ..Default::default() }
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        let parse = |value: &str| parse_quantity(&Quantity(value.into()));
+
+        assert_eq!(parse("100m"), Some(0.1));
+        assert_eq!(parse("1.5"), Some(1.5));
+        assert_eq!(parse("128Mi"), Some(134217728.0));
+        assert_eq!(parse("1G"), Some(1e9));
+        assert_eq!(parse("1e3"), Some(1000.0));
+        assert_eq!(parse("abc"), None);
+        assert_eq!(parse("1Xi"), None);
+        assert_eq!(parse("-1"), None);
+    }
+
+    #[test]
+    fn test_footprint() {
+        let footprint = ResourceFootprint::new(&resources(&[("cpu", "250m"), ("memory", "128Mi")], &[]), 2);
+        assert_eq!(footprint, ResourceFootprint { cpu_millis: 500, memory_bytes: 268435456 });
+    }
+
+    #[test]
+    fn test_footprint_defaults_to_limits() {
+        let footprint = ResourceFootprint::new(&resources(&[("cpu", "100m")], &[("cpu", "1"), ("memory", "1Gi")]), 1);
+        assert_eq!(footprint, ResourceFootprint { cpu_millis: 100, memory_bytes: 1073741824 });
+    }
+
+    #[test]
+    fn test_footprint_of_pod() {
+        let container = |name: &str, resources| Container { name: name.into(), resources, ..Default::default() };
+        let pod = PodSpec {
+            containers: vec![
+                container("main", Some(resources(&[("cpu", "500m"), ("memory", "256Mi")], &[]))),
+                container("sidecar", Some(resources(&[("cpu", "100m")], &[("memory", "64Mi")]))),
+                container("unbounded", None),
+            ],
+            init_containers: Some(vec![container("init", Some(resources(&[("cpu", "2")], &[])))]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            ResourceFootprint::of_pod(&pod, 3),
+            ResourceFootprint { cpu_millis: 1800, memory_bytes: 3 * (256 + 64) * 1024 * 1024 }
+        );
+    }
+
+    #[test]
+    fn test_sum() {
+        let footprints = [
+            ResourceFootprint { cpu_millis: 100, memory_bytes: 1024 },
+            ResourceFootprint { cpu_millis: 400, memory_bytes: 2048 },
+        ];
+        assert_eq!(
+            footprints.into_iter().sum::<ResourceFootprint>(),
+            ResourceFootprint { cpu_millis: 500, memory_bytes: 3072 }
+        );
+    }
+}
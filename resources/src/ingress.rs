@@ -0,0 +1,185 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::networking::v1::{
+    HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule, IngressServiceBackend, IngressSpec,
+    IngressTLS, ServiceBackendPort,
+};
+use kube::core::ObjectMeta;
+use kube::{Resource, ResourceExt};
+use serde::{Deserialize, Serialize};
+
+use super::ext::ActorExt;
+
+/// The annotation of cert-manager naming the cluster issuer of the certificate.
+const CLUSTER_ISSUER_KEY: &str = "cert-manager.io/cluster-issuer";
+
+/// The TLS configuration of the Ingress of an actor.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressTls {
+    /// The name of the secret holding the certificate and its key.
+    pub secret_name: String,
+    /// The hosts covered by the certificate, all the hosts of the Ingress if empty.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// The cert-manager cluster issuer of the certificate, if it is managed by cert-manager.
+    #[serde(default)]
+    pub issuer: Option<String>,
+}
+
+/// Build the Ingress routing the external traffic to the exposed ports of the
+/// actor, with the host rendered from the template like the external URLs.
+/// The exposed ports share the host under a path prefix each, unless the
+/// template contains `{port}`. Returns `None` if no port is exposed.
+pub fn new(actor: &Actor, host_template: &str) -> Option<Ingress> {
+    let ports: Vec<i32> = actor
+        .spec
+        .character
+        .deploy
+        .as_ref()
+        .and_then(|deploy| deploy.services.as_ref())
+        .into_iter()
+        .flatten()
+        .flat_map(|service| service.ports.iter())
+        .filter(|port| port.expose.unwrap_or_default())
+        .map(|port| port.port)
+        .collect();
+    if ports.is_empty() {
+        return None;
+    }
+
+    let name = actor.name_any();
+    let host = |port: i32| host_template.replace("{name}", &name).replace("{port}", &port.to_string());
+    let shared = ports.len() > 1 && !host_template.contains("{port}");
+
+    // Route each exposed port to the service of the actor
+    let rules: Vec<IngressRule> = ports
+        .iter()
+        .map(|port| IngressRule {
+            host: Some(host(*port)),
+            http: Some(HTTPIngressRuleValue {
+                paths: vec![HTTPIngressPath {
+                    path: Some(if shared { format!("/{}/", port) } else { "/".into() }),
+                    path_type: "Prefix".into(),
+                    backend: IngressBackend {
+                        service: Some(IngressServiceBackend {
+                            name: name.clone(),
+                            port: Some(ServiceBackendPort { number: Some(*port), ..Default::default() }),
+                        }),
+                        ..Default::default()
+                    },
+                }],
+            }),
+        })
+        .collect();
+
+    // Terminate TLS for the configured hosts, or all of them
+    let config = actor.ingress_tls();
+    let tls = config.as_ref().map(|config| {
+        let mut hosts = config.hosts.clone();
+        if hosts.is_empty() {
+            hosts = rules.iter().filter_map(|rule| rule.host.clone()).collect();
+            hosts.dedup();
+        }
+        vec![IngressTLS { hosts: Some(hosts), secret_name: Some(config.secret_name.clone()) }]
+    });
+
+    // Let cert-manager issue the certificate if an issuer is specified
+    let annotations = config
+        .and_then(|config| config.issuer)
+        .map(|issuer| BTreeMap::from([(CLUSTER_ISSUER_KEY.to_string(), issuer)]));
+
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = BTreeMap::from([
+        ("amphitheatre.app/character".into(), name.clone()),
+        ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
+    ]);
+    let metadata = ObjectMeta {
+        name: Some(name),
+        owner_references: Some(vec![owner_reference]),
+        labels: Some(labels),
+        annotations,
+        ..Default::default()
+    };
+
+    Some(Ingress {
+        metadata,
+        spec: Some(IngressSpec { rules: Some(rules), tls, ..Default::default() }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+    use amp_common::schema::{Deploy, Port, Service};
+
+    use super::*;
+    use crate::options::ActorOptions;
+
+    fn actor(ports: &[i32], tls: Option<&str>) -> Actor {
+        let ports = ports.iter().map(|port| Port { port: *port, expose: Some(true), ..Default::default() }).collect();
+        let mut spec = ActorSpec { name: "api".into(), ..Default::default() };
+        let services = vec![Service { ports, ..Default::default() }];
+        spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+
+        let mut actor = Actor::new("api", spec);
+        actor.metadata.uid = Some("uid".into());
+        let tls = tls.map(|value| serde_json::from_str(value).unwrap());
+        actor.set_options(&ActorOptions { tls, ..Default::default() });
+        actor
+    }
+
+    #[test]
+    fn test_ingress_without_exposed_ports() {
+        assert!(new(&actor(&[], None), "{name}.apps.example.com").is_none());
+    }
+
+    #[test]
+    fn test_ingress_without_tls() {
+        let ingress = new(&actor(&[8080], None), "{name}.apps.example.com").unwrap();
+
+        let spec = ingress.spec.unwrap();
+        assert_eq!(spec.tls, None);
+        assert_eq!(spec.rules.unwrap()[0].host, Some("api.apps.example.com".into()));
+        assert_eq!(ingress.metadata.annotations, None);
+    }
+
+    #[test]
+    fn test_ingress_tls() {
+        let tls = r#"{"secretName": "api-tls", "issuer": "letsencrypt"}"#;
+        let ingress = new(&actor(&[8080, 9090], Some(tls)), "{name}.apps.example.com").unwrap();
+
+        let tls = ingress.spec.unwrap().tls.unwrap();
+        assert_eq!(
+            tls,
+            vec![IngressTLS { hosts: Some(vec!["api.apps.example.com".into()]), secret_name: Some("api-tls".into()) }]
+        );
+        assert_eq!(ingress.metadata.annotations.unwrap()[CLUSTER_ISSUER_KEY], "letsencrypt");
+    }
+
+    #[test]
+    fn test_ingress_tls_with_hosts() {
+        let tls = r#"{"secretName": "api-tls", "hosts": ["api.example.com"]}"#;
+        let ingress = new(&actor(&[8080], Some(tls)), "{name}.apps.example.com").unwrap();
+
+        let tls = &ingress.spec.unwrap().tls.unwrap()[0];
+        assert_eq!(tls.hosts, Some(vec!["api.example.com".into()]));
+        assert_eq!(ingress.metadata.annotations, None);
+    }
+}
@@ -16,18 +16,20 @@ use std::collections::BTreeMap;
 
 use amp_common::resource::Actor;
 use k8s_openapi::api::batch::v1::{Job, JobSpec};
-use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
-use kube::api::{Patch, PatchParams, PostParams};
+use k8s_openapi::api::core::v1::{Pod, PodSpec, PodTemplateSpec};
+use kube::api::{ListParams, Patch, PatchParams, PostParams};
 use kube::core::ObjectMeta;
 use kube::{Api, Client, Resource, ResourceExt};
 
+use crate::containers::pre_build;
 use crate::error::{Error, Result};
-use crate::{hash, LAST_APPLIED_HASH_KEY};
+use crate::ext::ActorExt;
+use crate::{hash, is_digest, LAST_APPLIED_HASH_KEY};
 
 pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
 
     Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
 }
@@ -46,7 +48,7 @@ pub async fn create(client: &Client, actor: &Actor, pod: PodSpec) -> Result<Job>
 pub async fn update(client: &Client, actor: &Actor, pod: PodSpec) -> Result<Job> {
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
 
     let mut job = api.get(&name).await.map_err(Error::KubeError)?;
     tracing::debug!("The Job {} already exists", &name);
@@ -71,7 +73,7 @@ pub async fn update(client: &Client, actor: &Actor, pod: PodSpec) -> Result<Job>
 
 /// Create a Job for build images
 fn new(actor: &Actor, pod: PodSpec) -> Result<Job> {
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
     let owner_reference = actor.controller_owner_ref(&()).unwrap();
     let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&actor.spec)?)]);
     let labels = BTreeMap::from([
@@ -89,6 +91,7 @@ fn new(actor: &Actor, pod: PodSpec) -> Result<Job> {
         },
         spec: Some(JobSpec {
             backoff_limit: Some(0),
+            active_deadline_seconds: actor.build_timeout_seconds().and_then(|seconds| i64::try_from(seconds).ok()),
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta { labels: Some(labels), ..Default::default() }),
                 spec: Some(pod),
@@ -104,7 +107,7 @@ pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
 
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
 
     if let Ok(Some(job)) = api.get_opt(&name).await {
         tracing::debug!("Found Job {}", &name);
@@ -114,3 +117,104 @@ pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
         Ok(false)
     }
 }
+
+/// Returns the digest of the image pushed by the build Job, which the builder
+/// container writes to its termination message, if it does.
+pub async fn digest(client: &Client, actor: &Actor) -> Result<Option<String>> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.build_name();
+
+    let pods =
+        api.list(&ListParams::default().labels(&format!("job-name={}", name))).await.map_err(Error::KubeError)?;
+    let digest = pods
+        .iter()
+        .filter_map(|pod| pod.status.as_ref()?.container_statuses.as_ref())
+        .flatten()
+        .filter_map(|status| status.state.as_ref()?.terminated.as_ref()?.message.as_ref())
+        .map(|message| message.trim())
+        .find(|message| is_digest(message));
+
+    Ok(digest.map(String::from))
+}
+
+/// Check if the build Job failed, and returns a message describing the failure.
+/// The Job is not retried, as its backoff limit is zero.
+pub async fn failure(client: &Client, actor: &Actor) -> Result<Option<String>> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.build_name();
+
+    let job = api.get_opt(&name).await.map_err(Error::KubeError)?;
+    Ok(job.and_then(|job| failure_message(&job)))
+}
+
+fn failure_message(job: &Job) -> Option<String> {
+    let status = job.status.as_ref()?;
+    if status.failed.unwrap_or_default() < 1 {
+        return None;
+    }
+
+    let reason = status
+        .conditions
+        .iter()
+        .flatten()
+        .find(|condition| condition.type_ == "Failed" && condition.status == "True")
+        .and_then(|condition| condition.message.clone().or_else(|| condition.reason.clone()));
+    Some(reason.unwrap_or_else(|| format!("The build Job {} failed", job.name_any())))
+}
+
+/// Check if the pre-build command of the build Job exited with a non-zero
+/// code, and returns a message describing the failure.
+pub async fn pre_build_failure(client: &Client, actor: &Actor) -> Result<Option<String>> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.build_name();
+
+    let pods =
+        api.list(&ListParams::default().labels(&format!("job-name={}", name))).await.map_err(Error::KubeError)?;
+    let code = pods
+        .iter()
+        .filter_map(|pod| pod.status.as_ref()?.init_container_statuses.as_ref())
+        .flatten()
+        .filter(|status| status.name == pre_build::CONTAINER_NAME)
+        .filter_map(|status| status.state.as_ref()?.terminated.as_ref())
+        .map(|terminated| terminated.exit_code)
+        .find(|code| *code != 0);
+
+    Ok(code.map(|code| format!("The pre-build command exited with code {}", code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::batch::v1::{JobCondition, JobStatus};
+
+    use super::*;
+
+    fn job(status: JobStatus) -> Job {
+        Job {
+            metadata: ObjectMeta { name: Some("foo-build".into()), ..Default::default() },
+            status: Some(status),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_failure_message() {
+        assert_eq!(failure_message(&job(JobStatus { active: Some(1), ..Default::default() })), None);
+        assert_eq!(
+            failure_message(&job(JobStatus { failed: Some(1), ..Default::default() })),
+            Some("The build Job foo-build failed".into())
+        );
+
+        let condition = JobCondition {
+            type_: "Failed".into(),
+            status: "True".into(),
+            reason: Some("DeadlineExceeded".into()),
+            message: Some("Job was active longer than specified deadline".into()),
+            ..Default::default()
+        };
+        let status = JobStatus { failed: Some(1), conditions: Some(vec![condition]), ..Default::default() };
+        assert_eq!(failure_message(&job(status)), Some("Job was active longer than specified deadline".into()));
+    }
+}
@@ -28,7 +28,7 @@ pub trait BuildExt {
     // fn builder_orders(&self) -> serde_json::Value;
     fn builder_tag(&self, credentials: &Credentials) -> Result<String>;
     fn store_name(&self) -> String;
-    fn store_image(&self) -> String;
+    fn store_image(&self, default_builder: &str) -> String;
     fn pvc_name(&self) -> String;
 
     fn get_buildpacks_config(&self) -> Option<&BuildpacksConfig>;
@@ -78,11 +78,10 @@ impl BuildExt for CharacterSpec {
             .unwrap_or_else(|| "default-cluster-store".to_string())
     }
 
-    /// Returns the image of the ClusterStore
-    fn store_image(&self) -> String {
-        self.get_buildpacks_config()
-            .map(|config| config.builder.clone())
-            .unwrap_or_else(|| "default-cluster-store".to_string())
+    /// Returns the image of the ClusterStore, the builder of the buildpacks
+    /// config or the default builder of the controller.
+    fn store_image(&self, default_builder: &str) -> String {
+        self.get_buildpacks_config().map(|config| config.builder.clone()).unwrap_or_else(|| default_builder.to_string())
     }
 
     /// Returns the name of the PVC
@@ -231,7 +230,11 @@ mod tests {
             ..Default::default()
         };
 
-        assert_eq!(character.store_image(), "amp-buildpacks/sample-builder:v1");
+        assert_eq!(character.store_image("gcr.io/buildpacks/builder:v1"), "amp-buildpacks/sample-builder:v1");
+        assert_eq!(
+            CharacterSpec::default().store_image("gcr.io/buildpacks/builder:v1"),
+            "gcr.io/buildpacks/builder:v1"
+        );
     }
 
     #[test]
@@ -38,10 +38,10 @@ pub async fn get(client: &Client, actor: &Actor) -> Result<DynamicObject> {
     api.get(&name).await.map_err(Error::KubeError)
 }
 
-pub async fn create(client: &Client, actor: &Actor) -> Result<DynamicObject> {
+pub async fn create(client: &Client, actor: &Actor, default_builder: &str) -> Result<DynamicObject> {
     let api: Api<DynamicObject> = Api::all_with(client.clone(), &api_resource());
 
-    let resource = new(actor).await?;
+    let resource = new(actor, default_builder).await?;
     let builder = api.create(&PostParams::default(), &resource).await.map_err(Error::KubeError)?;
     info!("Created ClusterStore: {}", builder.name_any());
 
@@ -74,7 +74,7 @@ fn api_resource() -> ApiResource {
     ApiResource::from_gvk(&GroupVersionKind::gvk("kpack.io", "v1alpha2", "ClusterStore"))
 }
 
-async fn new(actor: &Actor) -> Result<DynamicObject> {
+async fn new(actor: &Actor, default_builder: &str) -> Result<DynamicObject> {
     let name = actor.spec.character.store_name();
     let resource = from_value(json!({
         "apiVersion": "kpack.io/v1alpha2",
@@ -91,7 +91,7 @@ async fn new(actor: &Actor) -> Result<DynamicObject> {
                 "namespace": "amp-system", // @TODO: Use the namespace from configuration
             },
             "sources": [{
-                "image": actor.spec.character.store_image(),
+                "image": actor.spec.character.store_image(default_builder),
             }],
         }
     }))
@@ -22,12 +22,14 @@ use serde_json::{from_value, json};
 use tracing::{debug, info};
 
 use crate::error::{Error, Result};
+use crate::ext::{ActorExt, BuildSpecExt};
+use crate::is_digest;
 use crate::kpack::BuildExt;
 
 pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
 
     Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
 }
@@ -47,7 +49,7 @@ pub async fn update(client: &Client, actor: &Actor) -> Result<DynamicObject> {
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
 
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
     let mut image = api.get(&name).await.map_err(Error::KubeError)?;
     debug!("The Image \"{}\" already exists", name);
 
@@ -70,7 +72,7 @@ fn api_resource() -> ApiResource {
 }
 
 fn new(actor: &Actor) -> Result<DynamicObject> {
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
     let owner_reference = actor.controller_owner_ref(&()).unwrap();
 
     // Build the source based on the build strategy
@@ -94,12 +96,12 @@ fn new(actor: &Actor) -> Result<DynamicObject> {
 
     let mut build = json!({});
 
-    // Set environment variables if build.env is not empty
-    if let Some(env) = actor.spec.character.build.as_ref().and_then(|build| build.env.as_ref()) {
-        build["env"] = env.iter().map(|(name, value)| json!({"name": name, "value": value})).collect();
+    // Set environment variables if build.env is not empty, sorted by name
+    if let Some(env) = actor.spec.character.build.as_ref().and_then(|build| build.sorted_env()) {
+        build["env"] = env.iter().map(|var| json!({"name": var.name, "value": var.value})).collect();
     }
 
-    let resource = from_value(json!({
+    let mut resource = json!({
         "apiVersion": "kpack.io/v1alpha2",
         "kind": "Image",
         "metadata": {
@@ -122,8 +124,15 @@ fn new(actor: &Actor) -> Result<DynamicObject> {
             "source": source,
             "tag": actor.spec.image,
         }
-    }))
-    .map_err(Error::SerializationError)?;
+    });
+
+    // Push the additional tags along with the primary tag
+    let tags = actor.resolved_post_tags();
+    if !tags.is_empty() {
+        resource["spec"]["additionalTags"] = json!(tags);
+    }
+
+    let resource = from_value(resource).map_err(Error::SerializationError)?;
 
     Ok(resource)
 }
@@ -133,7 +142,7 @@ pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
 
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
-    let name = format!("{}-builder", actor.spec.name);
+    let name = actor.build_name();
 
     if let Some(image) = api.get_opt(&name).await.map_err(Error::KubeError)? {
         debug!("Found Image {}", &name);
@@ -148,3 +157,39 @@ pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
     debug!("Not found Image {}", &name);
     Ok(false)
 }
+
+/// Returns the digest of the latest image built by kpack, read from the
+/// `latestImage` of the Image status, like `registry.local/api@sha256:...`.
+pub async fn digest(client: &Client, actor: &Actor) -> Result<Option<String>> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
+    let name = actor.build_name();
+
+    let image = api.get_opt(&name).await.map_err(Error::KubeError)?;
+    Ok(image.and_then(|image| latest_digest(&image)))
+}
+
+fn latest_digest(image: &DynamicObject) -> Option<String> {
+    let latest = image.data.pointer("/status/latestImage")?.as_str()?;
+    let (_, digest) = latest.rsplit_once('@')?;
+    is_digest(digest).then(|| digest.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(status: serde_json::Value) -> DynamicObject {
+        DynamicObject::new("api-builder", &api_resource()).data(json!({ "status": status }))
+    }
+
+    #[test]
+    fn test_latest_digest() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let latest = format!("registry.local/api@{}", digest);
+        assert_eq!(latest_digest(&image(json!({ "latestImage": latest }))), Some(digest));
+
+        assert_eq!(latest_digest(&image(json!({ "latestImage": "registry.local/api:v1" }))), None);
+        assert_eq!(latest_digest(&image(json!({}))), None);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use amp_common::resource::Actor;
+use kube::api::ObjectMeta;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::containers::application;
+use crate::error::{Error, Result};
+use crate::ext::ActorExt;
+use crate::{deployment, service};
+
+const DEPLOYMENT_FILE: &str = "deployment.yaml";
+const SERVICE_FILE: &str = "service.yaml";
+const HEADLESS_SERVICE_FILE: &str = "headless-service.yaml";
+const KUSTOMIZATION_FILE: &str = "kustomization.yaml";
+
+/// Render the resources of the actor as a Kustomize base, returned as a map of
+/// filename to YAML. The resources are built the same way as when deploying,
+/// less the owner references that only make sense inside the cluster.
+pub fn render_kustomize_base(actor: &Actor) -> Result<HashMap<String, String>> {
+    let mut files = HashMap::new();
+    let mut resources = vec![];
+
+    let mut deployment = deployment::new(actor, application::pod(actor))?;
+    strip(&mut deployment.metadata);
+    files.insert(DEPLOYMENT_FILE.to_string(), to_yaml(&deployment)?);
+    resources.push(DEPLOYMENT_FILE);
+
+    if actor.spec.has_services() {
+        let mut service = service::new(actor)?;
+        strip(&mut service.metadata);
+        files.insert(SERVICE_FILE.to_string(), to_yaml(&service)?);
+        resources.push(SERVICE_FILE);
+
+        if actor.publish_not_ready_addresses() {
+            let mut service = service::headless(actor)?;
+            strip(&mut service.metadata);
+            files.insert(HEADLESS_SERVICE_FILE.to_string(), to_yaml(&service)?);
+            resources.push(HEADLESS_SERVICE_FILE);
+        }
+    }
+
+    let kustomization = json!({
+        "apiVersion": "kustomize.config.k8s.io/v1beta1",
+        "kind": "Kustomization",
+        "resources": resources,
+    });
+    files.insert(KUSTOMIZATION_FILE.to_string(), to_yaml(&kustomization)?);
+
+    Ok(files)
+}
+
+/// Remove the cluster-bound fields from the metadata.
+fn strip(metadata: &mut ObjectMeta) {
+    metadata.owner_references = None;
+}
+
+fn to_yaml<T: Serialize>(resource: &T) -> Result<String> {
+    serde_yaml::to_string(resource).map_err(Error::YamlSerializationError)
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::{ActorSpec, CharacterSpec};
+    use amp_common::schema::{Deploy, Port, Service};
+
+    use super::*;
+    use crate::options::ActorOptions;
+
+    fn actor(services: Option<Vec<Service>>) -> Actor {
+        let deploy = Deploy { services, ..Default::default() };
+        let character = CharacterSpec { deploy: Some(deploy), ..Default::default() };
+        let spec = ActorSpec { name: "test".into(), image: "test".into(), character, ..Default::default() };
+
+        let mut actor = Actor::new("test", spec);
+        actor.metadata.uid = Some("uid".into());
+        actor
+    }
+
+    #[test]
+    fn test_render_kustomize_base() {
+        let port = Port { port: 8080, ..Default::default() };
+        let service = Service { ports: vec![port], ..Default::default() };
+        let files = render_kustomize_base(&actor(Some(vec![service]))).unwrap();
+
+        let mut names: Vec<_> = files.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, vec!["deployment.yaml", "kustomization.yaml", "service.yaml"]);
+        assert_eq!(
+            files["kustomization.yaml"],
+            "apiVersion: kustomize.config.k8s.io/v1beta1\nkind: Kustomization\nresources:\n- deployment.yaml\n- service.yaml\n"
+        );
+        assert!(files["deployment.yaml"].starts_with("apiVersion: apps/v1\nkind: Deployment\n"));
+        assert!(!files["deployment.yaml"].contains("ownerReferences"));
+    }
+
+    #[test]
+    fn test_render_kustomize_base_with_headless_service() {
+        let port = Port { port: 8080, ..Default::default() };
+        let service = Service { ports: vec![port], ..Default::default() };
+        let mut actor = actor(Some(vec![service]));
+        actor.set_options(&ActorOptions { publish_not_ready_addresses: true, ..Default::default() });
+        let files = render_kustomize_base(&actor).unwrap();
+
+        assert!(files["kustomization.yaml"].ends_with("- service.yaml\n- headless-service.yaml\n"));
+        assert!(files["headless-service.yaml"].contains("name: test-headless\n"));
+        assert!(files["headless-service.yaml"].contains("clusterIP: None\n"));
+        assert!(!files["headless-service.yaml"].contains("ownerReferences"));
+    }
+
+    #[test]
+    fn test_render_kustomize_base_without_services() {
+        let files = render_kustomize_base(&actor(None)).unwrap();
+
+        assert!(!files.contains_key("service.yaml"));
+        assert!(files["kustomization.yaml"].ends_with("resources:\n- deployment.yaml\n"));
+    }
+}
@@ -22,6 +22,7 @@ use kube::{Api, Client, Resource, ResourceExt};
 use tracing::info;
 
 use super::error::{Error, Result};
+use super::ext::PlaybookExt;
 
 pub async fn create(client: &Client, playbook: &Playbook) -> Result<Namespace> {
     let api: Api<Namespace> = Api::all(client.clone());
@@ -35,6 +36,22 @@ pub async fn create(client: &Client, playbook: &Playbook) -> Result<Namespace> {
     Ok(namespace)
 }
 
+/// Create the namespace the partners of the playbook are placed in, if any.
+/// It is shared with other playbooks, so it is not owned by this one.
+pub async fn create_partner_namespace(client: &Client, playbook: &Playbook) -> Result<Option<Namespace>> {
+    let Some(name) = playbook.partner_namespace() else { return Ok(None) };
+    let api: Api<Namespace> = Api::all(client.clone());
+
+    let mut resource = new(playbook);
+    resource.metadata.name = Some(name.clone());
+    resource.metadata.owner_references = None;
+    let params = &PatchParams::apply("amp-controllers").force();
+    let namespace = api.patch(&name, params, &Patch::Apply(&resource)).await.map_err(Error::KubeError)?;
+
+    info!("Added partner namespace: {}", namespace.name_any());
+    Ok(Some(namespace))
+}
+
 fn new(playbook: &Playbook) -> Namespace {
     let name = playbook.spec.namespace();
     let owner_reference = playbook.controller_owner_ref(&()).unwrap();
@@ -0,0 +1,274 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::resource::{Actor, Playbook};
+use k8s_openapi::api::networking::v1::{
+    NetworkPolicy, NetworkPolicyEgressRule, NetworkPolicyIngressRule, NetworkPolicyPort, NetworkPolicySpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::api::{DeleteParams, Patch, PatchParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+use tracing::info;
+
+use super::error::{Error, Result};
+use super::ext::{ActorExt, ActorSpecExt};
+use super::playbook;
+
+/// Apply the NetworkPolicy of the actor if it is enabled, or delete the one
+/// left from a previous spec otherwise.
+pub async fn apply(client: &Client, actor: &Actor) -> Result<Option<NetworkPolicy>> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<NetworkPolicy> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    // Lift the restrictions applied while the network policy was enabled
+    if !actor.network_policy() {
+        if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+            api.delete(&name, &DeleteParams::default()).await.map_err(Error::KubeError)?;
+            info!("Deleted NetworkPolicy: {}", name);
+        }
+        return Ok(None);
+    }
+
+    // The partners may be deployed in another namespace, as told by the playbook
+    let playbook = playbook::get(client, &actor.playbook_name()?).await?;
+    let resource = match new(&playbook, actor) {
+        Some(resource) => resource,
+        None => return Ok(None),
+    };
+
+    let params = &PatchParams::apply("amp-controllers").force();
+    let policy = api.patch(&name, params, &Patch::Apply(&resource)).await.map_err(Error::KubeError)?;
+
+    info!("Applied NetworkPolicy: {}", policy.name_any());
+    Ok(Some(policy))
+}
+
+/// Build a default-deny NetworkPolicy for the pods of the actor, which only
+/// allows ingress to the exposed ports, and if the egress policy is enabled,
+/// egress to the partners and DNS. Returns `None` unless the network policy
+/// is enabled for the actor.
+pub fn new(playbook: &Playbook, actor: &Actor) -> Option<NetworkPolicy> {
+    if !actor.network_policy() {
+        return None;
+    }
+
+    let name = actor.name_any();
+
+    // Build the metadata for the network policy
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = BTreeMap::from([
+        ("amphitheatre.app/character".into(), name.clone()),
+        ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
+    ]);
+    let metadata = ObjectMeta {
+        name: Some(name.clone()),
+        owner_references: Some(vec![owner_reference]),
+        labels: Some(labels),
+        ..Default::default()
+    };
+
+    // Allow ingress only to the exposed ports, deny everything else.
+    let ports: Vec<NetworkPolicyPort> = actor
+        .spec
+        .character
+        .deploy
+        .as_ref()
+        .and_then(|deploy| deploy.services.as_ref())
+        .into_iter()
+        .flatten()
+        .flat_map(|service| service.ports.iter())
+        .filter(|port| port.expose.unwrap_or_default())
+        .map(|port| NetworkPolicyPort {
+            port: Some(IntOrString::Int(port.port)),
+            protocol: Some(port.protocol.clone().unwrap_or_else(|| "TCP".into())),
+            ..Default::default()
+        })
+        .collect();
+    let ingress =
+        if ports.is_empty() { vec![] } else { vec![NetworkPolicyIngressRule { ports: Some(ports), from: None }] };
+
+    let mut policy_types = vec!["Ingress".into()];
+    let mut egress = None;
+    if actor.egress_policy() {
+        policy_types.push("Egress".into());
+        egress = Some(egress_rules(playbook, actor));
+    }
+
+    Some(NetworkPolicy {
+        metadata,
+        spec: Some(NetworkPolicySpec {
+            pod_selector: LabelSelector {
+                match_labels: Some(BTreeMap::from([("amphitheatre.app/character".into(), name)])),
+                ..Default::default()
+            },
+            policy_types: Some(policy_types),
+            ingress: Some(ingress),
+            egress,
+        }),
+    })
+}
+
+/// Allow egress to the pods of each partner in its namespace, and to DNS on
+/// any destination, otherwise the partner services could not be resolved at all.
+fn egress_rules(playbook: &Playbook, actor: &Actor) -> Vec<NetworkPolicyEgressRule> {
+    let dns = ["UDP", "TCP"].map(|protocol| NetworkPolicyPort {
+        port: Some(IntOrString::Int(53)),
+        protocol: Some(protocol.into()),
+        ..Default::default()
+    });
+    let mut rules = vec![NetworkPolicyEgressRule { ports: Some(dns.to_vec()), to: None }];
+
+    let peers = actor.spec.partner_egress_targets(playbook);
+    if !peers.is_empty() {
+        rules.push(NetworkPolicyEgressRule { ports: None, to: Some(peers) });
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use amp_common::resource::{ActorSpec, CharacterSpec, Partner, PlaybookSpec};
+    use amp_common::schema::{Deploy, GitReference, Metadata, Port, Service};
+
+    use super::*;
+    use crate::ext::playbook::PARTNER_NAMESPACE_KEY;
+    use crate::options::ActorOptions;
+
+    fn actor(ports: Vec<Port>, enabled: bool) -> Actor {
+        let deploy = Deploy { services: Some(vec![Service { ports, ..Default::default() }]), ..Default::default() };
+        let spec = ActorSpec {
+            name: "test".into(),
+            character: CharacterSpec { deploy: Some(deploy), ..Default::default() },
+            ..Default::default()
+        };
+
+        let mut actor = Actor::new("test", spec);
+        actor.metadata.uid = Some("uid".into());
+        actor.set_options(&ActorOptions { network_policy: enabled, ..Default::default() });
+        actor
+    }
+
+    fn playbook() -> Playbook {
+        Playbook::new("demo", PlaybookSpec { id: "demo".into(), ..Default::default() })
+    }
+
+    fn port(port: i32, expose: bool) -> Port {
+        Port { port, expose: Some(expose), ..Default::default() }
+    }
+
+    #[test]
+    fn test_network_policy_disabled() {
+        assert!(new(&playbook(), &actor(vec![port(80, true)], false)).is_none());
+    }
+
+    #[test]
+    fn test_network_policy_exposed_ports() {
+        let policy = new(&playbook(), &actor(vec![port(80, true), port(9090, false)], true)).unwrap();
+        let spec = policy.spec.unwrap();
+
+        assert_eq!(spec.policy_types, Some(vec!["Ingress".into()]));
+        assert_eq!(
+            spec.pod_selector.match_labels,
+            Some(BTreeMap::from([("amphitheatre.app/character".into(), "test".into())]))
+        );
+
+        let ingress = spec.ingress.unwrap();
+        assert_eq!(ingress.len(), 1);
+        assert_eq!(
+            ingress[0].ports,
+            Some(vec![NetworkPolicyPort {
+                port: Some(IntOrString::Int(80)),
+                protocol: Some("TCP".into()),
+                ..Default::default()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_network_policy_without_exposed_ports() {
+        let policy = new(&playbook(), &actor(vec![port(9090, false)], true)).unwrap();
+
+        assert_eq!(policy.spec.unwrap().ingress, Some(vec![]));
+    }
+
+    #[test]
+    fn test_network_policy_without_egress() {
+        let spec = new(&playbook(), &actor(vec![port(80, true)], true)).unwrap().spec.unwrap();
+
+        assert_eq!(spec.policy_types, Some(vec!["Ingress".into()]));
+        assert!(spec.egress.is_none());
+    }
+
+    #[test]
+    fn test_network_policy_partner_egress() {
+        let mut actor = actor(vec![port(80, true)], true);
+        actor.set_options(&ActorOptions { network_policy: true, egress_policy: true, ..Default::default() });
+
+        let partner = Partner::Repository(GitReference::default());
+        actor.spec.character.partners =
+            Some(HashMap::from([("redis".into(), partner.clone()), ("postgres".into(), partner)]));
+
+        let spec = new(&playbook(), &actor).unwrap().spec.unwrap();
+        assert_eq!(spec.policy_types, Some(vec!["Ingress".into(), "Egress".into()]));
+
+        let egress = spec.egress.unwrap();
+        assert_eq!(egress.len(), 2);
+        assert!(egress[0].to.is_none());
+
+        let partners: Vec<_> = egress[1]
+            .to
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|peer| peer.pod_selector.unwrap().match_labels.unwrap()["amphitheatre.app/character"].clone())
+            .collect();
+        assert_eq!(partners, vec!["postgres", "redis"]);
+    }
+
+    #[test]
+    fn test_network_policy_partner_in_another_namespace() {
+        let mut actor = actor(vec![port(80, true)], true);
+        actor.set_options(&ActorOptions { network_policy: true, egress_policy: true, ..Default::default() });
+
+        let partner = Partner::Repository(GitReference::default());
+        actor.spec.character.partners = Some(HashMap::from([("postgres".into(), partner.clone())]));
+
+        let mut playbook = playbook();
+        playbook.spec.characters = Some(vec![CharacterSpec {
+            meta: Metadata { name: "test".into(), ..Default::default() },
+            partners: Some(HashMap::from([("postgres".into(), partner)])),
+            ..Default::default()
+        }]);
+        playbook.annotations_mut().insert(PARTNER_NAMESPACE_KEY.into(), "shared-db".into());
+
+        let egress = new(&playbook, &actor).unwrap().spec.unwrap().egress.unwrap();
+        let peer = &egress[1].to.as_ref().unwrap()[0];
+        assert_eq!(
+            peer.namespace_selector.as_ref().unwrap().match_labels,
+            Some(BTreeMap::from([("kubernetes.io/metadata.name".into(), "shared-db".into())]))
+        );
+        assert_eq!(
+            peer.pod_selector.as_ref().unwrap().match_labels,
+            Some(BTreeMap::from([("amphitheatre.app/character".into(), "postgres".into())]))
+        );
+    }
+}
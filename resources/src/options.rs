@@ -0,0 +1,187 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{Probe, ResourceRequirements};
+use serde::{Deserialize, Serialize};
+
+use crate::canary::CanaryConfig;
+use crate::containers::artifacts::SharedArtifact;
+use crate::containers::EnvSource;
+use crate::error::{Error, Result};
+use crate::ext::{BuildCache, BuildSecret, DeploymentStrategy};
+use crate::ingress::IngressTls;
+use crate::ports::PortMapping;
+
+/// The annotation holding the options of an actor as a JSON object.
+pub const OPTIONS_KEY: &str = "amphitheatre.app/options";
+
+/// The version of the options understood by the controller.
+pub const OPTIONS_VERSION: &str = "v1";
+
+/// The options of an actor that the spec of amp-common does not carry yet,
+/// held as a single versioned JSON object in the `amphitheatre.app/options`
+/// annotation, like `{"version": "v1", "replicas": 3, "buildTimeout": "30m"}`.
+/// Unknown options are rejected, so that a typo is reported by the validation
+/// of the actor rather than silently ignored.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct ActorOptions {
+    pub version: String,
+    #[serde(skip_serializing_if = "is_false")]
+    pub allow_reserved_env: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_cache: Option<BuildCache>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build_secrets: Vec<BuildSecret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canary: Option<CanaryConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency_policy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deploy_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployment_strategy: Option<DeploymentStrategy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dockerfile_target: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub egress_policy: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, EnvSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_pull_policy: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liveness_probe: Option<Probe>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub materialize_env: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub network_policy: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub ports: BTreeMap<i32, PortMapping>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub post_build_tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pre_build: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_build_image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub publish_not_ready_addresses: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readiness_probe: Option<Probe>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_affinity: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shared_artifacts: Vec<SharedArtifact>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub sync: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sync_refs: Vec<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub tag_from_reference: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<IngressTls>,
+}
+
+impl ActorOptions {
+    /// Parse the options from the value of the annotation. Fails if it is not
+    /// a JSON object of known options, or is not of the supported version.
+    pub fn parse(value: &str) -> Result<Self> {
+        let options: ActorOptions =
+            serde_json::from_str(value).map_err(|err| Error::InvalidOptions(err.to_string()))?;
+
+        match options.version.as_str() {
+            OPTIONS_VERSION => Ok(options),
+            "" => Err(Error::InvalidOptions(format!("version is required, expected `{}`", OPTIONS_VERSION))),
+            version => Err(Error::InvalidOptions(format!(
+                "version `{}` is not supported, expected `{}`",
+                version, OPTIONS_VERSION
+            ))),
+        }
+    }
+
+    /// Returns the value of the annotation, stamped with the supported version.
+    pub fn to_annotation(&self) -> String {
+        let options = ActorOptions { version: OPTIONS_VERSION.into(), ..self.clone() };
+        serde_json::to_string(&options).unwrap_or_default()
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let options = ActorOptions::parse(r#"{"version": "v1", "replicas": 3, "buildTimeout": "30m"}"#).unwrap();
+        assert_eq!(options.replicas, Some(3));
+        assert_eq!(options.build_timeout, Some("30m".into()));
+        assert!(!options.sync);
+
+        let options = ActorOptions::parse(r#"{"version": "v1", "deploymentStrategy": "blue-green"}"#).unwrap();
+        assert_eq!(options.deployment_strategy, Some(DeploymentStrategy::BlueGreen));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let error = |value: &str| ActorOptions::parse(value).unwrap_err().to_string();
+
+        assert!(error(r#"{"version": "v1", "replica": 3}"#).starts_with("InvalidOptions: unknown field `replica`"));
+        assert!(error(r#"{"version": "v1", "replicas": "many"}"#).starts_with("InvalidOptions: invalid type"));
+        assert!(error("replicas=3").starts_with("InvalidOptions: expected value"));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            ActorOptions::parse(r#"{"replicas": 3}"#).unwrap_err().to_string(),
+            "InvalidOptions: version is required, expected `v1`"
+        );
+        assert_eq!(
+            ActorOptions::parse(r#"{"version": "v2"}"#).unwrap_err().to_string(),
+            "InvalidOptions: version `v2` is not supported, expected `v1`"
+        );
+    }
+
+    #[test]
+    fn test_to_annotation() {
+        let options = ActorOptions { replicas: Some(3), sync: true, ..Default::default() };
+
+        let value = options.to_annotation();
+        assert_eq!(value, r#"{"version":"v1","replicas":3,"sync":true}"#);
+        assert_eq!(ActorOptions::parse(&value).unwrap(), ActorOptions { version: "v1".into(), ..options });
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+
+use amp_common::resource::Actor;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::{DeleteParams, ListParams};
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use tracing::info;
+
+use super::error::{Error, Result};
+use super::{managed_selector, MANAGED_BY_KEY};
+
+const CHARACTER_KEY: &str = "amphitheatre.app/character";
+
+/// Returns the resources managed by Amphitheatre whose actor no longer exists
+/// in their namespace, e.g. after the owner references or the finalizer of
+/// the actor were removed by hand.
+pub fn find_orphans<'a, K: Resource>(actors: &[Actor], resources: &'a [K]) -> Vec<&'a K> {
+    resources
+        .iter()
+        .filter(|resource| resource.labels().contains_key(MANAGED_BY_KEY))
+        .filter(|resource| {
+            let character = resource.labels().get(CHARACTER_KEY).cloned().unwrap_or_else(|| resource.name_any());
+            !actors.iter().any(|actor| actor.namespace() == resource.namespace() && actor.name_any() == character)
+        })
+        .collect()
+}
+
+/// Delete the orphaned Deployments and Services in all the namespaces, see
+/// [`find_orphans`]. It is run periodically rather than on the deletion of an
+/// actor, as an orphan has by definition no actor left to be reconciled.
+pub async fn delete_orphans(client: &Client) -> Result<()> {
+    let api: Api<Actor> = Api::all(client.clone());
+    let actors = api.list(&ListParams::default()).await.map_err(Error::KubeError)?;
+
+    delete::<Deployment>(client, &actors.items).await?;
+    delete::<Service>(client, &actors.items).await?;
+
+    Ok(())
+}
+
+async fn delete<K>(client: &Client, actors: &[Actor]) -> Result<()>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + Debug + DeserializeOwned,
+    K::DynamicType: Default,
+{
+    let api: Api<K> = Api::all(client.clone());
+    let resources = api.list(&ListParams::default().labels(&managed_selector())).await.map_err(Error::KubeError)?;
+
+    for resource in find_orphans(actors, &resources.items) {
+        let namespace = resource.namespace().unwrap_or_default();
+        let api: Api<K> = Api::namespaced(client.clone(), &namespace);
+        api.delete(&resource.name_any(), &DeleteParams::default()).await.map_err(Error::KubeError)?;
+        info!("Deleted orphaned {}: {}/{}", K::kind(&Default::default()), namespace, resource.name_any());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn child<K: Resource + Default>(namespace: &str, character: &str) -> K {
+        let mut resource = K::default();
+        resource.meta_mut().name = Some(character.into());
+        resource.meta_mut().namespace = Some(namespace.into());
+        resource.meta_mut().labels = Some(BTreeMap::from([
+            (CHARACTER_KEY.into(), character.into()),
+            (MANAGED_BY_KEY.into(), "Amphitheatre".into()),
+        ]));
+        resource
+    }
+
+    fn names<K: Resource>(resources: Vec<&K>) -> Vec<String> {
+        resources.into_iter().map(|resource| resource.name_any()).collect()
+    }
+
+    #[test]
+    fn test_find_orphans() {
+        let mut actor = Actor::new("api", Default::default());
+        actor.metadata.namespace = Some("default".into());
+
+        let mut unmanaged: Deployment = child("default", "gone");
+        unmanaged.metadata.labels = None;
+
+        let deployments = vec![child("default", "api"), child("default", "web"), child("other", "api"), unmanaged];
+        assert_eq!(names(find_orphans(&[actor], &deployments)), vec!["web", "api"]);
+    }
+
+    #[test]
+    fn test_find_orphaned_services() {
+        let mut actor = Actor::new("api", Default::default());
+        actor.metadata.namespace = Some("default".into());
+
+        let services: Vec<Service> = vec![child("default", "api"), child("default", "web")];
+        assert_eq!(names(find_orphans(&[actor], &services)), vec!["web"]);
+    }
+}
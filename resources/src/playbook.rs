@@ -29,6 +29,7 @@ use tokio::time::sleep;
 use tracing::{debug, info};
 
 use super::error::{Error, Result};
+use super::ext::ConditionExt;
 
 pub async fn install(client: &Client) -> Result<()> {
     let api: Api<CustomResourceDefinition> = Api::all(client.clone());
@@ -103,6 +104,7 @@ pub async fn add(client: &Client, playbook: &Playbook, character: CharacterSpec)
 pub async fn patch_status(client: &Client, playbook: &Playbook, condition: Condition) -> Result<()> {
     let api: Api<Playbook> = Api::all(client.clone());
 
+    let condition = condition.observed(playbook.metadata.generation);
     let status = json!({ "status": { "conditions": vec![condition.clone()] }});
     let playbook = api
         .patch_status(playbook.name_any().as_str(), &PatchParams::default(), &Patch::Merge(&status))
@@ -0,0 +1,149 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashSet};
+
+use k8s_openapi::api::core::v1::{ContainerPort, ServicePort};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use serde::{Deserialize, Serialize};
+
+/// Where the traffic to a service port goes: the port the container listens
+/// on, if different from the exposed one, and the port on the nodes.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortMapping {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_port: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_port: Option<i32>,
+}
+
+/// Name the unnamed container ports after their protocol and port, e.g. `tcp-8080`.
+pub fn name_container_ports(ports: &mut [ContainerPort]) {
+    let names = derive_names(ports.iter().map(|p| (p.name.as_deref(), p.protocol.as_deref(), p.container_port)));
+    for (port, name) in ports.iter_mut().zip(names) {
+        port.name = Some(name);
+    }
+}
+
+/// Name the unnamed service ports after their protocol and port, e.g. `tcp-8080`.
+pub fn name_service_ports(ports: &mut [ServicePort]) {
+    let names = derive_names(ports.iter().map(|p| (p.name.as_deref(), p.protocol.as_deref(), p.port)));
+    for (port, name) in ports.iter_mut().zip(names) {
+        port.name = Some(name);
+    }
+}
+
+/// Set the target and node ports of the service ports from their mappings,
+/// keyed by the exposed port. The target defaults to the exposed port.
+pub fn map_service_ports(ports: &mut [ServicePort], mappings: &BTreeMap<i32, PortMapping>) {
+    for port in ports.iter_mut() {
+        let mapping = mappings.get(&port.port).cloned().unwrap_or_default();
+        port.target_port = Some(IntOrString::Int(mapping.target_port.unwrap_or(port.port)));
+        port.node_port = mapping.node_port;
+    }
+}
+
+/// Keep the explicit names, and derive the others from the protocol (TCP by
+/// default) and the port. A derived name that is already taken gets a numeric
+/// suffix, so the names are always unique and stable for the same input.
+fn derive_names<'a>(ports: impl Iterator<Item = (Option<&'a str>, Option<&'a str>, i32)>) -> Vec<String> {
+    let ports: Vec<_> = ports.collect();
+    let mut taken: HashSet<String> = ports.iter().filter_map(|(name, _, _)| name.map(String::from)).collect();
+
+    ports
+        .iter()
+        .map(|(name, protocol, port)| {
+            if let Some(name) = name {
+                return name.to_string();
+            }
+
+            let base = format!("{}-{}", protocol.unwrap_or("TCP").to_ascii_lowercase(), port);
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while taken.contains(&name) {
+                name = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+
+            taken.insert(name.clone());
+            name
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(name: Option<&str>, protocol: Option<&str>, port: i32) -> ContainerPort {
+        ContainerPort {
+            name: name.map(Into::into),
+            protocol: protocol.map(Into::into),
+            container_port: port,
+            ..Default::default()
+        }
+    }
+
+    fn names(ports: &[ContainerPort]) -> Vec<&str> {
+        ports.iter().map(|port| port.name.as_deref().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_name_container_ports() {
+        let mut ports = vec![port(None, None, 8080), port(Some("metrics"), None, 9090), port(None, Some("UDP"), 53)];
+        name_container_ports(&mut ports);
+
+        assert_eq!(names(&ports), vec!["tcp-8080", "metrics", "udp-53"]);
+    }
+
+    #[test]
+    fn test_name_container_ports_collision() {
+        let mut ports = vec![port(None, None, 8080), port(Some("tcp-8080"), None, 8081), port(None, None, 8080)];
+        name_container_ports(&mut ports);
+
+        assert_eq!(names(&ports), vec!["tcp-8080-2", "tcp-8080", "tcp-8080-3"]);
+    }
+
+    #[test]
+    fn test_name_service_ports() {
+        let mut ports = vec![ServicePort { port: 80, ..Default::default() }];
+        name_service_ports(&mut ports);
+
+        assert_eq!(ports[0].name.as_deref(), Some("tcp-80"));
+    }
+
+    #[test]
+    fn test_port_mapping_round_trip() {
+        let mapping = PortMapping { target_port: Some(8080), node_port: Some(30080) };
+        let value = serde_json::to_string(&mapping).unwrap();
+
+        assert_eq!(value, r#"{"targetPort":8080,"nodePort":30080}"#);
+        assert_eq!(serde_json::from_str::<PortMapping>(&value).unwrap(), mapping);
+        assert_eq!(serde_json::from_str::<PortMapping>("{}").unwrap(), PortMapping::default());
+    }
+
+    #[test]
+    fn test_map_service_ports() {
+        let mut ports =
+            vec![ServicePort { port: 80, ..Default::default() }, ServicePort { port: 9090, ..Default::default() }];
+        let mappings = BTreeMap::from([(80, PortMapping { target_port: Some(8080), node_port: Some(30080) })]);
+        map_service_ports(&mut ports, &mappings);
+
+        assert_eq!(ports[0].target_port, Some(IntOrString::Int(8080)));
+        assert_eq!(ports[0].node_port, Some(30080));
+        assert_eq!(ports[1].target_port, Some(IntOrString::Int(9090)));
+        assert_eq!(ports[1].node_port, None);
+    }
+}
@@ -13,16 +13,73 @@
 // limitations under the License.
 
 use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 
 use amp_common::resource::Actor;
-use k8s_openapi::api::core::v1::{Service, ServiceSpec};
-use kube::api::{Patch, PatchParams, PostParams};
+use k8s_openapi::api::core::v1::{Service, ServicePort, ServiceSpec};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::api::{DeleteParams, Patch, PatchParams, PostParams};
 use kube::core::ObjectMeta;
 use kube::{Api, Client, Resource, ResourceExt};
-use tracing::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::{debug, info};
 
 use super::error::{Error, Result};
-use super::{hash, LAST_APPLIED_HASH_KEY};
+use super::ext::{ActorExt, ActorSpecExt};
+use super::ports::{map_service_ports, name_service_ports};
+use super::{blue_green, hash, LAST_APPLIED_HASH_KEY};
+
+/// The suffix of the name of the headless companion Service, see [`headless`].
+const HEADLESS_SUFFIX: &str = "-headless";
+
+/// The type of the Service exposing the actor, `ClusterIP` by default.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ServiceKind {
+    #[default]
+    ClusterIP,
+    NodePort,
+    LoadBalancer,
+    ExternalName,
+}
+
+impl ServiceKind {
+    const ALL: [ServiceKind; 4] =
+        [ServiceKind::ClusterIP, ServiceKind::NodePort, ServiceKind::LoadBalancer, ServiceKind::ExternalName];
+}
+
+impl fmt::Display for ServiceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceKind::ClusterIP => write!(f, "ClusterIP"),
+            ServiceKind::NodePort => write!(f, "NodePort"),
+            ServiceKind::LoadBalancer => write!(f, "LoadBalancer"),
+            ServiceKind::ExternalName => write!(f, "ExternalName"),
+        }
+    }
+}
+
+impl FromStr for ServiceKind {
+    type Err = Error;
+
+    /// Parse the type in the casing of Kubernetes. Any other value is rejected,
+    /// suggesting the type it differs from only in casing, if any.
+    fn from_str(value: &str) -> Result<Self> {
+        if let Some(kind) = ServiceKind::ALL.into_iter().find(|kind| kind.to_string() == value) {
+            return Ok(kind);
+        }
+
+        let message = match ServiceKind::ALL.into_iter().find(|kind| kind.to_string().eq_ignore_ascii_case(value)) {
+            Some(kind) => format!("service type `{}` is unknown, did you mean `{}`?", value, kind),
+            None => format!(
+                "service type `{}` is unknown, expected one of ClusterIP, NodePort, LoadBalancer or ExternalName",
+                value
+            ),
+        };
+        Err(Error::InvalidServiceType(message))
+    }
+}
 
 pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
     let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
@@ -53,7 +110,7 @@ pub async fn update(client: &Client, actor: &Actor) -> Result<Service> {
     let mut service = api.get(&name).await.map_err(Error::KubeError)?;
     tracing::debug!("The Service {} already exists: {:?}", &name, service);
 
-    let expected_hash = hash(&actor.spec)?;
+    let expected_hash = expected_hash(actor)?;
     let found_hash: String = service.annotations().get(LAST_APPLIED_HASH_KEY).map_or("".into(), |v| v.into());
 
     if found_hash == expected_hash {
@@ -64,14 +121,26 @@ pub async fn update(client: &Client, actor: &Actor) -> Result<Service> {
     let resource = new(actor)?;
     tracing::debug!("The updating Service resource:\n {:?}\n", resource);
 
-    let params = &PatchParams::apply("amp-controllers").force();
-    service = api.patch(&name, params, &Patch::Apply(&resource)).await.map_err(Error::KubeError)?;
+    // Only the managed fields are patched, along with the hash of the spec,
+    // so the fields assigned by the cluster are left as they are.
+    let mut patch = patch(&resource, &service).unwrap_or_else(|| json!({}));
+    patch["metadata"] = json!({
+        "labels": resource.metadata.labels,
+        "annotations": { LAST_APPLIED_HASH_KEY: expected_hash },
+    });
+    service = api.patch(&name, &PatchParams::default(), &Patch::Merge(&patch)).await.map_err(Error::KubeError)?;
 
     tracing::info!("Updated Service: {}", service.name_any());
     Ok(service)
 }
 
-fn new(actor: &Actor) -> Result<Service> {
+/// Returns the hash of the inputs of the Service, the spec and the rendered
+/// workload of the actor, so that changing its options updates the Service.
+fn expected_hash(actor: &Actor) -> Result<String> {
+    hash(&(&actor.spec, actor.render_cache_key()))
+}
+
+pub(crate) fn new(actor: &Actor) -> Result<Service> {
     let name = actor.name_any();
 
     // Build the metadata for the service
@@ -80,11 +149,13 @@ fn new(actor: &Actor) -> Result<Service> {
         ("amphitheatre.app/character".into(), name.clone()),
         ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
     ]);
-    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&actor.spec)?)]);
+    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), expected_hash(actor)?)]);
+    let mut metadata_labels = labels;
+    metadata_labels.extend(actor.metrics_labels());
     let metadata = ObjectMeta {
         name: Some(name),
         owner_references: Some(vec![owner_reference]),
-        labels: Some(labels.clone()),
+        labels: Some(metadata_labels),
         annotations: Some(annotations),
         ..Default::default()
     };
@@ -94,11 +165,334 @@ fn new(actor: &Actor) -> Result<Service> {
     if let Some(deploy) = &actor.spec.character.deploy {
         service_ports = deploy.service_ports();
     }
+    if let Some(ports) = service_ports.as_mut() {
+        name_service_ports(ports);
+        map_service_ports(ports, &actor.port_mappings());
+    }
 
     // Build and return the service resource.
     Ok(Service {
         metadata,
-        spec: Some(ServiceSpec { selector: Some(labels), ports: service_ports, ..Default::default() }),
+        spec: Some(ServiceSpec {
+            type_: Some(actor.spec.service_type()?.to_string()),
+            selector: Some(blue_green::active_selector(actor)),
+            ports: service_ports,
+            session_affinity: actor.session_affinity(),
+            ..Default::default()
+        }),
         ..Default::default()
     })
 }
+
+/// Returns the name of the headless companion Service of the actor.
+pub fn headless_name(actor: &Actor) -> String {
+    format!("{}{}", actor.name_any(), HEADLESS_SUFFIX)
+}
+
+/// Apply the headless companion Service of the actor if it publishes the not
+/// ready addresses, or delete the one left from a previous spec otherwise.
+pub async fn apply_headless(client: &Client, actor: &Actor) -> Result<Option<Service>> {
+    let namespace = actor.namespace().ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Service> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = headless_name(actor);
+
+    if !actor.publish_not_ready_addresses() {
+        if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+            api.delete(&name, &DeleteParams::default()).await.map_err(Error::KubeError)?;
+            info!("Deleted headless Service: {}", name);
+        }
+        return Ok(None);
+    }
+
+    let resource = headless(actor)?;
+    let params = &PatchParams::apply("amp-controllers").force();
+    let service = api.patch(&name, params, &Patch::Apply(&resource)).await.map_err(Error::KubeError)?;
+
+    info!("Applied headless Service: {}", service.name_any());
+    Ok(Some(service))
+}
+
+/// Build the headless companion of the Service of the actor, which resolves to
+/// the addresses of all its pods, ready or not, for the peers to discover each
+/// other before readiness. The Service itself only routes to the ready pods,
+/// so both the "all pods" and the "ready only" views are exposed.
+pub(crate) fn headless(actor: &Actor) -> Result<Service> {
+    let mut service = new(actor)?;
+    service.metadata.name = Some(headless_name(actor));
+    service.metadata.annotations = None;
+
+    // A headless Service has no virtual IP, so neither node ports nor affinity
+    let spec = service.spec.get_or_insert_with(Default::default);
+    spec.type_ = Some(ServiceKind::ClusterIP.to_string());
+    spec.cluster_ip = Some("None".into());
+    spec.publish_not_ready_addresses = Some(true);
+    spec.session_affinity = None;
+    for port in spec.ports.iter_mut().flatten() {
+        port.node_port = None;
+    }
+
+    Ok(service)
+}
+
+/// Returns the minimal merge patch turning the live service into the desired
+/// one, or `None` if their managed fields (ports, type, selector, affinity) are the
+/// same. The fields assigned by the cluster, like the cluster IP and the node
+/// ports, are ignored unless they are specified by the desired service.
+pub fn patch(desired: &Service, actual: &Service) -> Option<Value> {
+    let default = ServiceSpec::default();
+    let desired = desired.spec.as_ref().unwrap_or(&default);
+    let actual = actual.spec.as_ref().unwrap_or(&default);
+    let mut spec = Map::new();
+
+    let live_ports = actual.ports.as_deref().unwrap_or_default();
+    let ports = desired_ports(desired.ports.as_deref().unwrap_or_default(), live_ports);
+    if ports.as_slice() != live_ports {
+        spec.insert("ports".into(), json!(ports));
+    }
+
+    let type_ = desired.type_.as_deref().unwrap_or("ClusterIP");
+    if actual.type_.as_deref().unwrap_or("ClusterIP") != type_ {
+        spec.insert("type".into(), json!(type_));
+    }
+
+    if desired.selector != actual.selector {
+        spec.insert("selector".into(), json!(desired.selector));
+    }
+
+    if desired.publish_not_ready_addresses.unwrap_or_default() != actual.publish_not_ready_addresses.unwrap_or_default()
+    {
+        spec.insert("publishNotReadyAddresses".into(), json!(desired.publish_not_ready_addresses));
+    }
+
+    let session_affinity = desired.session_affinity.as_deref().unwrap_or("None");
+    if actual.session_affinity.as_deref().unwrap_or("None") != session_affinity {
+        spec.insert("sessionAffinity".into(), json!(session_affinity));
+    }
+
+    if let Some(cluster_ip) = desired.cluster_ip.as_ref().filter(|ip| actual.cluster_ip.as_ref() != Some(*ip)) {
+        spec.insert("clusterIP".into(), json!(cluster_ip));
+    }
+
+    (!spec.is_empty()).then(|| json!({ "spec": spec }))
+}
+
+/// Complete the desired ports with the defaults of the cluster, TCP and the
+/// port as the target, and with the node ports it assigned to the live ones
+/// of the same port and protocol, so that they compare equal when unchanged.
+fn desired_ports(desired: &[ServicePort], actual: &[ServicePort]) -> Vec<ServicePort> {
+    desired
+        .iter()
+        .map(|port| {
+            let mut port = port.clone();
+            port.protocol.get_or_insert_with(|| "TCP".into());
+            port.target_port.get_or_insert(IntOrString::Int(port.port));
+            if port.node_port.is_none() {
+                port.node_port = actual
+                    .iter()
+                    .find(|live| live.port == port.port && live.protocol == port.protocol)
+                    .and_then(|live| live.node_port);
+            }
+            port
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+
+    use super::*;
+    use crate::options::ActorOptions;
+    use crate::ports::PortMapping;
+
+    fn actor(publish_not_ready_addresses: bool) -> Actor {
+        with_options(ActorOptions { publish_not_ready_addresses, ..Default::default() })
+    }
+
+    fn with_options(options: ActorOptions) -> Actor {
+        let mut actor = Actor::new("test", ActorSpec { name: "test".into(), ..Default::default() });
+        actor.metadata.uid = Some("uid".into());
+        actor.set_options(&options);
+        actor
+    }
+
+    #[test]
+    fn test_metrics_labels() {
+        let service = new(&actor(false)).unwrap();
+
+        let labels = service.metadata.labels.unwrap();
+        assert_eq!(labels["amphitheatre.app/actor"], "test");
+        assert!(!service.spec.unwrap().selector.unwrap().contains_key("amphitheatre.app/actor"));
+    }
+
+    #[test]
+    fn test_publish_not_ready_addresses() {
+        // The Service only routes to the ready pods, its headless companion to all
+        let spec = new(&actor(true)).unwrap().spec.unwrap();
+        assert_eq!(spec.publish_not_ready_addresses, None);
+
+        let service = headless(&actor(true)).unwrap();
+        assert_eq!(service.metadata.name.as_deref(), Some("test-headless"));
+        assert_eq!(service.metadata.annotations, None);
+
+        let spec = service.spec.unwrap();
+        assert_eq!(spec.publish_not_ready_addresses, Some(true));
+        assert_eq!(spec.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(spec.type_.as_deref(), Some("ClusterIP"));
+    }
+
+    #[test]
+    fn test_session_affinity() {
+        let options = ActorOptions { session_affinity: Some("ClientIP".into()), ..Default::default() };
+        let spec = new(&with_options(options.clone())).unwrap().spec.unwrap();
+        assert_eq!(spec.session_affinity.as_deref(), Some("ClientIP"));
+
+        // The headless companion has no virtual IP to stick the clients to
+        let options = ActorOptions { publish_not_ready_addresses: true, ..options };
+        let spec = headless(&with_options(options)).unwrap().spec.unwrap();
+        assert_eq!(spec.session_affinity, None);
+
+        assert_eq!(new(&actor(false)).unwrap().spec.unwrap().session_affinity, None);
+    }
+
+    #[test]
+    fn test_service_kind() {
+        for (value, kind) in [
+            ("ClusterIP", ServiceKind::ClusterIP),
+            ("NodePort", ServiceKind::NodePort),
+            ("LoadBalancer", ServiceKind::LoadBalancer),
+            ("ExternalName", ServiceKind::ExternalName),
+        ] {
+            assert_eq!(value.parse::<ServiceKind>().unwrap(), kind);
+            assert_eq!(kind.to_string(), value);
+            assert_eq!(serde_json::to_value(kind).unwrap(), json!(value));
+            assert_eq!(serde_json::from_value::<ServiceKind>(json!(value)).unwrap(), kind);
+        }
+        assert_eq!(ServiceKind::default(), ServiceKind::ClusterIP);
+    }
+
+    #[test]
+    fn test_unknown_service_kind() {
+        let error = |value: &str| value.parse::<ServiceKind>().unwrap_err().to_string();
+
+        assert_eq!(
+            error("nodeport"),
+            "InvalidServiceType: service type `nodeport` is unknown, did you mean `NodePort`?"
+        );
+        assert_eq!(
+            error("Headless"),
+            "InvalidServiceType: service type `Headless` is unknown, \
+            expected one of ClusterIP, NodePort, LoadBalancer or ExternalName"
+        );
+        assert!(serde_json::from_value::<ServiceKind>(json!("clusterip")).is_err());
+    }
+
+    #[test]
+    fn test_service_type() {
+        use amp_common::schema::{self, Deploy, Port};
+
+        let mut actor = actor(false);
+        assert_eq!(new(&actor).unwrap().spec.unwrap().type_, Some("ClusterIP".into()));
+
+        let ports = vec![Port { port: 80, ..Default::default() }];
+        let services = vec![schema::Service { kind: Some("LoadBalancer".into()), ports, ..Default::default() }];
+        actor.spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+        assert_eq!(new(&actor).unwrap().spec.unwrap().type_, Some("LoadBalancer".into()));
+    }
+
+    #[test]
+    fn test_target_port() {
+        use amp_common::schema::{self, Deploy, Port};
+
+        let mut actor = actor(false);
+        let ports = vec![Port { port: 80, ..Default::default() }];
+        let services = vec![schema::Service { ports, ..Default::default() }];
+        actor.spec.character.deploy = Some(Deploy { services: Some(services), ..Default::default() });
+        assert_eq!(new(&actor).unwrap().spec.unwrap().ports.unwrap()[0].target_port, Some(IntOrString::Int(80)));
+
+        let ports = BTreeMap::from([(80, PortMapping { target_port: Some(8080), node_port: None })]);
+        actor.set_options(&ActorOptions { ports, ..Default::default() });
+        let ports = new(&actor).unwrap().spec.unwrap().ports.unwrap();
+        assert_eq!(ports[0].target_port, Some(IntOrString::Int(8080)));
+        assert_eq!(ports[0].node_port, None);
+    }
+
+    fn service(type_: Option<&str>, ports: Vec<ServicePort>) -> Service {
+        let selector = BTreeMap::from([("amphitheatre.app/character".to_string(), "test".to_string())]);
+        Service {
+            spec: Some(ServiceSpec {
+                type_: type_.map(Into::into),
+                selector: Some(selector),
+                ports: Some(ports),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn port(port: i32) -> ServicePort {
+        ServicePort { name: Some(format!("tcp-{}", port)), port, ..Default::default() }
+    }
+
+    /// The port as returned by the cluster, with its defaults filled in.
+    fn live_port(port: i32, node_port: Option<i32>) -> ServicePort {
+        ServicePort {
+            protocol: Some("TCP".into()),
+            target_port: Some(IntOrString::Int(port)),
+            node_port,
+            ..self::port(port)
+        }
+    }
+
+    #[test]
+    fn test_patch_ignores_assigned_fields() {
+        let desired = service(None, vec![port(8080)]);
+        let mut actual = service(Some("ClusterIP"), vec![live_port(8080, None)]);
+        let spec = actual.spec.as_mut().unwrap();
+        spec.cluster_ip = Some("10.0.0.1".into());
+        spec.cluster_ips = Some(vec!["10.0.0.1".into()]);
+        spec.session_affinity = Some("None".into());
+
+        assert_eq!(patch(&desired, &actual), None);
+
+        let desired = service(Some("NodePort"), vec![port(8080)]);
+        let actual = service(Some("NodePort"), vec![live_port(8080, Some(30080))]);
+        assert_eq!(patch(&desired, &actual), None);
+    }
+
+    #[test]
+    fn test_patch_managed_fields() {
+        let desired = service(Some("NodePort"), vec![port(8080), port(9090)]);
+        let actual = service(Some("ClusterIP"), vec![live_port(8080, None)]);
+
+        let patch = patch(&desired, &actual).unwrap();
+        assert_eq!(patch["spec"]["type"], "NodePort");
+        assert_eq!(patch["spec"]["ports"].as_array().unwrap().len(), 2);
+        assert_eq!(patch["spec"]["ports"][1]["port"], 9090);
+        assert!(patch["spec"].get("selector").is_none());
+    }
+
+    #[test]
+    fn test_patch_session_affinity() {
+        let mut desired = service(None, vec![port(8080)]);
+        desired.spec.as_mut().unwrap().session_affinity = Some("ClientIP".into());
+        let mut actual = service(Some("ClusterIP"), vec![live_port(8080, None)]);
+
+        let patch = |desired: &Service, actual: &Service| patch(desired, actual).unwrap();
+        assert_eq!(patch(&desired, &actual), json!({ "spec": { "sessionAffinity": "ClientIP" } }));
+
+        actual.spec.as_mut().unwrap().session_affinity = Some("ClientIP".into());
+        desired.spec.as_mut().unwrap().session_affinity = None;
+        assert_eq!(patch(&desired, &actual), json!({ "spec": { "sessionAffinity": "None" } }));
+    }
+
+    #[test]
+    fn test_patch_keeps_specified_fields() {
+        let mut desired = service(None, vec![port(8080)]);
+        desired.spec.as_mut().unwrap().cluster_ip = Some("None".into());
+        let actual = service(Some("ClusterIP"), vec![live_port(8080, None)]);
+
+        let patch = patch(&desired, &actual).unwrap();
+        assert_eq!(patch, json!({ "spec": { "clusterIP": "None" } }));
+    }
+}
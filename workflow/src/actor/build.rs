@@ -22,8 +22,11 @@ use amp_builder::{BuildDirector, KanikoBuilder, KpackBuilder};
 use amp_common::resource::{Actor, ActorState};
 use amp_common::schema::BuildMethod;
 
-use amp_resources::actor;
+use amp_resources::error::Error as ResourceError;
+use amp_resources::ext::{ActorExt, ActorPhase, ActorSpecExt, ActorStateExt, BuildSpecExt};
+use amp_resources::{actor, capability, job};
 use async_trait::async_trait;
+use k8s_openapi::chrono::Utc;
 use kube::runtime::controller::Action;
 use kube::ResourceExt;
 use tracing::{error, info, trace};
@@ -68,15 +71,73 @@ impl Task<Actor> for BuildTask {
         let actor = &ctx.object;
         let build = actor.spec.character.build.clone().unwrap_or_default();
 
+        // Fail the build if it is configured for contradictory build methods
+        let method = match build.strategy() {
+            Ok(method) => method,
+            Err(err) => {
+                let condition = ActorState::failed(true, "InvalidBuild", Some(err.to_string()));
+                actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+                return Ok(None);
+            }
+        };
+
+        // Fail the build if its secrets can not be mounted, e.g. by a repeated id
+        if let Err(err) = actor.build_secrets() {
+            let condition = ActorState::failed(true, "InvalidBuild", Some(err.to_string()));
+            actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+            return Ok(None);
+        }
+
+        // Fail the build if the cluster misses the build subsystem it needs
+        match capability::ensure(&ctx.k8s, &actor.spec.required_build_capabilities()).await {
+            Err(ResourceError::MissingCapability(name)) => {
+                let message = format!("{} is not installed in the cluster", name);
+                let condition = ActorState::failed(true, "MissingCapability", Some(message));
+                actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+                return Ok(None);
+            }
+            result => result.map_err(Error::ResourceError)?,
+        }
+
+        // Queue the build while its namespace is at capacity
+        if !ctx.throttle.acquire_for(actor) {
+            info!("Too many builds in the namespace, the build of actor {} is queued", actor.name_any());
+            if !queued(actor) {
+                let message = "Waiting for the other builds in the namespace to finish".to_string();
+                let condition = ActorState::condition(ActorPhase::Building, "BuildQueued", Some(message));
+                actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+            }
+            return Ok(Some(Intent::Action(Action::requeue(Duration::from_secs(10)))));
+        }
+
+        // Give the slot back on every error, as the build is retried from scratch
+        let result = self.build(ctx, method).await;
+        if result.is_err() {
+            ctx.throttle.release_for(actor);
+        }
+
+        result
+    }
+}
+
+impl BuildTask {
+    /// Run the build with the builder of the method, holding the slot of the
+    /// actor in the build throttle until it completed or failed.
+    async fn build(&self, ctx: &Context<Actor>, method: BuildMethod) -> Result<Option<Intent<Actor>>> {
+        let actor = &ctx.object;
+
         // Generate `Builder` based on the build method
-        let builder = match build.method() {
+        let builder = match method {
             BuildMethod::Dockerfile => {
                 info!("Found dockerfile, build it with Kaniko");
                 BuildDirector::new(Box::new(KanikoBuilder::new(ctx.k8s.clone(), actor.clone())))
             }
             BuildMethod::Buildpacks => {
                 info!("Build the image with Cloud Native Buildpacks (kpack)");
-                BuildDirector::new(Box::new(KpackBuilder::new(ctx.k8s.clone(), actor.clone(), ctx.credentials.clone())))
+                let credentials = ctx.credentials.clone();
+                let builder =
+                    KpackBuilder::new(ctx.k8s.clone(), actor.clone(), credentials, ctx.default_builder.clone());
+                BuildDirector::new(Box::new(builder))
             }
         };
 
@@ -88,16 +149,60 @@ impl Task<Actor> for BuildTask {
         // Build the image
         builder.build().await.map_err(Error::BuildError)?;
 
+        // Fail the build if the pre-build command of the build Job failed
+        if let Some(message) = job::pre_build_failure(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
+            ctx.throttle.release_for(actor);
+            let condition = ActorState::failed(true, "PreBuildFailed", Some(message));
+            actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+            return Ok(None);
+        }
+
+        // Fail the build if its Job failed, as it is never retried
+        if let Some(message) = job::failure(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
+            ctx.throttle.release_for(actor);
+            let condition = ActorState::failed(true, "BuildFailed", Some(message));
+            actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+            return Ok(None);
+        }
+
         // Check if the build is completed and wait for it to finish.
         if !builder.completed().await.map_err(Error::BuildError)? {
+            // Fail the build if it ran longer than its timeout, e.g. hung
+            if let Some(timeout) = actor.build_timeout_seconds().filter(|_| actor.build_timed_out(Utc::now())) {
+                ctx.throttle.release_for(actor);
+                let message = format!("The build did not complete within {}s", timeout);
+                let condition = ActorState::failed(true, "BuildTimeout", Some(message));
+                actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+                return Ok(None);
+            }
+
             info!("Build job is not completed yet, wait for it to finish");
             return Ok(Some(Intent::Action(Action::requeue(Duration::from_secs(5)))));
         }
 
-        // Patch the status to running
+        // Record the end of the build and the digest of the image to deploy it
+        // pinned, then patch the status to running
+        ctx.throttle.release_for(actor);
+        actor::mark_build_finished(&ctx.k8s, &ctx.object).await.map_err(Error::ResourceError)?;
+        if let Some(digest) = builder.digest().await.map_err(Error::BuildError)? {
+            actor::record_image_digest(&ctx.k8s, &ctx.object, &digest).await.map_err(Error::ResourceError)?;
+        }
+        if let Some(started_at) = actor.build_started_at() {
+            info!("Build of actor {} completed in {}s", actor.name_any(), (Utc::now() - started_at).num_seconds());
+        }
+
         let condition = ActorState::running(true, "AutoRun", None);
         actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
 
         Ok(None)
     }
 }
+
+/// Check if the actor was already reported as queued, to avoid patching its
+/// status again on every retry.
+fn queued(actor: &Actor) -> bool {
+    let building = ActorState::building().type_;
+    actor.status.as_ref().is_some_and(|status| {
+        status.conditions.iter().any(|c| c.type_ == building && c.status == "True" && c.reason == "BuildQueued")
+    })
+}
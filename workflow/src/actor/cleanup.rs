@@ -59,6 +59,7 @@ impl Task<Actor> for CleanupTask {
 
     // Execute the task logic for CleanupTask using shared data
     async fn execute(&self, ctx: &Context<Actor>) -> Result<Option<Intent<Actor>>> {
+        ctx.throttle.release_for(&ctx.object);
         self.cleanup(ctx, &ctx.object).await?;
         Ok(None)
     }
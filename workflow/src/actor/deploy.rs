@@ -17,17 +17,17 @@ use crate::errors::Result;
 use crate::Intent;
 use crate::{Context, State, Task};
 
-use amp_common::resource::Actor;
+use amp_common::resource::{Actor, ActorState};
+use amp_resources::actor;
 use amp_resources::containers::application;
-use amp_resources::deployment;
 use amp_resources::error::Error as ResourceError;
-use amp_resources::hash;
+use amp_resources::ext::{ActorExt, ActorStateExt, ActorStatusExt, DeploymentStrategy};
+use amp_resources::{blue_green, canary, config_map, cronjob, deployment, event};
 
 use async_trait::async_trait;
-use k8s_openapi::api::core::v1::PodSpec;
 use kube::ResourceExt;
 use tracing::trace;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use super::ExposingState;
 
@@ -63,7 +63,10 @@ impl Task<Actor> for DeployTask {
     }
 
     fn matches(&self, ctx: &Context<Actor>) -> bool {
-        ctx.object.status.as_ref().is_some_and(|status| status.running())
+        ctx.object
+            .status
+            .as_ref()
+            .is_some_and(|status| (status.running() || status.scaling()) && status.build_succeeded())
     }
 
     /// Execute the task logic for DeployTask using shared data
@@ -80,25 +83,113 @@ impl DeployTask {
         let name = actor.name_any();
         let namespace = actor.namespace().ok_or_else(|| ResourceError::MissingObjectKey(".metadata.namespace"))?;
 
-        let resource = deployment::new(actor, self.pod(actor))?;
-        match deployment::exists(&ctx.k8s, &namespace, &name).await? {
-            true => {
+        // Materialize the resolved environment for troubleshooting if enabled
+        if let Some(config_map) = config_map::apply(&ctx.k8s, actor).await? {
+            actor::record_env_configmap(&ctx.k8s, actor, &config_map.name_any()).await?;
+        }
+
+        let pod = application::pod(actor);
+
+        // Scheduled actors run as a CronJob instead of a Deployment
+        if let Some(schedule) = actor.cron_schedule() {
+            let resource = cronjob::new(actor, schedule, pod)?;
+            match cronjob::exists(&ctx.k8s, &namespace, &name).await? {
+                true => cronjob::update(&ctx.k8s, &namespace, &name, resource).await?,
+                false => cronjob::create(&ctx.k8s, &namespace, resource).await?,
+            };
+            return Ok(());
+        }
+
+        let env_from: Vec<_> = pod.containers.iter().flat_map(|c| c.env_from.iter().flatten().cloned()).collect();
+
+        let mut resource = deployment::new(actor, pod)?;
+        let mut expected_hash = deployment::expected_hash(actor)?;
+
+        // Roll out the pods when the referenced ConfigMaps or Secrets change
+        if !env_from.is_empty() {
+            let data = deployment::config_data(&ctx.k8s, &namespace, &env_from).await?;
+            let checksum = deployment::config_checksum(&env_from, &data);
+            expected_hash = deployment::set_config_checksum(&mut resource, actor, checksum)?;
+        }
+
+        // Deploy the new version next to the active one when deployed blue-green
+        let mut color = None;
+        let mut canary_rollout = false;
+        if actor.deployment_strategy() == DeploymentStrategy::BlueGreen {
+            let target = blue_green::target_color(&ctx.k8s, actor, &expected_hash).await?;
+            blue_green::paint(&mut resource, target);
+            color = Some(target);
+        } else if let Some(config) = actor.canary().filter(|config| config.weight < 100) {
+            // Run the new version as a canary next to the stable one, sized to its weight
+            let stable = deployment::get_opt(&ctx.k8s, &namespace, &name).await?;
+            if stable.is_some_and(|stable| !deployment::up_to_date(&stable, &expected_hash)) {
+                let (canary_replicas, stable_replicas) = config.replica_split(actor.replicas());
+                expected_hash = canary::paint(&mut resource, canary_replicas, &expected_hash)?;
+                deployment::scale(&ctx.k8s, &namespace, &name, stable_replicas).await?;
+                canary_rollout = true;
+            }
+        }
+        let name = resource.name_any();
+        let desired_replicas = resource.spec.as_ref().and_then(|spec| spec.replicas).unwrap_or(1);
+
+        let previous = deployment::get_opt(&ctx.k8s, &namespace, &name).await?;
+        let previous_replicas = previous.as_ref().and_then(|deployment| deployment.spec.as_ref()?.replicas);
+        let deployment = match previous {
+            Some(_) => {
                 // Deployment already exists, update it if there are new changes
                 info!("Try to refresh an existing Deployment {name}");
-                let expected_hash = hash(&actor.spec)?;
-                deployment::update(&ctx.k8s, &namespace, &name, resource, expected_hash).await?;
+                deployment::update(&ctx.k8s, &namespace, &name, resource, expected_hash).await?
             }
-            false => {
+            None => {
                 // Create a new Deployment
-                deployment::create(&ctx.k8s, &namespace, resource).await?;
+                let deployment = deployment::create(&ctx.k8s, &namespace, resource).await?;
                 info!("Created new Deployment: {name}");
+                deployment
             }
+        };
+
+        // Fail the actor if the rollout did not become ready in time
+        if deployment::progress_deadline_exceeded(&deployment) {
+            warn!("The Deployment {name} exceeded its progress deadline");
+            let message = format!("Deployment {name} did not become ready within the deploy timeout");
+            let condition = ActorState::failed(true, "ProgressDeadlineExceeded", Some(message));
+            actor::patch_status(&ctx.k8s, actor, condition).await?;
+            return Ok(());
         }
 
-        Ok(())
-    }
+        // Scale until the desired replicas are ready, the Deployment is watched
+        if let Some(from) = previous_replicas.filter(|replicas| *replicas != desired_replicas) {
+            let message = format!("Scaling from {} to {} replicas", from, desired_replicas);
+            let condition = ActorState::scaling("ReplicasChanged", Some(message));
+            actor::patch_status(&ctx.k8s, actor, condition).await?;
+            return Ok(());
+        }
+        if actor.status.as_ref().is_some_and(|status| status.scaling()) {
+            if !blue_green::ready(&deployment) {
+                info!("Wait for the Deployment {name} to scale to {desired_replicas} replicas");
+                return Ok(());
+            }
+            let condition = ActorState::running(true, "Scaled", None);
+            actor::patch_status(&ctx.k8s, actor, condition).await?;
+        }
+
+        // Switch the traffic once the new version is ready, the Deployment is watched
+        if let Some(color) = color.filter(|color| actor.active_color() != Some(*color)) {
+            if !blue_green::ready(&deployment) {
+                info!("Wait for the Deployment {name} to be ready before switching the traffic");
+                return Ok(());
+            }
+            blue_green::switch(&ctx.k8s, actor, color).await?;
+        }
+
+        // Retire the canary once its version is promoted to the Deployment of the actor
+        if color.is_none() && !canary_rollout {
+            canary::finish(&ctx.k8s, actor).await?;
+        }
+
+        // Only tell about the reconciliations that changed the desired state
+        event::reconciled(&ctx.k8s, actor).await?;
 
-    fn pod(&self, actor: &Actor) -> PodSpec {
-        PodSpec { containers: vec![application::container(&actor.spec)], ..Default::default() }
+        Ok(())
     }
 }
@@ -17,7 +17,8 @@ use crate::{Context, Intent, State, Task};
 
 use amp_common::resource::Actor;
 
-use amp_resources::service;
+use amp_resources::ext::ActorStatusExt;
+use amp_resources::{network_policy, service};
 use async_trait::async_trait;
 use kube::ResourceExt;
 use tracing::{error, info, trace};
@@ -53,7 +54,8 @@ impl Task<Actor> for ExposeTask {
     }
 
     fn matches(&self, ctx: &Context<Actor>) -> bool {
-        ctx.object.status.as_ref().is_some_and(|status| status.running()) && ctx.object.spec.has_services()
+        ctx.object.status.as_ref().is_some_and(|status| status.running() || status.scaling())
+            && ctx.object.spec.has_services()
     }
 
     /// Execute the task logic for ExposeTask using shared data
@@ -77,6 +79,12 @@ impl ExposeTask {
             }
         }
 
+        // Publish the addresses of the pods before readiness if enabled
+        service::apply_headless(&ctx.k8s, actor).await?;
+
+        // Restrict the ingress traffic to the exposed ports if enabled
+        network_policy::apply(&ctx.k8s, actor).await?;
+
         Ok(())
     }
 }
@@ -21,11 +21,13 @@ use crate::{Context, Intent, State, Task};
 use amp_common::docker::{self, registry, DockerConfig};
 use amp_common::resource::{Actor, ActorState};
 
+use amp_resolver::validation;
 use amp_resources::actor;
+use amp_resources::ext::{ActorExt, ActorSpecExt, ActorStatusExt};
 use async_trait::async_trait;
 use kube::runtime::controller::Action;
 use kube::ResourceExt;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
 pub struct InitialState;
 
@@ -50,8 +52,8 @@ impl State<Actor> for InitialState {
             return Some(Intent::State(Box::new(BuildingState)));
         }
 
-        // Transition to the deploying state if status of actor is running
-        if ctx.object.status.as_ref().is_some_and(|status| status.running()) {
+        // Transition to the deploying state if status of actor is running or scaling
+        if ctx.object.status.as_ref().is_some_and(|status| status.running() || status.scaling()) {
             return Some(Intent::State(Box::new(DeployingState)));
         }
 
@@ -75,8 +77,23 @@ impl Task<Actor> for InitTask {
     async fn execute(&self, ctx: &Context<Actor>) -> Result<Option<Intent<Actor>>> {
         let actor = &ctx.object;
 
-        // build if actor is live or the image is not built, else skip to next state
-        if actor.spec.live || !self.built(ctx).await? {
+        // Validate the actor before doing anything, reject it on errors
+        let report = validation::validate_full(actor);
+        for warning in &report.warnings {
+            warn!("Actor {}: {}", actor.name_any(), warning);
+        }
+        if !report.is_valid() {
+            let condition = ActorState::failed(true, "ValidationFailed", Some(report.errors.join("; ")));
+            actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
+            return Ok(None);
+        }
+
+        // build if actor is live, was never built, the build has changed since the
+        // last one, or the image is missing from the registry, else skip to next state
+        let commit = actor.spec.source.as_ref().map(|source| source.rev()).unwrap_or_default();
+        let changed = actor.should_build(&actor.spec.build_fingerprint(), &commit);
+        if actor.spec.live || changed || !self.built(ctx).await? {
+            actor::mark_build_started(&ctx.k8s, &ctx.object).await.map_err(Error::ResourceError)?;
             let condition = ActorState::building();
             actor::patch_status(&ctx.k8s, &ctx.object, condition).await.map_err(Error::ResourceError)?;
         } else {
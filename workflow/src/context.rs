@@ -18,10 +18,14 @@ use async_nats::jetstream;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::BuildThrottle;
+
 /// Represents the context shared among different states and tasks.
 pub struct Context<T> {
     pub object: Arc<T>,
     pub k8s: Arc<kube::Client>,
     pub credentials: Arc<RwLock<Credentials>>,
     pub jetstream: Arc<jetstream::Context>,
+    pub throttle: Arc<BuildThrottle>,
+    pub default_builder: String,
 }
@@ -30,3 +30,6 @@ pub use context::Context;
 
 mod intent;
 pub use intent::Intent;
+
+mod throttle;
+pub use throttle::{BuildThrottle, Permit};
@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 use crate::{Context, Intent, State, Task};
 use amp_common::resource::Playbook;
+use amp_resolver::playbook::PlaybookSpecExt;
+use amp_resources::actor;
 use async_trait::async_trait;
 use kube::ResourceExt;
 use tracing::{error, info, trace};
@@ -63,6 +65,11 @@ impl Task<Playbook> for CleanupTask {
 
 impl CleanupTask {
     async fn cleanup(&self, ctx: &Context<Playbook>, playbook: &Playbook) -> Result<()> {
+        // Delete the actors depending on others first, then their partners
+        for name in playbook.spec.rollback_order() {
+            actor::delete(&ctx.k8s, playbook, &name).await.map_err(Error::ResourceError)?;
+        }
+
         // Try to delete the NATS stream for this playbook if it exists.
         if ctx.jetstream.delete_stream(playbook.name_any()).await.is_ok() {
             info!("Deleted NATS stream for playbook {}", playbook.name_any());
@@ -67,6 +67,7 @@ impl Task<Playbook> for InitTask {
         // Create namespace for this playbook
         namespace::create(&ctx.k8s, &ctx.object).await.map_err(Error::ResourceError)?;
         info!("Created namespace for playbook {}", ctx.object.name_any());
+        namespace::create_partner_namespace(&ctx.k8s, &ctx.object).await.map_err(Error::ResourceError)?;
 
         // Add the preface to the playbook for first resolving
         self.add_preface(ctx, &ctx.object).await?;
@@ -15,8 +15,9 @@
 use crate::errors::{Error, Result};
 use crate::{Context, Intent, State, Task};
 
-use amp_common::resource::{Partner, Playbook, PlaybookState};
-use amp_resolver::partner::load;
+use amp_common::resource::{ActorSpec, Partner, Playbook, PlaybookState};
+use amp_resolver::partner::{detect_cycles, load};
+use amp_resolver::playbook::PlaybookSpecExt;
 
 use amp_resources::playbook;
 use async_trait::async_trait;
@@ -75,6 +76,22 @@ impl ResolveTask {
         let mut fetches: HashSet<(&str, Partner)> = HashSet::new();
 
         if let Some(characters) = &playbook.spec.characters {
+            // Fail the playbook on duplicate names, their actors would fight over the same resources
+            if let Err(errors) = playbook.spec.validate_unique_names() {
+                let condition = PlaybookState::failed(true, "DuplicateActorName", Some(errors.join("; ")));
+                playbook::patch_status(&ctx.k8s, playbook, condition).await.map_err(Error::ResourceError)?;
+                return Ok(());
+            }
+
+            // Fail the playbook on dependency cycles, which can not be resolved
+            let actors: Vec<ActorSpec> = characters.iter().map(ActorSpec::from).collect();
+            if let Err(cycles) = detect_cycles(&actors) {
+                let message = format!("dependency cycles among the partners: {}", cycles.join("; "));
+                let condition = PlaybookState::failed(true, "DependencyCycle", Some(message));
+                playbook::patch_status(&ctx.k8s, playbook, condition).await.map_err(Error::ResourceError)?;
+                return Ok(());
+            }
+
             let exists: HashSet<&String> = characters.iter().map(|char| &char.meta.name).collect();
             debug!("The currently existing actors are: {exists:?}");
 
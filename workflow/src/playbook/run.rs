@@ -15,6 +15,7 @@
 use crate::errors::{Error, Result};
 use crate::{Context, Intent, State, Task};
 use amp_common::resource::Playbook;
+use amp_resolver::playbook::PlaybookSpecExt;
 use amp_resolver::to_actor;
 use amp_resources::actor;
 use async_trait::async_trait;
@@ -70,7 +71,12 @@ impl RunTask {
             return Ok(());
         }
 
-        let characters = playbook.spec.characters.as_ref().unwrap();
+        // Create the actors of the partners before the actors depending on them
+        let mut characters: Vec<_> = playbook.spec.characters.iter().flatten().collect();
+        if let Ok(order) = playbook.spec.deploy_order() {
+            characters.sort_by_key(|character| order.iter().position(|name| *name == character.meta.name));
+        }
+
         for character in characters {
             let name = &character.meta.name;
             match actor::exists(&ctx.k8s, playbook, name).await.map_err(Error::ResourceError)? {
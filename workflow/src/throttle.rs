@@ -0,0 +1,154 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use amp_common::resource::Actor;
+use kube::ResourceExt;
+
+/// Caps the number of concurrent builds in each namespace, to avoid
+/// overloading the cluster. A build takes a [`Permit`], which gives its
+/// slot back when dropped.
+#[derive(Default)]
+pub struct BuildThrottle {
+    max_per_namespace: usize,
+    running: Arc<Mutex<HashMap<String, usize>>>,
+    held: Mutex<HashMap<String, Permit>>,
+}
+
+impl BuildThrottle {
+    /// Create a throttle allowing up to `max_per_namespace` concurrent builds
+    /// in each namespace, where zero means no limit.
+    pub fn new(max_per_namespace: usize) -> Self {
+        BuildThrottle { max_per_namespace, ..Default::default() }
+    }
+
+    /// Take a slot in the namespace, or `None` if it is at capacity.
+    pub fn try_acquire(&self, namespace: &str) -> Option<Permit> {
+        let mut running = self.running.lock().unwrap();
+        let count = running.entry(namespace.to_string()).or_default();
+        if self.max_per_namespace > 0 && *count >= self.max_per_namespace {
+            return None;
+        }
+
+        *count += 1;
+        Some(Permit { namespace: namespace.to_string(), running: self.running.clone() })
+    }
+
+    /// Take a slot for the build of the actor, which is kept across the
+    /// reconciliations until released. Returns false if the actor has to
+    /// wait, as its namespace is at capacity.
+    pub fn acquire_for(&self, actor: &Actor) -> bool {
+        let mut held = self.held.lock().unwrap();
+        let key = key(actor);
+        if held.contains_key(&key) {
+            return true;
+        }
+
+        match self.try_acquire(&actor.namespace().unwrap_or_default()) {
+            Some(permit) => {
+                held.insert(key, permit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Give back the slot of the actor, once its build finished or failed.
+    pub fn release_for(&self, actor: &Actor) {
+        self.held.lock().unwrap().remove(&key(actor));
+    }
+}
+
+/// A slot for a build in a namespace, given back when dropped.
+pub struct Permit {
+    namespace: String,
+    running: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap();
+        if let Some(count) = running.get_mut(&self.namespace) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                running.remove(&self.namespace);
+            }
+        }
+    }
+}
+
+#[inline]
+fn key(actor: &Actor) -> String {
+    format!("{}/{}", actor.namespace().unwrap_or_default(), actor.name_any())
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::resource::ActorSpec;
+
+    use super::*;
+
+    fn actor(namespace: &str, name: &str) -> Actor {
+        let mut actor = Actor::new(name, ActorSpec { name: name.into(), ..Default::default() });
+        actor.metadata.namespace = Some(namespace.into());
+        actor
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let throttle = BuildThrottle::new(1);
+
+        let permit = throttle.try_acquire("default").unwrap();
+        assert!(throttle.try_acquire("default").is_none());
+
+        drop(permit);
+        assert!(throttle.try_acquire("default").is_some());
+    }
+
+    #[test]
+    fn test_at_capacity() {
+        let throttle = BuildThrottle::new(2);
+
+        let _first = throttle.try_acquire("default").unwrap();
+        let _second = throttle.try_acquire("default").unwrap();
+        assert!(throttle.try_acquire("default").is_none());
+
+        // Other namespaces have their own slots
+        assert!(throttle.try_acquire("other").is_some());
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let throttle = BuildThrottle::new(0);
+
+        let permits: Vec<_> = (0..10).filter_map(|_| throttle.try_acquire("default")).collect();
+        assert_eq!(permits.len(), 10);
+    }
+
+    #[test]
+    fn test_acquire_for_actor() {
+        let throttle = BuildThrottle::new(1);
+        let api = actor("default", "api");
+        let web = actor("default", "web");
+
+        assert!(throttle.acquire_for(&api));
+        assert!(throttle.acquire_for(&api), "the actor keeps its slot across reconciliations");
+        assert!(!throttle.acquire_for(&web));
+
+        throttle.release_for(&api);
+        assert!(throttle.acquire_for(&web));
+    }
+}